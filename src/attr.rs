@@ -4,7 +4,7 @@
 
 use std::rc::Rc;
 
-use rspirv::spirv::{BuiltIn, ExecutionMode, ExecutionModel, StorageClass};
+use rspirv::spirv::{BuiltIn, ExecutionMode, ExecutionModel, Scope, StorageClass};
 use rustc_ast::Attribute;
 use rustc_hir as hir;
 use rustc_hir::{
@@ -69,6 +69,14 @@ pub enum IntrinsicType {
     Matrix,
 }
 
+/// Read-modify-write operations of `#[spirv(atomic_float_*_intrinsic)]` functions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AtomicFloatOp {
+    Add,
+    Min,
+    Max,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct SpecConstant {
     pub id: u32,
@@ -85,6 +93,8 @@ pub enum SpirvAttribute {
 
     // `fn` attributes:
     Entry(Entry),
+    CpuOnly,
+    AtomicScope(Scope),
 
     // (entry) `fn` parameter attributes:
     StorageClass(StorageClass),
@@ -99,6 +109,7 @@ pub enum SpirvAttribute {
     // `fn`/closure attributes:
     BufferLoadIntrinsic,
     BufferStoreIntrinsic,
+    AtomicFloatIntrinsic(AtomicFloatOp),
 }
 
 // HACK(eddyb) this is similar to `rustc_span::Spanned` but with `value` as the
@@ -120,6 +131,8 @@ pub struct AggregatedSpirvAttributes {
 
     // `fn` attributes:
     pub entry: Option<Spanned<Entry>>,
+    pub cpu_only: Option<Spanned<()>>,
+    pub atomic_scope: Option<Spanned<Scope>>,
 
     // (entry) `fn` parameter attributes:
     pub storage_class: Option<Spanned<StorageClass>>,
@@ -134,6 +147,7 @@ pub struct AggregatedSpirvAttributes {
     // `fn`/closure attributes:
     pub buffer_load_intrinsic: Option<Spanned<()>>,
     pub buffer_store_intrinsic: Option<Spanned<()>>,
+    pub atomic_float_intrinsic: Option<Spanned<AtomicFloatOp>>,
 }
 
 struct MultipleAttrs {
@@ -203,6 +217,13 @@ impl AggregatedSpirvAttributes {
             }
             Block => try_insert(&mut self.block, (), span, "#[spirv(block)]"),
             Entry(value) => try_insert(&mut self.entry, value, span, "entry-point"),
+            CpuOnly => try_insert(&mut self.cpu_only, (), span, "#[spirv(cpu_only)]"),
+            AtomicScope(value) => try_insert(
+                &mut self.atomic_scope,
+                value,
+                span,
+                "#[spirv(atomic_scope)]",
+            ),
             StorageClass(value) => {
                 try_insert(&mut self.storage_class, value, span, "storage class")
             }
@@ -240,6 +261,12 @@ impl AggregatedSpirvAttributes {
                 span,
                 "#[spirv(buffer_store_intrinsic)]",
             ),
+            AtomicFloatIntrinsic(value) => try_insert(
+                &mut self.atomic_float_intrinsic,
+                value,
+                span,
+                "#[spirv(atomic_float_*_intrinsic)]",
+            ),
         }
     }
 }
@@ -311,6 +338,15 @@ impl CheckSpirvAttrVisitor<'_> {
                     _ => Err(Expected("function")),
                 },
 
+                SpirvAttribute::CpuOnly | SpirvAttribute::AtomicScope(_) => match target {
+                    Target::Fn
+                    | Target::Method(MethodKind::Trait { body: true } | MethodKind::Inherent) => {
+                        Ok(())
+                    }
+
+                    _ => Err(Expected("function")),
+                },
+
                 SpirvAttribute::StorageClass(_)
                 | SpirvAttribute::Builtin(_)
                 | SpirvAttribute::DescriptorSet(_)
@@ -361,12 +397,12 @@ impl CheckSpirvAttrVisitor<'_> {
 
                     _ => Err(Expected("function parameter")),
                 },
-                SpirvAttribute::BufferLoadIntrinsic | SpirvAttribute::BufferStoreIntrinsic => {
-                    match target {
-                        Target::Fn => Ok(()),
-                        _ => Err(Expected("function")),
-                    }
-                }
+                SpirvAttribute::BufferLoadIntrinsic
+                | SpirvAttribute::BufferStoreIntrinsic
+                | SpirvAttribute::AtomicFloatIntrinsic(_) => match target {
+                    Target::Fn => Ok(()),
+                    _ => Err(Expected("function")),
+                },
             };
             match valid_target {
                 Err(Expected(expected_target)) => {
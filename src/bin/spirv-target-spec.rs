@@ -0,0 +1,60 @@
+//! Prints (or writes) the JSON target spec for a `spirv-*` target triple, for
+//! use with `rustc --target path/to/<triple>.json`.
+//!
+//! ```text
+//! spirv-target-spec <TRIPLE>                 # print to stdout
+//! spirv-target-spec <TRIPLE> -o <FILE>       # write to FILE
+//! spirv-target-spec --all <DIR>              # write DIR/<TRIPLE>.json for all targets
+//! spirv-target-spec --list                   # list all valid targets
+//! ```
+
+use std::{path::Path, process::ExitCode};
+
+use rustc_codegen_spir::{target_spec_json, ALL_VALID_TARGETS};
+
+const USAGE: &str = "usage:
+    spirv-target-spec <TRIPLE> [-o <FILE>]
+    spirv-target-spec --all <DIR>
+    spirv-target-spec --list";
+
+fn write(path: &Path, triple: &str) -> Result<(), String> {
+    let json = target_spec_json(triple).map_err(|e| e.to_string())?;
+    std::fs::write(path, json).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [list] if list == "--list" => {
+            for triple in ALL_VALID_TARGETS {
+                println!("{triple}");
+            }
+            Ok(())
+        }
+        [all, dir] if all == "--all" => {
+            let dir = Path::new(dir);
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+            for triple in ALL_VALID_TARGETS {
+                write(&dir.join(format!("{triple}.json")), triple)?;
+            }
+            Ok(())
+        }
+        [triple] => {
+            println!("{}", target_spec_json(triple).map_err(|e| e.to_string())?);
+            Ok(())
+        }
+        [triple, o, file] if o == "-o" => write(Path::new(file), triple),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -7,7 +7,10 @@ use std::{
 use itertools::Itertools;
 use rspirv::{
     dr::{InsertPoint, Instruction, Operand},
-    spirv::{Capability, MemoryModel, MemorySemantics, Op, Scope, StorageClass, Word},
+    spirv::{
+        AddressingModel, Capability, Decoration, FPFastMathMode, MemoryAccess, MemoryModel,
+        MemorySemantics, Op, Scope, StorageClass, Word,
+    },
 };
 use rustc_apfloat::{ieee, Float, Round, Status};
 use rustc_codegen_ssa::{
@@ -35,6 +38,7 @@ use super::Builder;
 use crate::{
     abi::ConvSpirvType,
     assert_ty_eq,
+    attr::AtomicFloatOp,
     builder_spirv::{BuilderCursor, SpirvConst, SpirvValue, SpirvValueExt, SpirvValueKind},
     custom_insts::{CustomInst, CustomOp},
     rustc_codegen_ssa::traits::BaseTypeMethods,
@@ -161,6 +165,351 @@ fn memset_dynamic_scalar(
 }
 
 impl<'a, 'tcx> Builder<'a, 'tcx> {
+    /// Decorates the result of an "algebraic" float operation (e.g. `fadd_algebraic`)
+    /// with the fast-math flags it allows, when the target can express them.
+    ///
+    /// `FPFastMathMode` requires `Kernel`, and Vulkan has no equivalent, but
+    /// shaders are already allowed e.g. contraction by default (i.e. unless
+    /// decorated with `NoContraction`), so the undecorated op is valid there.
+    //
+    // NOTE only `NSZ` and `AllowRecip` match LLVM's algebraic flags, as
+    // `Fast` also implies `NotNaN` and `NotInf` (which algebraic ops don't),
+    // while reassociation/contraction need `SPV_KHR_float_controls2`.
+    fn decorate_algebraic(&self, value: SpirvValue) -> SpirvValue {
+        if self.builder.has_capability(Capability::Kernel) {
+            self.emit().decorate(
+                value.def(self),
+                Decoration::FPFastMathMode,
+                [Operand::FPFastMathMode(
+                    FPFastMathMode::NSZ | FPFastMathMode::ALLOW_RECIP,
+                )],
+            );
+        }
+        value
+    }
+
+    /// Emits one of `OpIAddCarry`, `OpISubBorrow`, `OpUMulExtended` or `OpSMulExtended`
+    /// (which all return a pair of integers), returning both halves of the result,
+    /// i.e. the low (wrapping) result, and the carry/borrow/high bits.
+    fn extended_int_op(
+        &mut self,
+        op: Op,
+        lhs: SpirvValue,
+        rhs: SpirvValue,
+    ) -> (SpirvValue, SpirvValue) {
+        assert_ty_eq!(self, lhs.ty, rhs.ty);
+        let int_ty = lhs.ty;
+        let int_size = self.lookup_type(int_ty).sizeof(self).unwrap();
+        let pair_ty = SpirvType::Adt {
+            def_id: None,
+            size: Some(int_size * 2),
+            align: self.lookup_type(int_ty).alignof(self),
+            field_types: &[int_ty, int_ty],
+            field_offsets: &[Size::ZERO, int_size],
+            field_names: None,
+        }
+        .def(self.span(), self);
+
+        let (lhs, rhs) = (lhs.def(self), rhs.def(self));
+        let mut emit = self.emit();
+        let pair = match op {
+            Op::IAddCarry => emit.i_add_carry(pair_ty, None, lhs, rhs),
+            Op::ISubBorrow => emit.i_sub_borrow(pair_ty, None, lhs, rhs),
+            Op::UMulExtended => emit.u_mul_extended(pair_ty, None, lhs, rhs),
+            Op::SMulExtended => emit.s_mul_extended(pair_ty, None, lhs, rhs),
+            _ => bug!("extended_int_op: unsupported Op{op:?}"),
+        }
+        .unwrap();
+        let low = emit.composite_extract(int_ty, None, pair, [0]).unwrap();
+        let high = emit.composite_extract(int_ty, None, pair, [1]).unwrap();
+        (low.with_type(int_ty), high.with_type(int_ty))
+    }
+
+    /// Maps `flags` (and `align`, where needed) to a `MemoryAccess` mask (if any
+    /// bits are needed) and its extra operands, in the order SPIR-V expects
+    /// them. Under the Vulkan memory model, volatile accesses also make the
+    /// pointer available (for writes, i.e. `make_available`) and/or visible
+    /// (for reads).
+    fn memory_access(
+        &self,
+        flags: MemFlags,
+        align: Align,
+        make_available: bool,
+        make_visible: bool,
+    ) -> (Option<MemoryAccess>, SmallVec<[Operand; 3]>) {
+        let mut access = MemoryAccess::NONE;
+        let mut operands = SmallVec::new();
+        if flags.contains(MemFlags::VOLATILE) {
+            access |= MemoryAccess::VOLATILE;
+        }
+        // NOTE accesses through `PhysicalStorageBuffer` pointers require
+        // `Aligned`, but storage classes are only known after linking, so
+        // every access gets it when such pointers can exist at all.
+        let aligned = if flags.contains(MemFlags::UNALIGNED) {
+            Some(1)
+        } else if self.target.addressing_model() == AddressingModel::PhysicalStorageBuffer64 {
+            Some(align.bytes() as u32)
+        } else {
+            None
+        };
+        if let Some(aligned) = aligned {
+            access |= MemoryAccess::ALIGNED;
+            operands.push(Operand::LiteralInt32(aligned));
+        }
+        if flags.contains(MemFlags::NONTEMPORAL) {
+            access |= MemoryAccess::NONTEMPORAL;
+        }
+        if flags.contains(MemFlags::VOLATILE) && (make_available || make_visible) {
+            if self.target.memory_model() == MemoryModel::Vulkan {
+                // NOTE `Device` scope would require `VulkanMemoryModelDeviceScope`,
+                // while `QueueFamily` is the widest scope available without it.
+                let scope = self.constant_u32(self.span(), Scope::QueueFamily as u32);
+                access |= MemoryAccess::NON_PRIVATE_POINTER;
+                if make_available {
+                    access |= MemoryAccess::MAKE_POINTER_AVAILABLE;
+                    operands.push(Operand::IdScope(scope.def(self)));
+                }
+                if make_visible {
+                    access |= MemoryAccess::MAKE_POINTER_VISIBLE;
+                    operands.push(Operand::IdScope(scope.def(self)));
+                }
+            }
+        }
+        ((!access.is_empty()).then_some(access), operands)
+    }
+
+    /// Memory operands for `OpCopyMemory{,Sized}` with `flags`, which (since
+    /// SPIR-V 1.4) can be given separately for the target and the source.
+    fn copy_memory_operands(
+        &self,
+        flags: MemFlags,
+        dst_align: Align,
+        src_align: Align,
+    ) -> Vec<Operand> {
+        let mut operands = vec![];
+        if self.target.spirv_version() >= (1, 4) {
+            let (dst_access, dst_operands) = self.memory_access(flags, dst_align, true, false);
+            let (src_access, src_operands) = self.memory_access(flags, src_align, false, true);
+            if dst_access.is_some() || src_access.is_some() {
+                operands.push(Operand::MemoryAccess(
+                    dst_access.unwrap_or(MemoryAccess::NONE),
+                ));
+                operands.extend(dst_operands);
+            }
+            if let Some(src_access) = src_access {
+                operands.push(Operand::MemoryAccess(src_access));
+                operands.extend(src_operands);
+            }
+        } else {
+            // NOTE a single set of memory operands can't have both
+            // `MakePointerAvailable` and `MakePointerVisible` (which only
+            // make sense for the target and source, respectively), so the
+            // copy is left without availability/visibility operations.
+            let align = dst_align.min(src_align);
+            let (access, access_operands) = self.memory_access(flags, align, false, false);
+            if let Some(access) = access {
+                operands.push(Operand::MemoryAccess(access));
+                operands.extend(access_operands);
+            }
+        }
+        operands
+    }
+
+    fn load_with_flags(
+        &mut self,
+        ty: Word,
+        ptr: SpirvValue,
+        align: Align,
+        flags: MemFlags,
+    ) -> SpirvValue {
+        let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, ty);
+        let folded = if flags.contains(MemFlags::VOLATILE) {
+            None
+        } else {
+            ptr.const_fold_load(self)
+        };
+        let loaded_val = folded.unwrap_or_else(|| {
+            let (access, access_operands) = self.memory_access(flags, align, false, true);
+            self.emit()
+                .load(access_ty, None, ptr.def(self), access, access_operands)
+                .unwrap()
+                .with_type(access_ty)
+        });
+        self.bitcast(loaded_val, ty)
+    }
+
+    /// The scope of atomic operations (and fences) in the current function, i.e.
+    /// the one from its `#[spirv(atomic_scope(...))]` attribute, or otherwise
+    /// `Device`, unless the linker replaces it with the scope of a caller (see
+    /// `DefaultAtomicScopeDecoration`).
+    fn atomic_scope_def(&self) -> SpirvValue {
+        let scope = self
+            .atomic_scope_fn_id
+            .borrow()
+            .get(&self.current_fn.def(self))
+            .copied();
+        if let Some(scope) = scope {
+            return self.constant_u32(self.span(), scope as u32);
+        }
+
+        // NOTE this can't use `constant_u32`, as that would reuse the same
+        // `OpConstant` as any other (non-atomic-scope) use of `1u32`.
+        let ty = SpirvType::Integer(32, false).def(self.span(), self);
+        let id = *self
+            .default_atomic_scope_id
+            .get_or_init(|| self.emit_global().constant_u32(ty, Scope::Device as u32));
+        id.with_type(ty)
+    }
+
+    /// Emits an atomic read-modify-write of `*ptr` (to `f(old_value)`), as a
+    /// compare-exchange loop, for operations without a SPIR-V instruction.
+    /// Returns the old value, just like the `OpAtomic*` instructions do.
+    fn atomic_rmw_cas_loop(
+        &mut self,
+        ptr: SpirvValue,
+        semantics: SpirvValue,
+        f: impl FnOnce(&mut Self, SpirvValue) -> SpirvValue,
+    ) -> SpirvValue {
+        let ty = match self.lookup_type(ptr.ty) {
+            SpirvType::Pointer { pointee } => pointee,
+            other => self.fatal(format!(
+                "atomic_rmw_cas_loop called on non-pointer type: {other:?}"
+            )),
+        };
+        self.validate_atomic(ty, ptr.def(self));
+        let memory = self.atomic_scope_def().def(self);
+        // Only the final (successful) compare-exchange has to synchronize.
+        let relaxed = self
+            .constant_u32(self.span(), MemorySemantics::NONE.bits())
+            .def(self);
+        let zero_align = Align::from_bytes(0).unwrap();
+
+        let loop_bb = self.append_sibling_block("atomic_rmw_loop");
+        let exit_bb = self.append_sibling_block("atomic_rmw_exit");
+
+        let old_var = self.alloca(ty, zero_align);
+        let initial = self
+            .emit()
+            .atomic_load(ty, None, ptr.def(self), memory, relaxed)
+            .unwrap()
+            .with_type(ty);
+        self.store(initial, old_var, zero_align);
+        self.br(loop_bb);
+
+        self.switch_to_block(loop_bb);
+        let expected = self.load(ty, old_var, zero_align);
+        let new = f(self, expected);
+        let old = self
+            .emit()
+            .atomic_compare_exchange(
+                ty,
+                None,
+                ptr.def(self),
+                memory,
+                semantics.def(self),
+                relaxed,
+                new.def(self),
+                expected.def(self),
+            )
+            .unwrap()
+            .with_type(ty);
+        self.store(old, old_var, zero_align);
+        let success = self.icmp(IntPredicate::IntEQ, old, expected);
+        self.cond_br(success, exit_bb, loop_bb);
+
+        self.switch_to_block(exit_bb);
+        old
+    }
+
+    /// Atomic read-modify-write operations on floats, called through functions
+    /// with `#[spirv(atomic_float_{add,min,max}_intrinsic)]`, of the signature
+    /// `unsafe fn(ptr: *mut F, value: F, semantics: u32) -> F` (`semantics`
+    /// being `MemorySemantics` bits, and the scope coming from the caller).
+    fn codegen_atomic_float_intrinsic(
+        &mut self,
+        op: AtomicFloatOp,
+        result_type: Word,
+        args: &[SpirvValue],
+    ) -> SpirvValue {
+        if args.len() != 3 {
+            self.fatal(format!(
+                "atomic_float_intrinsic should have 3 args, it has {}",
+                args.len()
+            ));
+        }
+        let (ptr, value, semantics) = (args[0], args[1], args[2]);
+        let width = match self.lookup_type(result_type) {
+            SpirvType::Float(width) => width,
+            other => self.fatal(format!(
+                "atomic_float_intrinsic called on non-float type: {other:?}"
+            )),
+        };
+        let (ptr, ty) = self.adjust_pointer_for_typed_access(ptr, result_type);
+        let value = self.bitcast(value, ty);
+
+        match op {
+            AtomicFloatOp::Add => {
+                let cap = match width {
+                    32 => Capability::AtomicFloat32AddEXT,
+                    64 => Capability::AtomicFloat64AddEXT,
+                    _ => self.fatal(format!("atomic float add on unsupported f{width}")),
+                };
+                let memory = self.atomic_scope_def().def(self);
+                let result = self
+                    .emit()
+                    .atomic_f_add_ext(
+                        ty,
+                        None,
+                        ptr.def(self),
+                        memory,
+                        semantics.def(self),
+                        value.def(self),
+                    )
+                    .unwrap()
+                    .with_type(ty);
+                if !self.builder.has_capability(cap) {
+                    self.zombie(
+                        result.def(self),
+                        &format!(
+                            "atomic float add requires the `{cap:?}` capability \
+                             (from `SPV_EXT_shader_atomic_float_add`)"
+                        ),
+                    );
+                }
+                self.bitcast(result, result_type)
+            }
+            AtomicFloatOp::Min | AtomicFloatOp::Max => {
+                // FIXME `OpAtomicF{Min,Max}EXT` (from
+                // `SPV_EXT_shader_atomic_float_min_max`) are missing from
+                // `rspirv`'s grammar, so this loops on the bits instead, which
+                // requires casting the pointer, i.e. this is always a zombie on
+                // `Shader` targets (even with that extension enabled).
+                let int_ty = SpirvType::Integer(width, false).def(self.span(), self);
+                let int_ptr = self.pointercast(ptr, self.type_ptr_to(int_ty));
+                let old_bits = self.atomic_rmw_cas_loop(int_ptr, semantics, |bx, old_bits| {
+                    let old = bx.bitcast(old_bits, ty);
+                    let predicate = match op {
+                        AtomicFloatOp::Min => RealPredicate::RealOLT,
+                        _ => RealPredicate::RealOGT,
+                    };
+                    let replace = bx.fcmp(predicate, value, old);
+                    let new = bx.select(replace, value, old);
+                    bx.bitcast(new, int_ty)
+                });
+                if !self.target.is_kernel() {
+                    self.zombie(
+                        old_bits.def(self),
+                        "atomic float min/max is only supported on `Kernel` targets \
+                         (`SPV_EXT_shader_atomic_float_min_max` isn't supported, \
+                         even when enabled)",
+                    );
+                }
+                let result = self.bitcast(old_bits, ty);
+                self.bitcast(result, result_type)
+            }
+        }
+    }
+
     fn ordering_to_semantics_def(&self, ordering: AtomicOrdering) -> SpirvValue {
         let mut invalid_seq_cst = false;
         let semantics = match ordering {
@@ -175,9 +524,7 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                     | MemorySemantics::ACQUIRE_RELEASE
             }
             AtomicOrdering::SequentiallyConsistent => {
-                let emit = self.emit();
-                let memory_model = emit.module_ref().memory_model.as_ref().unwrap();
-                if memory_model.operands[1].unwrap_memory_model() == MemoryModel::Vulkan {
+                if self.target.memory_model() == MemoryModel::Vulkan {
                     invalid_seq_cst = true;
                 }
                 MemorySemantics::MAKE_AVAILABLE
@@ -196,10 +543,36 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
         semantics
     }
 
+    /// Converts the integer `int_pattern` (of pointer width) into a pointer of
+    /// type `ty`, for memsets with non-zero patterns (e.g. of `MaybeUninit`).
+    fn memset_ptr_pattern(&self, ty: &SpirvType<'tcx>, int_pattern: Word) -> Word {
+        let result = self
+            .emit()
+            .convert_u_to_ptr(ty.def(self.span(), self), None, int_pattern)
+            .unwrap();
+        self.zombie_convert_u_to_ptr(result);
+        result
+    }
+
     fn memset_const_pattern(&self, ty: &SpirvType<'tcx>, fill_byte: u8) -> Word {
         match *ty {
+            // Zero fills (e.g. `mem::zeroed`) don't need building up a value,
+            // and zero-sized structs have no bytes to fill at all.
+            SpirvType::Adt { .. }
+            | SpirvType::Vector { .. }
+            | SpirvType::Matrix { .. }
+            | SpirvType::Array { .. }
+            | SpirvType::Pointer { .. }
+                if fill_byte == 0 =>
+            {
+                self.constant_null(ty.def(self.span(), self)).def(self)
+            }
+            SpirvType::Adt { field_types, .. } if field_types.is_empty() => {
+                self.constant_null(ty.def(self.span(), self)).def(self)
+            }
+
             SpirvType::Void => self.fatal("memset invalid on void pattern"),
-            SpirvType::Bool => self.fatal("memset invalid on bool pattern"),
+            SpirvType::Bool => self.constant_bool(self.span(), fill_byte != 0).def(self),
             SpirvType::Integer(width, _signedness) => match width {
                 8 => self.constant_u8(self.span(), fill_byte).def(self),
                 16 => self
@@ -216,6 +589,12 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                 )),
             },
             SpirvType::Float(width) => match width {
+                16 => self
+                    .def_constant(
+                        ty.def(self.span(), self),
+                        SpirvConst::F16(memset_fill_u16(fill_byte)),
+                    )
+                    .def(self),
                 32 => self
                     .constant_f32(self.span(), f32::from_bits(memset_fill_u32(fill_byte)))
                     .def(self),
@@ -224,7 +603,14 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                     .def(self),
                 _ => self.fatal(format!("memset on float width {width} not implemented yet")),
             },
-            SpirvType::Adt { .. } => self.fatal("memset on structs not implemented yet"),
+            SpirvType::Adt { field_types, .. } => {
+                let field_pats = field_types
+                    .iter()
+                    .map(|&field| self.memset_const_pattern(&self.lookup_type(field), fill_byte))
+                    .collect::<Vec<_>>();
+                self.constant_composite(ty.def(self.span(), self), field_pats.into_iter())
+                    .def(self)
+            }
             SpirvType::Vector { element, count } | SpirvType::Matrix { element, count } => {
                 let elem_pat = self.memset_const_pattern(&self.lookup_type(element), fill_byte);
                 self.constant_composite(
@@ -242,10 +628,15 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                 )
                 .def(self)
             }
-            SpirvType::RuntimeArray { .. } => {
-                self.fatal("memset on runtime arrays not implemented yet")
+            // NOTE runtime arrays can only be memset element-wise (see `memset`).
+            SpirvType::RuntimeArray { .. } => self.fatal("cannot memset runtime array value"),
+            SpirvType::Pointer { .. } => {
+                let int_pattern = match self.tcx.data_layout.pointer_size.bits() {
+                    32 => self.constant_u32(self.span(), memset_fill_u32(fill_byte)),
+                    _ => self.constant_u64(self.span(), memset_fill_u64(fill_byte)),
+                };
+                self.memset_ptr_pattern(ty, int_pattern.def(self))
             }
-            SpirvType::Pointer { .. } => self.fatal("memset on pointers not implemented yet"),
             SpirvType::Function { .. } => self.fatal("memset on functions not implemented yet"),
             SpirvType::Image { .. } => self.fatal("cannot memset image"),
             SpirvType::Sampler => self.fatal("cannot memset sampler"),
@@ -261,7 +652,12 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
     fn memset_dynamic_pattern(&self, ty: &SpirvType<'tcx>, fill_var: Word) -> Word {
         match *ty {
             SpirvType::Void => self.fatal("memset invalid on void pattern"),
-            SpirvType::Bool => self.fatal("memset invalid on bool pattern"),
+            SpirvType::Bool => {
+                let zero = self.constant_u8(self.span(), 0).def(self);
+                self.emit()
+                    .i_not_equal(ty.def(self.span(), self), None, fill_var, zero)
+                    .unwrap()
+            }
             SpirvType::Integer(width, _signedness) => match width {
                 8 => fill_var,
                 16 => memset_dynamic_scalar(self, fill_var, 2, false),
@@ -272,11 +668,24 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                 )),
             },
             SpirvType::Float(width) => match width {
+                16 => memset_dynamic_scalar(self, fill_var, 2, true),
                 32 => memset_dynamic_scalar(self, fill_var, 4, true),
                 64 => memset_dynamic_scalar(self, fill_var, 8, true),
                 _ => self.fatal(format!("memset on float width {width} not implemented yet")),
             },
-            SpirvType::Adt { .. } => self.fatal("memset on structs not implemented yet"),
+            // Zero-sized structs have no bytes to fill.
+            SpirvType::Adt { field_types, .. } if field_types.is_empty() => {
+                self.constant_null(ty.def(self.span(), self)).def(self)
+            }
+            SpirvType::Adt { field_types, .. } => {
+                let field_pats = field_types
+                    .iter()
+                    .map(|&field| self.memset_dynamic_pattern(&self.lookup_type(field), fill_var))
+                    .collect::<Vec<_>>();
+                self.emit()
+                    .composite_construct(ty.def(self.span(), self), None, field_pats)
+                    .unwrap()
+            }
             SpirvType::Array { element, count } => {
                 let elem_pat = self.memset_dynamic_pattern(&self.lookup_type(element), fill_var);
                 let count = self.builder.lookup_const_u64(count).unwrap() as usize;
@@ -298,10 +707,13 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
                     )
                     .unwrap()
             }
-            SpirvType::RuntimeArray { .. } => {
-                self.fatal("memset on runtime arrays not implemented yet")
+            // NOTE runtime arrays can only be memset element-wise (see `memset`).
+            SpirvType::RuntimeArray { .. } => self.fatal("cannot memset runtime array value"),
+            SpirvType::Pointer { .. } => {
+                let ptr_bytes = self.tcx.data_layout.pointer_size.bytes() as usize;
+                let int_pattern = memset_dynamic_scalar(self, fill_var, ptr_bytes, false);
+                self.memset_ptr_pattern(ty, int_pattern)
             }
-            SpirvType::Pointer { .. } => self.fatal("memset on pointers not implemented yet"),
             SpirvType::Function { .. } => self.fatal("memset on functions not implemented yet"),
             SpirvType::Image { .. } => self.fatal("cannot memset image"),
             SpirvType::Sampler => self.fatal("cannot memset sampler"),
@@ -314,25 +726,40 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
         }
     }
 
-    fn memset_constant_size(&mut self, ptr: SpirvValue, pat: SpirvValue, size_bytes: u64) {
+    fn memset_constant_size(
+        &mut self,
+        ptr: SpirvValue,
+        pat: SpirvValue,
+        size_bytes: u64,
+        align: Align,
+        flags: MemFlags,
+    ) {
         let size_elem = self
             .lookup_type(pat.ty)
             .sizeof(self)
             .expect("Memset on unsized values not supported");
         let count = size_bytes / size_elem.bytes();
         if count == 1 {
-            self.store(pat, ptr, Align::from_bytes(0).unwrap());
+            self.store_with_flags(pat, ptr, align, flags);
         } else {
+            let elem_align = align.restrict_for_offset(size_elem);
             for index in 0..count {
                 let const_index = self.constant_u32(self.span(), index as u32);
                 let gep_ptr = self.gep(pat.ty, ptr, &[const_index]);
-                self.store(pat, gep_ptr, Align::from_bytes(0).unwrap());
+                self.store_with_flags(pat, gep_ptr, elem_align, flags);
             }
         }
     }
 
     // TODO: Test this is correct
-    fn memset_dynamic_size(&mut self, ptr: SpirvValue, pat: SpirvValue, size_bytes: SpirvValue) {
+    fn memset_dynamic_size(
+        &mut self,
+        ptr: SpirvValue,
+        pat: SpirvValue,
+        size_bytes: SpirvValue,
+        align: Align,
+        flags: MemFlags,
+    ) {
         let size_elem = self
             .lookup_type(pat.ty)
             .sizeof(self)
@@ -358,7 +785,7 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
 
         self.switch_to_block(body_bb);
         let gep_ptr = self.gep(pat.ty, ptr, &[current_index]);
-        self.store(pat, gep_ptr, zero_align);
+        self.store_with_flags(pat, gep_ptr, align.restrict_for_offset(size_elem), flags);
         let current_index_plus_1 = self.add(current_index, one);
         self.store(current_index_plus_1, index, zero_align);
         self.br(header_bb);
@@ -366,16 +793,25 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
         self.switch_to_block(exit_bb);
     }
 
+    // NOTE with `PhysicalStorageBuffer64` addressing, conversions are only
+    // legal for `PhysicalStorageBuffer` pointers, which the linker checks
+    // once storage classes have been inferred.
     fn zombie_convert_ptr_to_u(&self, def: Word) {
-        self.zombie(def, "cannot convert pointers to integers");
+        if self.target.addressing_model() == AddressingModel::Logical {
+            self.zombie(def, "cannot convert pointers to integers");
+        }
     }
 
     fn zombie_convert_u_to_ptr(&self, def: Word) {
-        self.zombie(def, "cannot convert integers to pointers");
+        if self.target.addressing_model() == AddressingModel::Logical {
+            self.zombie(def, "cannot convert integers to pointers");
+        }
     }
 
     fn zombie_ptr_equal(&self, def: Word, inst: &str) {
-        if !self.builder.has_capability(Capability::VariablePointers) {
+        if self.target.addressing_model() == AddressingModel::Logical
+            && !self.builder.has_capability(Capability::VariablePointers)
+        {
             self.zombie(
                 def,
                 &format!("{inst} without OpCapability VariablePointers"),
@@ -835,42 +1271,47 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
 
     fn fadd_algebraic(
         &mut self,
-        _: <Self as BackendTypes>::Value,
-        _: <Self as BackendTypes>::Value,
+        lhs: <Self as BackendTypes>::Value,
+        rhs: <Self as BackendTypes>::Value,
     ) -> <Self as BackendTypes>::Value {
-        todo!()
+        let result = self.fadd(lhs, rhs);
+        self.decorate_algebraic(result)
     }
 
     fn fsub_algebraic(
         &mut self,
-        _: <Self as BackendTypes>::Value,
-        _: <Self as BackendTypes>::Value,
+        lhs: <Self as BackendTypes>::Value,
+        rhs: <Self as BackendTypes>::Value,
     ) -> <Self as BackendTypes>::Value {
-        todo!()
+        let result = self.fsub(lhs, rhs);
+        self.decorate_algebraic(result)
     }
 
     fn fmul_algebraic(
         &mut self,
-        _: <Self as BackendTypes>::Value,
-        _: <Self as BackendTypes>::Value,
+        lhs: <Self as BackendTypes>::Value,
+        rhs: <Self as BackendTypes>::Value,
     ) -> <Self as BackendTypes>::Value {
-        todo!()
+        let result = self.fmul(lhs, rhs);
+        self.decorate_algebraic(result)
     }
 
     fn fdiv_algebraic(
         &mut self,
-        _: <Self as BackendTypes>::Value,
-        _: <Self as BackendTypes>::Value,
+        lhs: <Self as BackendTypes>::Value,
+        rhs: <Self as BackendTypes>::Value,
     ) -> <Self as BackendTypes>::Value {
-        todo!()
+        let result = self.fdiv(lhs, rhs);
+        self.decorate_algebraic(result)
     }
 
     fn frem_algebraic(
         &mut self,
-        _: <Self as BackendTypes>::Value,
-        _: <Self as BackendTypes>::Value,
+        lhs: <Self as BackendTypes>::Value,
+        rhs: <Self as BackendTypes>::Value,
     ) -> <Self as BackendTypes>::Value {
-        todo!()
+        let result = self.frem(lhs, rhs);
+        self.decorate_algebraic(result)
     }
 
     fn cx(&self) -> &Self::CodegenCx {
@@ -1202,28 +1643,69 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
     fn checked_binop(
         &mut self,
         oop: OverflowOp,
-        _ty: Ty<'_>,
+        ty: Ty<'_>,
         lhs: Self::Value,
         rhs: Self::Value,
     ) -> (Self::Value, Self::Value) {
-        // NOTE(eddyb) this needs to be `undef`, not `false`/`true`, because
-        // we don't want the user's boolean constants to keep the zombie alive.
-        let bool = SpirvType::Bool.def(self.span(), self);
-        let overflowed = self.undef(bool);
-        let result = match oop {
-            OverflowOp::Add => (self.add(lhs, rhs), overflowed),
-            OverflowOp::Sub => (self.sub(lhs, rhs), overflowed),
-            OverflowOp::Mul => (self.mul(lhs, rhs), overflowed),
-        };
-        self.zombie(
-            result.1.def(self),
-            match oop {
-                OverflowOp::Add => "checked add is not supported yet",
-                OverflowOp::Sub => "checked sub is not supported yet",
-                OverflowOp::Mul => "checked mul is not supported yet",
-            },
-        );
-        result
+        assert_ty_eq!(self, lhs.ty, rhs.ty);
+        let int_ty = lhs.ty;
+        let zero = self.const_uint(int_ty, 0);
+        match (oop, ty.is_signed()) {
+            (OverflowOp::Add, false) => {
+                let (result, carry) = self.extended_int_op(Op::IAddCarry, lhs, rhs);
+                (result, self.icmp(IntPredicate::IntNE, carry, zero))
+            }
+            (OverflowOp::Sub, false) => {
+                let (result, borrow) = self.extended_int_op(Op::ISubBorrow, lhs, rhs);
+                (result, self.icmp(IntPredicate::IntNE, borrow, zero))
+            }
+            (OverflowOp::Mul, false) => {
+                let (low, high) = self.extended_int_op(Op::UMulExtended, lhs, rhs);
+                (low, self.icmp(IntPredicate::IntNE, high, zero))
+            }
+
+            // Signed addition overflows iff both operands have the same sign,
+            // and the result has the opposite one, i.e. when the sign bit of
+            // `(lhs ^ result) & (rhs ^ result)` is set.
+            (OverflowOp::Add, true) => {
+                let result = self.add(lhs, rhs);
+                let lhs_sign_changed = self.xor(lhs, result);
+                let rhs_sign_changed = self.xor(rhs, result);
+                let both_sign_changed = self.and(lhs_sign_changed, rhs_sign_changed);
+                (
+                    result,
+                    self.icmp(IntPredicate::IntSLT, both_sign_changed, zero),
+                )
+            }
+            // Signed subtraction overflows iff the operands have different signs,
+            // and the result doesn't have the sign of `lhs`, i.e. when the sign
+            // bit of `(lhs ^ rhs) & (lhs ^ result)` is set.
+            (OverflowOp::Sub, true) => {
+                let result = self.sub(lhs, rhs);
+                let signs_differ = self.xor(lhs, rhs);
+                let lhs_sign_changed = self.xor(lhs, result);
+                let overflow_bits = self.and(signs_differ, lhs_sign_changed);
+                (result, self.icmp(IntPredicate::IntSLT, overflow_bits, zero))
+            }
+            // Signed multiplication overflows iff the high half of the full
+            // result isn't just the sign extension of the low half.
+            (OverflowOp::Mul, true) => {
+                let (low, high) = self.extended_int_op(Op::SMulExtended, lhs, rhs);
+                let bits = match self.lookup_type(int_ty) {
+                    SpirvType::Integer(bits, _) => bits,
+                    other => self.fatal(format!(
+                        "checked mul on non-integer type: {}",
+                        other.debug(int_ty, self)
+                    )),
+                };
+                let sign_shift = self.const_uint(int_ty, u64::from(bits - 1));
+                let low_sign_extension = self.ashr(low, sign_shift);
+                (
+                    low,
+                    self.icmp(IntPredicate::IntNE, high, low_sign_extension),
+                )
+            }
+        }
     }
 
     // rustc has the concept of an immediate vs. memory type - bools are compiled to LLVM bools as
@@ -1282,22 +1764,13 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         self.fatal("array alloca not supported yet")
     }
 
-    fn load(&mut self, ty: Self::Type, ptr: Self::Value, _align: Align) -> Self::Value {
-        let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, ty);
-        let loaded_val = ptr.const_fold_load(self).unwrap_or_else(|| {
-            self.emit()
-                .load(access_ty, None, ptr.def(self), None, empty())
-                .unwrap()
-                .with_type(access_ty)
-        });
-        self.bitcast(loaded_val, ty)
+    fn load(&mut self, ty: Self::Type, ptr: Self::Value, align: Align) -> Self::Value {
+        self.load_with_flags(ty, ptr, align, MemFlags::empty())
     }
 
     fn volatile_load(&mut self, ty: Self::Type, ptr: Self::Value) -> Self::Value {
-        // TODO: Implement this
-        let result = self.load(ty, ptr, Align::from_bytes(0).unwrap());
-        self.zombie(result.def(self), "volatile load is not supported yet");
-        result
+        let align = Align::from_bytes(0).unwrap();
+        self.load_with_flags(ty, ptr, align, MemFlags::VOLATILE)
     }
 
     fn atomic_load(
@@ -1309,8 +1782,7 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
     ) -> Self::Value {
         let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, ty);
 
-        // TODO: Default to device scope
-        let memory = self.constant_u32(self.span(), Scope::Device as u32);
+        let memory = self.atomic_scope_def();
         let semantics = self.ordering_to_semantics_def(order);
         let result = self
             .emit()
@@ -1405,15 +1877,8 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         // ignore
     }
 
-    fn store(&mut self, val: Self::Value, ptr: Self::Value, _align: Align) -> Self::Value {
-        let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, val.ty);
-        let val = self.bitcast(val, access_ty);
-
-        self.emit()
-            .store(ptr.def(self), val.def(self), None, empty())
-            .unwrap();
-        // FIXME(eddyb) this is meant to be a handle the store instruction itself.
-        val
+    fn store(&mut self, val: Self::Value, ptr: Self::Value, align: Align) -> Self::Value {
+        self.store_with_flags(val, ptr, align, MemFlags::empty())
     }
 
     fn store_with_flags(
@@ -1423,10 +1888,15 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         align: Align,
         flags: MemFlags,
     ) -> Self::Value {
-        if flags != MemFlags::empty() {
-            self.err(format!("store_with_flags is not supported yet: {flags:?}"));
-        }
-        self.store(val, ptr, align)
+        let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, val.ty);
+        let val = self.bitcast(val, access_ty);
+
+        let (access, access_operands) = self.memory_access(flags, align, true, false);
+        self.emit()
+            .store(ptr.def(self), val.def(self), access, access_operands)
+            .unwrap();
+        // FIXME(eddyb) this is meant to be a handle the store instruction itself.
+        val
     }
 
     fn atomic_store(
@@ -1439,8 +1909,7 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         let (ptr, access_ty) = self.adjust_pointer_for_typed_access(ptr, val.ty);
         let val = self.bitcast(val, access_ty);
 
-        // TODO: Default to device scope
-        let memory = self.constant_u32(self.span(), Scope::Device as u32);
+        let memory = self.atomic_scope_def();
         let semantics = self.ordering_to_semantics_def(order);
         self.validate_atomic(val.ty, ptr.def(self));
         self.emit()
@@ -1753,8 +2222,16 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                 "pointercast called on non-pointer dest type: {other:?}"
             )),
         };
+        let is_fn = |pointee| matches!(self.lookup_type(pointee), SpirvType::Function { .. });
         if ptr.ty == dest_ty {
             ptr
+        } else if is_fn(ptr_pointee) || is_fn(dest_pointee) {
+            // Casts from/to function pointers are left to the linker, which
+            // replaces function pointers with integers (see `linker::devirtualize`).
+            self.emit()
+                .bitcast(dest_ty, None, ptr.def(self))
+                .unwrap()
+                .with_type(dest_ty)
         } else if let Some((indices, _)) = self.recover_access_chain_from_offset(
             ptr_pointee,
             Size::ZERO,
@@ -1820,113 +2297,28 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                     .s_less_than_equal(b, None, lhs.def(self), rhs.def(self)),
             },
             SpirvType::Pointer { .. } => match op {
-                IntEQ => {
-                    if self.emit().version().unwrap() > (1, 3) {
-                        let ptr_equal =
-                            self.emit().ptr_equal(b, None, lhs.def(self), rhs.def(self));
-
-                        ptr_equal.map(|result| {
-                            self.zombie_ptr_equal(result, "OpPtrEqual");
-                            result
-                        })
-                    } else {
-                        let int_ty = self.type_usize();
-                        let lhs = self
-                            .emit()
-                            .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                            .unwrap();
-                        self.zombie_convert_ptr_to_u(lhs);
-                        let rhs = self
-                            .emit()
-                            .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                            .unwrap();
-                        self.zombie_convert_ptr_to_u(rhs);
-                        self.emit().i_not_equal(b, None, lhs, rhs)
-                    }
-                }
-                IntNE => {
-                    if self.emit().version().unwrap() > (1, 3) {
-                        self.emit()
-                            .ptr_not_equal(b, None, lhs.def(self), rhs.def(self))
-                            .map(|result| {
-                                self.zombie_ptr_equal(result, "OpPtrNotEqual");
-                                result
-                            })
-                    } else {
-                        let int_ty = self.type_usize();
-                        let lhs = self
-                            .emit()
-                            .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                            .unwrap();
-                        self.zombie_convert_ptr_to_u(lhs);
-                        let rhs = self
-                            .emit()
-                            .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                            .unwrap();
-                        self.zombie_convert_ptr_to_u(rhs);
-                        self.emit().i_not_equal(b, None, lhs, rhs)
-                    }
-                }
-                IntUGT => {
-                    let int_ty = self.type_usize();
-                    let lhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(lhs);
-                    let rhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(rhs);
-                    self.emit().u_greater_than(b, None, lhs, rhs)
-                }
-                IntUGE => {
-                    let int_ty = self.type_usize();
-                    let lhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(lhs);
-                    let rhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(rhs);
-                    self.emit().u_greater_than_equal(b, None, lhs, rhs)
-                }
-                IntULT => {
-                    let int_ty = self.type_usize();
-                    let lhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(lhs);
-                    let rhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(rhs);
-                    self.emit().u_less_than(b, None, lhs, rhs)
-                }
-                IntULE => {
+                IntEQ if self.emit().version().unwrap() > (1, 3) => self
+                    .emit()
+                    .ptr_equal(b, None, lhs.def(self), rhs.def(self))
+                    .map(|result| {
+                        self.zombie_ptr_equal(result, "OpPtrEqual");
+                        result
+                    }),
+                IntNE if self.emit().version().unwrap() > (1, 3) => self
+                    .emit()
+                    .ptr_not_equal(b, None, lhs.def(self), rhs.def(self))
+                    .map(|result| {
+                        self.zombie_ptr_equal(result, "OpPtrNotEqual");
+                        result
+                    }),
+                // Everything else compares the addresses (which is only legal
+                // for physical pointers, see `zombie_convert_ptr_to_u`).
+                _ => {
                     let int_ty = self.type_usize();
-                    let lhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, lhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(lhs);
-                    let rhs = self
-                        .emit()
-                        .convert_ptr_to_u(int_ty, None, rhs.def(self))
-                        .unwrap();
-                    self.zombie_convert_ptr_to_u(rhs);
-                    self.emit().u_less_than_equal(b, None, lhs, rhs)
+                    let lhs = self.ptrtoint(lhs, int_ty);
+                    let rhs = self.ptrtoint(rhs, int_ty);
+                    return self.icmp(op, lhs, rhs);
                 }
-                IntSGT => self.fatal("TODO: pointer operator IntSGT not implemented yet"),
-                IntSGE => self.fatal("TODO: pointer operator IntSGE not implemented yet"),
-                IntSLT => self.fatal("TODO: pointer operator IntSLT not implemented yet"),
-                IntSLE => self.fatal("TODO: pointer operator IntSLE not implemented yet"),
             },
             SpirvType::Bool => match op {
                 IntEQ => self
@@ -2040,17 +2432,12 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
     fn memcpy(
         &mut self,
         dst: Self::Value,
-        _dst_align: Align,
+        dst_align: Align,
         src: Self::Value,
-        _src_align: Align,
+        src_align: Align,
         size: Self::Value,
         flags: MemFlags,
     ) {
-        if flags != MemFlags::empty() {
-            self.err(format!(
-                "memcpy with mem flags is not supported yet: {flags:?}"
-            ));
-        }
         let const_size = self.builder.lookup_const_u64(size).map(Size::from_bytes);
         if const_size == Some(Size::ZERO) {
             // Nothing to do!
@@ -2079,13 +2466,15 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
 
         if let Some((dst, src)) = typed_copy_dst_src {
             if let Some(const_value) = src.const_fold_load(self) {
-                self.store(const_value, dst, Align::from_bytes(0).unwrap());
+                self.store_with_flags(const_value, dst, dst_align, flags);
             } else {
+                let access_operands = self.copy_memory_operands(flags, dst_align, src_align);
                 self.emit()
-                    .copy_memory(dst.def(self), src.def(self), None, None, empty())
+                    .copy_memory(dst.def(self), src.def(self), None, None, access_operands)
                     .unwrap();
             }
         } else {
+            let access_operands = self.copy_memory_operands(flags, dst_align, src_align);
             self.emit()
                 .copy_memory_sized(
                     dst.def(self),
@@ -2093,7 +2482,7 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                     size.def(self),
                     None,
                     None,
-                    empty(),
+                    access_operands,
                 )
                 .unwrap();
             self.zombie(dst.def(self), "cannot memcpy dynamically sized data");
@@ -2117,14 +2506,9 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         ptr: Self::Value,
         fill_byte: Self::Value,
         size: Self::Value,
-        _align: Align,
+        align: Align,
         flags: MemFlags,
     ) {
-        if flags != MemFlags::empty() {
-            self.err(format!(
-                "memset with mem flags is not supported yet: {flags:?}"
-            ));
-        }
         let elem_ty = match self.lookup_type(ptr.ty) {
             SpirvType::Pointer { pointee } => pointee,
             _ => self.fatal(format!(
@@ -2132,15 +2516,29 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                 self.debug_type(ptr.ty)
             )),
         };
+        let const_size = self.builder.lookup_const_u64(size);
+        if const_size == Some(0) {
+            // Nothing to do!
+            return;
+        }
+        // Runtime-sized destinations have no value to store as a whole, so
+        // they're filled element by element instead (in a loop, when the size
+        // isn't constant, see `memset_dynamic_size`).
+        let (ptr, elem_ty) = match self.lookup_type(elem_ty) {
+            SpirvType::RuntimeArray { element } => {
+                (self.pointercast(ptr, self.type_ptr_to(element)), element)
+            }
+            _ => (ptr, elem_ty),
+        };
         let elem_ty_spv = self.lookup_type(elem_ty);
         let pat = match self.builder.lookup_const_u64(fill_byte) {
             Some(fill_byte) => self.memset_const_pattern(&elem_ty_spv, fill_byte as u8),
             None => self.memset_dynamic_pattern(&elem_ty_spv, fill_byte.def(self)),
         }
         .with_type(elem_ty);
-        match self.builder.lookup_const_u64(size) {
-            Some(size) => self.memset_constant_size(ptr, pat, size),
-            None => self.memset_dynamic_size(ptr, pat, size),
+        match const_size {
+            Some(size) => self.memset_constant_size(ptr, pat, size, align, flags),
+            None => self.memset_dynamic_size(ptr, pat, size, align, flags),
         }
     }
 
@@ -2313,8 +2711,7 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         let src = self.bitcast(src, access_ty);
 
         self.validate_atomic(access_ty, dst.def(self));
-        // TODO: Default to device scope
-        let memory = self.constant_u32(self.span(), Scope::Device as u32);
+        let memory = self.atomic_scope_def();
         let semantics_equal = self.ordering_to_semantics_def(order);
         let semantics_unequal = self.ordering_to_semantics_def(failure_order);
         // Note: OpAtomicCompareExchangeWeak is deprecated, and has the same semantics
@@ -2348,10 +2745,7 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         let src = self.bitcast(src, access_ty);
 
         self.validate_atomic(access_ty, dst.def(self));
-        // TODO: Default to device scope
-        let memory = self
-            .constant_u32(self.span(), Scope::Device as u32)
-            .def(self);
+        let memory = self.atomic_scope_def().def(self);
         let semantics = self.ordering_to_semantics_def(order).def(self);
         use AtomicRmwBinOp::*;
         let result = match op {
@@ -2387,7 +2781,14 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                 semantics,
                 src.def(self),
             ),
-            AtomicNand => self.fatal("atomic nand is not supported"),
+            AtomicNand => {
+                let semantics = self.ordering_to_semantics_def(order);
+                let result = self.atomic_rmw_cas_loop(dst, semantics, |bx, old| {
+                    let and = bx.and(old, src);
+                    bx.not(and)
+                });
+                return self.bitcast(result, ty);
+            }
             AtomicOr => self.emit().atomic_or(
                 access_ty,
                 None,
@@ -2442,12 +2843,14 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
         self.bitcast(result, ty)
     }
 
-    fn atomic_fence(&mut self, order: AtomicOrdering, _scope: SynchronizationScope) {
-        // Ignore sync scope (it only has "single thread" and "cross thread")
-        // TODO: Default to device scope
-        let memory = self
-            .constant_u32(self.span(), Scope::Device as u32)
-            .def(self);
+    fn atomic_fence(&mut self, order: AtomicOrdering, scope: SynchronizationScope) {
+        // A single-thread fence (i.e. `compiler_fence`) only has to prevent
+        // reordering of the current invocation's own memory accesses, and
+        // there is no SPIR-V instruction for that (nor any need for one).
+        if let SynchronizationScope::SingleThread = scope {
+            return;
+        }
+        let memory = self.atomic_scope_def().def(self);
         let semantics = self.ordering_to_semantics_def(order).def(self);
         self.emit().memory_barrier(memory, semantics).unwrap();
     }
@@ -2513,11 +2916,10 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
                         assert_ty_eq!(self, callee_ty, pointee);
                         function
                     }
-                    // Truly indirect call.
+                    // Truly indirect call, which the linker replaces with direct
+                    // calls to every possible callee (see `linker::devirtualize`).
                     else {
-                        let fn_ptr_val = callee.def(self);
-                        self.zombie(fn_ptr_val, "indirect calls are not supported in SPIR-V");
-                        fn_ptr_val
+                        callee.def(self)
                     },
                     return_type,
                     arguments,
@@ -2554,6 +2956,11 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
             .borrow()
             .get(&callee_val)
             .copied();
+        let atomic_float_intrinsic = self
+            .atomic_float_intrinsic_fn_id
+            .borrow()
+            .get(&callee_val)
+            .copied();
         if let Some(libm_intrinsic) = libm_intrinsic {
             let result = self.call_libm_intrinsic(libm_intrinsic, result_type, args);
             if result_type != result.ty {
@@ -3048,6 +3455,8 @@ impl<'a, 'tcx> BuilderMethods<'a, 'tcx> for Builder<'a, 'tcx> {
             self.undef(result_type)
         } else if let Some(mode) = buffer_load_intrinsic {
             self.codegen_buffer_load_intrinsic(result_type, args, mode)
+        } else if let Some(op) = atomic_float_intrinsic {
+            self.codegen_atomic_float_intrinsic(op, result_type, args)
         } else if let Some(mode) = buffer_store_intrinsic {
             self.codegen_buffer_store_intrinsic(args, mode);
 
@@ -1,6 +1,6 @@
 use rspirv::{
     dr::Operand,
-    spirv::{Capability, GLOp, Word},
+    spirv::{CLOp, Capability, GLOp, Word},
 };
 
 use super::Builder;
@@ -10,6 +10,7 @@ use crate::{
 };
 
 const GLSL_STD_450: &str = "GLSL.std.450";
+const OPENCL_STD: &str = "OpenCL.std";
 
 /// Manager for OpExtInst/OpExtImport instructions
 #[derive(Default)]
@@ -18,6 +19,7 @@ pub struct ExtInst {
     custom: Option<Word>,
 
     glsl: Option<Word>,
+    opencl: Option<Word>,
     integer_functions_2_intel: bool,
 }
 
@@ -44,6 +46,16 @@ impl ExtInst {
         }
     }
 
+    pub fn import_opencl(&mut self, bx: &Builder<'_, '_>) -> Word {
+        if let Some(id) = self.opencl {
+            id
+        } else {
+            let id = bx.emit_global().ext_inst_import(OPENCL_STD);
+            self.opencl = Some(id);
+            id
+        }
+    }
+
     pub fn require_integer_functions_2_intel(&mut self, bx: &Builder<'_, '_>, to_zombie: Word) {
         if !self.integer_functions_2_intel {
             self.integer_functions_2_intel = true;
@@ -104,4 +116,106 @@ impl<'a, 'tcx> Builder<'a, 'tcx> {
             .unwrap()
             .with_type(result_type)
     }
+
+    pub fn cl_op(
+        &mut self,
+        op: CLOp,
+        result_type: Word,
+        args: impl AsRef<[SpirvValue]>,
+    ) -> SpirvValue {
+        let args = args.as_ref();
+        let opencl = self.ext_inst.borrow_mut().import_opencl(self);
+        self.emit()
+            .ext_inst(
+                result_type,
+                None,
+                opencl,
+                op as u32,
+                args.iter().map(|a| Operand::IdRef(a.def(self))),
+            )
+            .unwrap()
+            .with_type(result_type)
+    }
+
+    /// Emit the GLSL.std.450 instruction `op`, or, for targets that can't import
+    /// GLSL.std.450 (i.e. `Kernel` modules), its equivalent from OpenCL.std.
+    pub fn std_op(
+        &mut self,
+        op: GLOp,
+        result_type: Word,
+        args: impl AsRef<[SpirvValue]>,
+    ) -> SpirvValue {
+        if !self.target.is_kernel() {
+            return self.gl_op(op, result_type, args);
+        }
+        match opencl_equivalent(op) {
+            Some(cl_op) => self.cl_op(cl_op, result_type, args),
+            None => {
+                let undef = self.undef(result_type);
+                self.zombie(
+                    undef.def(self),
+                    &format!("GLSL.std.450 `{op:?}` has no OpenCL.std equivalent"),
+                );
+                undef
+            }
+        }
+    }
+}
+
+/// OpenCL.std instruction with the same semantics (and operands) as `op`.
+///
+/// Instructions returning structs in GLSL.std.450 (e.g. `ModfStruct`) take an
+/// output pointer in OpenCL.std instead, and so are not covered by this table
+/// (nor is `Fract`, as OpenCL.std `fract` also outputs the floor that way).
+fn opencl_equivalent(op: GLOp) -> Option<CLOp> {
+    Some(match op {
+        GLOp::Round => CLOp::round,
+        GLOp::RoundEven => CLOp::rint,
+        GLOp::Trunc => CLOp::trunc,
+        GLOp::FAbs => CLOp::fabs,
+        GLOp::SAbs => CLOp::s_abs,
+        GLOp::Floor => CLOp::floor,
+        GLOp::Ceil => CLOp::ceil,
+        GLOp::Radians => CLOp::radians,
+        GLOp::Degrees => CLOp::degrees,
+        GLOp::Sin => CLOp::sin,
+        GLOp::Cos => CLOp::cos,
+        GLOp::Tan => CLOp::tan,
+        GLOp::Asin => CLOp::asin,
+        GLOp::Acos => CLOp::acos,
+        GLOp::Atan => CLOp::atan,
+        GLOp::Sinh => CLOp::sinh,
+        GLOp::Cosh => CLOp::cosh,
+        GLOp::Tanh => CLOp::tanh,
+        GLOp::Asinh => CLOp::asinh,
+        GLOp::Acosh => CLOp::acosh,
+        GLOp::Atanh => CLOp::atanh,
+        GLOp::Atan2 => CLOp::atan2,
+        GLOp::Pow => CLOp::pow,
+        GLOp::Exp => CLOp::exp,
+        GLOp::Log => CLOp::log,
+        GLOp::Exp2 => CLOp::exp2,
+        GLOp::Log2 => CLOp::log2,
+        GLOp::Sqrt => CLOp::sqrt,
+        GLOp::InverseSqrt => CLOp::rsqrt,
+        GLOp::FMin | GLOp::NMin => CLOp::fmin,
+        GLOp::UMin => CLOp::u_min,
+        GLOp::SMin => CLOp::s_min,
+        GLOp::FMax | GLOp::NMax => CLOp::fmax,
+        GLOp::UMax => CLOp::u_max,
+        GLOp::SMax => CLOp::s_max,
+        GLOp::FClamp | GLOp::NClamp => CLOp::fclamp,
+        GLOp::UClamp => CLOp::u_clamp,
+        GLOp::SClamp => CLOp::s_clamp,
+        GLOp::FMix => CLOp::mix,
+        GLOp::Step => CLOp::step,
+        GLOp::SmoothStep => CLOp::smoothstep,
+        GLOp::Fma => CLOp::fma,
+        GLOp::Ldexp => CLOp::ldexp,
+        GLOp::Length => CLOp::length,
+        GLOp::Distance => CLOp::distance,
+        GLOp::Cross => CLOp::cross,
+        GLOp::Normalize => CLOp::normalize,
+        _ => return None,
+    })
 }
@@ -1,6 +1,9 @@
 use std::assert_matches::assert_matches;
 
-use rspirv::{dr::Operand, spirv::GLOp};
+use rspirv::{
+    dr::Operand,
+    spirv::{CLOp, GLOp},
+};
 use rustc_codegen_ssa::{
     mir::{operand::OperandRef, place::PlaceRef},
     traits::{BuilderMethods, IntrinsicCallMethods},
@@ -152,30 +155,36 @@ impl<'a, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'tcx> {
                 result
             }
 
-            sym::sqrtf32 | sym::sqrtf64 => self.gl_op(GLOp::Sqrt, ret_ty, [args[0].immediate()]),
+            sym::sqrtf32 | sym::sqrtf64 => self.std_op(GLOp::Sqrt, ret_ty, [args[0].immediate()]),
+            sym::powif32 | sym::powif64 if self.target.is_kernel() => {
+                self.cl_op(CLOp::pown, ret_ty, [args[0].immediate(), args[1].immediate()])
+            }
             sym::powif32 | sym::powif64 => {
                 let float = self.sitofp(args[1].immediate(), args[0].immediate().ty);
-                self.gl_op(GLOp::Pow, ret_ty, [args[0].immediate(), float])
+                self.std_op(GLOp::Pow, ret_ty, [args[0].immediate(), float])
             }
-            sym::sinf32 | sym::sinf64 => self.gl_op(GLOp::Sin, ret_ty, [args[0].immediate()]),
-            sym::cosf32 | sym::cosf64 => self.gl_op(GLOp::Cos, ret_ty, [args[0].immediate()]),
-            sym::powf32 | sym::powf64 => self.gl_op(
+            sym::sinf32 | sym::sinf64 => self.std_op(GLOp::Sin, ret_ty, [args[0].immediate()]),
+            sym::cosf32 | sym::cosf64 => self.std_op(GLOp::Cos, ret_ty, [args[0].immediate()]),
+            sym::powf32 | sym::powf64 => self.std_op(
                 GLOp::Pow,
                 ret_ty,
                 [args[0].immediate(), args[1].immediate()],
             ),
-            sym::expf32 | sym::expf64 => self.gl_op(GLOp::Exp, ret_ty, [args[0].immediate()]),
-            sym::exp2f32 | sym::exp2f64 => self.gl_op(GLOp::Exp2, ret_ty, [args[0].immediate()]),
-            sym::logf32 | sym::logf64 => self.gl_op(GLOp::Log, ret_ty, [args[0].immediate()]),
-            sym::log2f32 | sym::log2f64 => self.gl_op(GLOp::Log2, ret_ty, [args[0].immediate()]),
+            sym::expf32 | sym::expf64 => self.std_op(GLOp::Exp, ret_ty, [args[0].immediate()]),
+            sym::exp2f32 | sym::exp2f64 => self.std_op(GLOp::Exp2, ret_ty, [args[0].immediate()]),
+            sym::logf32 | sym::logf64 => self.std_op(GLOp::Log, ret_ty, [args[0].immediate()]),
+            sym::log2f32 | sym::log2f64 => self.std_op(GLOp::Log2, ret_ty, [args[0].immediate()]),
+            sym::log10f32 | sym::log10f64 if self.target.is_kernel() => {
+                self.cl_op(CLOp::log10, ret_ty, [args[0].immediate()])
+            }
             sym::log10f32 | sym::log10f64 => {
                 // spir-v glsl doesn't have log10, so,
                 // log10(x) == (1 / ln(10)) * ln(x)
                 let mul = self.constant_float(args[0].immediate().ty, 1.0 / 10.0f64.ln());
-                let ln = self.gl_op(GLOp::Log, ret_ty, [args[0].immediate()]);
+                let ln = self.std_op(GLOp::Log, ret_ty, [args[0].immediate()]);
                 self.mul(mul, ln)
             }
-            sym::fmaf32 | sym::fmaf64 => self.gl_op(
+            sym::fmaf32 | sym::fmaf64 => self.std_op(
                 GLOp::Fma,
                 ret_ty,
                 [
@@ -184,13 +193,13 @@ impl<'a, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'tcx> {
                     args[2].immediate(),
                 ],
             ),
-            sym::fabsf32 | sym::fabsf64 => self.gl_op(GLOp::FAbs, ret_ty, [args[0].immediate()]),
-            sym::minnumf32 | sym::minnumf64 => self.gl_op(
+            sym::fabsf32 | sym::fabsf64 => self.std_op(GLOp::FAbs, ret_ty, [args[0].immediate()]),
+            sym::minnumf32 | sym::minnumf64 => self.std_op(
                 GLOp::FMin,
                 ret_ty,
                 [args[0].immediate(), args[1].immediate()],
             ),
-            sym::maxnumf32 | sym::maxnumf64 => self.gl_op(
+            sym::maxnumf32 | sym::maxnumf64 => self.std_op(
                 GLOp::FMax,
                 ret_ty,
                 [args[0].immediate(), args[1].immediate()],
@@ -200,13 +209,13 @@ impl<'a, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'tcx> {
                 let sign = args[1].immediate();
                 self.copysign(val, sign)
             }
-            sym::floorf32 | sym::floorf64 => self.gl_op(GLOp::Floor, ret_ty, [args[0].immediate()]),
-            sym::ceilf32 | sym::ceilf64 => self.gl_op(GLOp::Ceil, ret_ty, [args[0].immediate()]),
-            sym::truncf32 | sym::truncf64 => self.gl_op(GLOp::Trunc, ret_ty, [args[0].immediate()]),
+            sym::floorf32 | sym::floorf64 => self.std_op(GLOp::Floor, ret_ty, [args[0].immediate()]),
+            sym::ceilf32 | sym::ceilf64 => self.std_op(GLOp::Ceil, ret_ty, [args[0].immediate()]),
+            sym::truncf32 | sym::truncf64 => self.std_op(GLOp::Trunc, ret_ty, [args[0].immediate()]),
             // TODO: Correctness of all these rounds
-            sym::rintf32 | sym::rintf64 => self.gl_op(GLOp::Round, ret_ty, [args[0].immediate()]),
+            sym::rintf32 | sym::rintf64 => self.std_op(GLOp::Round, ret_ty, [args[0].immediate()]),
             sym::nearbyintf32 | sym::nearbyintf64 | sym::roundf32 | sym::roundf64 => {
-                self.gl_op(GLOp::Round, ret_ty, [args[0].immediate()])
+                self.std_op(GLOp::Round, ret_ty, [args[0].immediate()])
             }
 
             sym::rotate_left | sym::rotate_right => {
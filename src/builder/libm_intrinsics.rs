@@ -1,8 +1,12 @@
-use rspirv::spirv::{GLOp, Word};
+use rspirv::spirv::{CLOp, GLOp, Word};
 use rustc_codegen_ssa::traits::BuilderMethods;
+use rustc_middle::bug;
 
 use super::Builder;
-use crate::builder_spirv::{SpirvValue, SpirvValueExt};
+use crate::{
+    builder_spirv::{SpirvValue, SpirvValueExt},
+    spirv_type::SpirvType,
+};
 
 #[derive(Copy, Clone, Debug)]
 pub enum LibmCustomIntrinsic {
@@ -34,6 +38,39 @@ pub enum LibmCustomIntrinsic {
     SinCos,
 }
 
+impl LibmCustomIntrinsic {
+    /// OpenCL.std instruction implementing this function directly, if any.
+    fn opencl_op(self) -> Option<CLOp> {
+        Some(match self {
+            Self::CopySign => CLOp::copysign,
+            Self::Cbrt => CLOp::cbrt,
+            Self::Erf => CLOp::erf,
+            Self::Erfc => CLOp::erfc,
+            Self::Exp10 => CLOp::exp10,
+            Self::Expm1 => CLOp::expm1,
+            Self::Fdim => CLOp::fdim,
+            Self::Fmod => CLOp::fmod,
+            Self::Log10 => CLOp::log10,
+            Self::Hypot => CLOp::hypot,
+            Self::Ilogb => CLOp::ilogb,
+            Self::Lgamma => CLOp::lgamma,
+            Self::Tgamma => CLOp::tgamma,
+            Self::Log1p => CLOp::log1p,
+            Self::NextAfter => CLOp::nextafter,
+            Self::Remainder => CLOp::remainder,
+            Self::Scalbn => CLOp::ldexp,
+
+            // These return a pair, see `cl_op_with_out_ptr`.
+            Self::LgammaR => CLOp::lgamma_r,
+            Self::RemQuo => CLOp::remquo,
+
+            Self::J0 | Self::Y0 | Self::J1 | Self::Y1 | Self::Jn | Self::Yn | Self::SinCos => {
+                return None;
+            }
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum LibmIntrinsic {
     GLOp(GLOp),
@@ -203,13 +240,36 @@ impl Builder<'_, '_> {
         result_type: Word,
         args: &[SpirvValue],
     ) -> SpirvValue {
+        if self.target.is_kernel() {
+            match intrinsic {
+                LibmIntrinsic::GLOp(GLOp::FrexpStruct) => {
+                    return self.cl_op_with_out_ptr(CLOp::frexp, result_type, args);
+                }
+                LibmIntrinsic::GLOp(GLOp::ModfStruct) => {
+                    return self.cl_op_with_out_ptr(CLOp::modf, result_type, args);
+                }
+                LibmIntrinsic::Custom(
+                    custom @ (LibmCustomIntrinsic::LgammaR | LibmCustomIntrinsic::RemQuo),
+                ) => {
+                    let op = custom.opencl_op().unwrap();
+                    return self.cl_op_with_out_ptr(op, result_type, args);
+                }
+                LibmIntrinsic::Custom(custom) => {
+                    if let Some(op) = custom.opencl_op() {
+                        return self.cl_op(op, result_type, args);
+                    }
+                }
+                LibmIntrinsic::GLOp(_) => {}
+            }
+        }
+
         match intrinsic {
-            LibmIntrinsic::GLOp(op) => self.gl_op(op, result_type, args),
+            LibmIntrinsic::GLOp(op) => self.std_op(op, result_type, args),
             LibmIntrinsic::Custom(LibmCustomIntrinsic::SinCos) => {
                 assert_eq!(args.len(), 1);
                 let x = args[0];
-                let sin = self.gl_op(GLOp::Sin, x.ty, [x]).def(self);
-                let cos = self.gl_op(GLOp::Cos, x.ty, [x]).def(self);
+                let sin = self.std_op(GLOp::Sin, x.ty, [x]).def(self);
+                let cos = self.std_op(GLOp::Cos, x.ty, [x]).def(self);
                 self.emit()
                     .composite_construct(result_type, None, [sin, cos].iter().copied())
                     .unwrap()
@@ -228,7 +288,7 @@ impl Builder<'_, '_> {
             }
             LibmIntrinsic::Custom(LibmCustomIntrinsic::Cbrt) => {
                 assert_eq!(args.len(), 1);
-                self.gl_op(
+                self.std_op(
                     GLOp::Pow,
                     result_type,
                     [args[0], self.constant_float(args[0].ty, 1.0 / 3.0)],
@@ -238,24 +298,24 @@ impl Builder<'_, '_> {
                 assert_eq!(args.len(), 1);
                 // log10(x) == (1 / ln(10)) * ln(x)
                 let mul = self.constant_float(args[0].ty, 1.0 / 10.0f64.ln());
-                let ln = self.gl_op(GLOp::Log, result_type, [args[0]]);
+                let ln = self.std_op(GLOp::Log, result_type, [args[0]]);
                 self.mul(mul, ln)
             }
             LibmIntrinsic::Custom(LibmCustomIntrinsic::Log1p) => {
                 assert_eq!(args.len(), 1);
                 let one = self.constant_float(args[0].ty, 1.0);
                 let add = self.add(args[0], one);
-                self.gl_op(GLOp::Log, result_type, [add])
+                self.std_op(GLOp::Log, result_type, [add])
             }
             LibmIntrinsic::Custom(LibmCustomIntrinsic::Exp10) => {
                 assert_eq!(args.len(), 1);
                 // exp10(x) == exp(x * log(10));
                 let log10 = self.constant_float(args[0].ty, 10.0f64.ln());
                 let mul = self.mul(args[0], log10);
-                self.gl_op(GLOp::Exp, result_type, [mul])
+                self.std_op(GLOp::Exp, result_type, [mul])
             }
             LibmIntrinsic::Custom(LibmCustomIntrinsic::Expm1) => {
-                let exp = self.gl_op(GLOp::Exp, args[0].ty, [args[0]]);
+                let exp = self.std_op(GLOp::Exp, args[0].ty, [args[0]]);
                 let one = self.constant_float(exp.ty, 1.0);
                 self.sub(exp, one)
             }
@@ -351,4 +411,34 @@ impl Builder<'_, '_> {
             }
         }
     }
+
+    /// OpenCL.std versions of libm functions returning a pair (e.g. `remquo`),
+    /// return only the first value, and write the second through a pointer.
+    fn cl_op_with_out_ptr(
+        &mut self,
+        op: CLOp,
+        result_type: Word,
+        args: &[SpirvValue],
+    ) -> SpirvValue {
+        let (value_ty, out_ty) = match self.lookup_type(result_type) {
+            SpirvType::Adt {
+                field_types: &[value_ty, out_ty],
+                ..
+            } => (value_ty, out_ty),
+            other => bug!(
+                "OpenCL.std `{:?}` must return a pair, not {}",
+                op,
+                other.debug(result_type, self)
+            ),
+        };
+        let out_align = self.lookup_type(out_ty).alignof(self);
+        let out_ptr = self.alloca(out_ty, out_align);
+        let args = args.iter().copied().chain([out_ptr]).collect::<Vec<_>>();
+        let value = self.cl_op(op, value_ty, args).def(self);
+        let out = self.load(out_ty, out_ptr, out_align).def(self);
+        self.emit()
+            .composite_construct(result_type, None, [value, out].iter().copied())
+            .unwrap()
+            .with_type(result_type)
+    }
 }
@@ -10,11 +10,11 @@ use rspirv::{
 use rustc_ast::ast::{InlineAsmOptions, InlineAsmTemplatePiece};
 use rustc_codegen_ssa::{
     mir::place::PlaceRef,
-    traits::{AsmBuilderMethods, InlineAsmOperandRef},
+    traits::{AsmBuilderMethods, BuilderMethods, GlobalAsmOperandRef, InlineAsmOperandRef},
 };
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_middle::{bug, ty::Instance};
-use rustc_span::{Span, DUMMY_SP};
+use rustc_span::{Span, Symbol, DUMMY_SP};
 use rustc_target::asm::{InlineAsmRegClass, InlineAsmRegOrRegClass, SpirVInlineAsmRegClass};
 
 use super::Builder;
@@ -72,65 +72,7 @@ impl<'a, 'tcx> AsmBuilderMethods<'tcx> for Builder<'a, 'tcx> {
         if !unsupported_options.is_empty() {
             self.err(format!("asm flags not supported: {unsupported_options:?}"));
         }
-        // vec of lines, and each line is vec of tokens
-        let mut tokens = vec![vec![]];
-        for piece in template {
-            match piece {
-                InlineAsmTemplatePiece::String(asm) => {
-                    // We cannot use str::lines() here because we don't want the behavior of "the
-                    // last newline is optional", we want an empty string for the last line if
-                    // there is no newline terminator.
-                    // Lambda copied from std LinesAnyMap
-                    let lines = asm.split('\n').map(|line| {
-                        let l = line.len();
-                        if l > 0 && line.as_bytes()[l - 1] == b'\r' {
-                            &line[0..l - 1]
-                        } else {
-                            line
-                        }
-                    });
-                    for (index, line) in lines.enumerate() {
-                        if index != 0 {
-                            // There was a newline, add a new line.
-                            tokens.push(vec![]);
-                        }
-                        let mut chars = line.chars();
-                        while let Some(token) = self.lex_word(&mut chars) {
-                            tokens.last_mut().unwrap().push(token);
-                        }
-                    }
-                }
-                &InlineAsmTemplatePiece::Placeholder {
-                    operand_idx,
-                    modifier,
-                    span,
-                } => {
-                    if let Some(modifier) = modifier {
-                        self.tcx
-                            .sess
-                            .psess
-                            .dcx
-                            .span_err(span, format!("asm modifiers are not supported: {modifier}"));
-                    }
-                    let line = tokens.last_mut().unwrap();
-                    let typeof_kind = line.last().and_then(|prev| match prev {
-                        Token::Word("typeof") => Some(TypeofKind::Plain),
-                        Token::Word("typeof*") => Some(TypeofKind::Dereference),
-                        _ => None,
-                    });
-                    match typeof_kind {
-                        Some(kind) => {
-                            *line.last_mut().unwrap() =
-                                Token::Typeof(&operands[operand_idx], span, kind);
-                        }
-                        None => match &operands[operand_idx] {
-                            InlineAsmOperandRef::Const { string } => line.push(Token::Word(string)),
-                            item => line.push(Token::Placeholder(item, span)),
-                        },
-                    }
-                }
-            }
-        }
+        let tokens = self.lex_template(template, operands);
 
         let mut id_map = FxHashMap::default();
         let mut defined_ids = FxHashSet::default();
@@ -183,6 +125,139 @@ impl<'a, 'tcx> AsmBuilderMethods<'tcx> for Builder<'a, 'tcx> {
     }
 }
 
+impl<'cx, 'tcx> Builder<'cx, 'tcx> {
+    /// `global_asm!` support, reusing the `asm!` parser (which needs a `Builder`),
+    /// by emitting into a temporary function, and then moving the instructions
+    /// into the module-level sections they belong to (function-level instructions
+    /// being reported as errors, as they have nowhere to go).
+    pub fn codegen_global_asm(
+        cx: &'cx CodegenCx<'tcx>,
+        template: &[InlineAsmTemplatePiece],
+        operands: &[GlobalAsmOperandRef<'tcx>],
+        options: InlineAsmOptions,
+        line_spans: &[Span],
+    ) {
+        let void = SpirvType::Void.def(DUMMY_SP, cx);
+        let fn_ty = SpirvType::Function {
+            return_type: void,
+            arguments: &[],
+        }
+        .def(DUMMY_SP, cx);
+        let fn_id = {
+            let mut emit = cx.emit_global();
+            let fn_id = emit
+                .begin_function(void, None, FunctionControl::NONE, fn_ty)
+                .unwrap();
+            emit.end_function().unwrap();
+            fn_id
+        };
+        let llbb = cx
+            .emit_with_cursor(cx.builder.select_function_by_id(fn_id))
+            .begin_block(None)
+            .unwrap();
+        let mut bx = Self::build(cx, llbb);
+        bx.current_span = line_spans.iter().copied().reduce(Span::to);
+
+        const SUPPORTED_OPTIONS: InlineAsmOptions = InlineAsmOptions::RAW;
+        let unsupported_options = options & !SUPPORTED_OPTIONS;
+        if !unsupported_options.is_empty() {
+            bx.err(format!(
+                "global_asm flags not supported: {unsupported_options:?}"
+            ));
+        }
+
+        let operands = operands
+            .iter()
+            .map(|operand| match *operand {
+                GlobalAsmOperandRef::Const { ref string } => InlineAsmOperandRef::Const {
+                    string: string.clone(),
+                },
+                GlobalAsmOperandRef::SymFn { instance } => InlineAsmOperandRef::SymFn { instance },
+                GlobalAsmOperandRef::SymStatic { def_id } => {
+                    InlineAsmOperandRef::SymStatic { def_id }
+                }
+            })
+            .collect::<Vec<_>>();
+        let tokens = bx.lex_template(template, &operands);
+
+        let mut id_map = FxHashMap::default();
+        let mut defined_ids = FxHashSet::default();
+        let mut id_to_type_map = FxHashMap::default();
+        let mut asm_block = AsmBlock::Open;
+        for line in tokens {
+            bx.codegen_asm(
+                &mut id_map,
+                &mut defined_ids,
+                &mut id_to_type_map,
+                &mut asm_block,
+                line.into_iter(),
+            );
+        }
+        for (id, num) in id_map {
+            if !defined_ids.contains(&num) {
+                bx.err(format!("%{id} is used but not defined"));
+            }
+        }
+
+        // Types and non-`Function` `OpVariable`s were already emitted globally
+        // (see `insert_inst`), everything else is in the temporary function.
+        let mut disallowed_ops = vec![];
+        let mut caps_and_exts = vec![];
+        {
+            let mut emit = cx.emit_global();
+            let module = emit.module_mut();
+            let fn_idx = module
+                .functions
+                .iter()
+                .position(|func| func.def_id() == Some(fn_id))
+                .unwrap();
+            let func = module.functions.remove(fn_idx);
+            for inst in func.blocks.into_iter().flat_map(|block| block.instructions) {
+                let section = match inst.class.opcode {
+                    // Added below, to keep `BuilderSpirv`'s own tracking of
+                    // enabled capabilities and extensions in sync.
+                    Op::Capability | Op::Extension => {
+                        caps_and_exts.push(inst);
+                        continue;
+                    }
+                    Op::ExtInstImport => &mut module.ext_inst_imports,
+                    Op::EntryPoint => &mut module.entry_points,
+                    Op::ExecutionMode | Op::ExecutionModeId => &mut module.execution_modes,
+                    Op::String | Op::Source | Op::SourceExtension | Op::SourceContinued => {
+                        &mut module.debug_string_source
+                    }
+                    Op::Name | Op::MemberName => &mut module.debug_names,
+                    Op::ModuleProcessed => &mut module.debug_module_processed,
+                    op if reflect::is_annotation(op) || op == Op::DecorateId => {
+                        &mut module.annotations
+                    }
+                    op if reflect::is_constant(op) || op == Op::Undef => {
+                        &mut module.types_global_values
+                    }
+                    op => {
+                        disallowed_ops.push(op);
+                        continue;
+                    }
+                };
+                section.push(inst);
+            }
+        }
+        for inst in caps_and_exts {
+            match inst.class.opcode {
+                Op::Capability => cx.builder.add_cap(inst.operands[0].unwrap_capability()),
+                _ => cx
+                    .builder
+                    .add_ext(Symbol::intern(inst.operands[0].unwrap_literal_string())),
+            }
+        }
+        for op in disallowed_ops {
+            let mut err = bx.struct_err(format!("`Op{op:?}` is not allowed in `global_asm!`"));
+            err.note("only module-level instructions can be used outside of functions");
+            err.emit();
+        }
+    }
+}
+
 enum TypeofKind {
     Plain,
     Dereference,
@@ -210,6 +285,75 @@ enum AsmBlock {
 }
 
 impl<'cx, 'tcx> Builder<'cx, 'tcx> {
+    /// Splits `template` into lines of tokens, with placeholders referring to `operands`
+    /// (or, for `const` operands, replaced with their value).
+    fn lex_template<'a>(
+        &self,
+        template: &[InlineAsmTemplatePiece],
+        operands: &'a [InlineAsmOperandRef<'tcx, Builder<'cx, 'tcx>>],
+    ) -> Vec<Vec<Token<'a, 'cx, 'tcx>>> {
+        // vec of lines, and each line is vec of tokens
+        let mut tokens = vec![vec![]];
+        for piece in template {
+            match piece {
+                InlineAsmTemplatePiece::String(asm) => {
+                    // We cannot use str::lines() here because we don't want the behavior of "the
+                    // last newline is optional", we want an empty string for the last line if
+                    // there is no newline terminator.
+                    // Lambda copied from std LinesAnyMap
+                    let lines = asm.split('\n').map(|line| {
+                        let l = line.len();
+                        if l > 0 && line.as_bytes()[l - 1] == b'\r' {
+                            &line[0..l - 1]
+                        } else {
+                            line
+                        }
+                    });
+                    for (index, line) in lines.enumerate() {
+                        if index != 0 {
+                            // There was a newline, add a new line.
+                            tokens.push(vec![]);
+                        }
+                        let mut chars = line.chars();
+                        while let Some(token) = self.lex_word(&mut chars) {
+                            tokens.last_mut().unwrap().push(token);
+                        }
+                    }
+                }
+                &InlineAsmTemplatePiece::Placeholder {
+                    operand_idx,
+                    modifier,
+                    span,
+                } => {
+                    if let Some(modifier) = modifier {
+                        self.tcx
+                            .sess
+                            .psess
+                            .dcx
+                            .span_err(span, format!("asm modifiers are not supported: {modifier}"));
+                    }
+                    let line = tokens.last_mut().unwrap();
+                    let typeof_kind = line.last().and_then(|prev| match prev {
+                        Token::Word("typeof") => Some(TypeofKind::Plain),
+                        Token::Word("typeof*") => Some(TypeofKind::Dereference),
+                        _ => None,
+                    });
+                    match typeof_kind {
+                        Some(kind) => {
+                            *line.last_mut().unwrap() =
+                                Token::Typeof(&operands[operand_idx], span, kind);
+                        }
+                        None => match &operands[operand_idx] {
+                            InlineAsmOperandRef::Const { string } => line.push(Token::Word(string)),
+                            item => line.push(Token::Placeholder(item, span)),
+                        },
+                    }
+                }
+            }
+        }
+        tokens
+    }
+
     fn lex_word<'a>(&self, line: &mut std::str::Chars<'a>) -> Option<Token<'a, 'cx, 'tcx>> {
         loop {
             let start = line.as_str();
@@ -1065,21 +1209,11 @@ impl<'cx, 'tcx> Builder<'cx, 'tcx> {
                         .span_err(span, "const asm argument not supported yet");
                     None
                 }
-                InlineAsmOperandRef::SymFn { instance: _ } => {
-                    self.tcx
-                        .sess
-                        .psess
-                        .dcx
-                        .span_err(span, "function asm argument not supported yet");
-                    None
+                InlineAsmOperandRef::SymFn { instance } => {
+                    Some(self.cx.get_fn_ext(*instance).def(self))
                 }
-                InlineAsmOperandRef::SymStatic { def_id: _ } => {
-                    self.tcx
-                        .sess
-                        .psess
-                        .dcx
-                        .span_err(span, "static variable asm argument not supported yet");
-                    None
+                InlineAsmOperandRef::SymStatic { def_id } => {
+                    Some(self.cx.get_static(*def_id).def(self))
                 }
             },
         }
@@ -176,15 +176,15 @@ impl SpirvValue {
                 id
             }
 
-            SpirvValueKind::FnAddr { .. } => {
+            SpirvValueKind::FnAddr { function } => {
                 cx.builder
                     .const_to_id
                     .borrow()
                     .get(&WithType {
                         ty: self.ty,
-                        val: SpirvConst::ZombieUndefForFnAddr,
+                        val: SpirvConst::FnAddr { function },
                     })
-                    .expect("FnAddr didn't go through proper undef registration")
+                    .expect("FnAddr didn't go through proper constant registration")
                     .val
             }
 
@@ -226,6 +226,8 @@ impl SpirvValueExt for Word {
 pub enum SpirvConst<'a, 'tcx> {
     U32(u32),
     U64(u64),
+    /// f16 isn't stable (nor hash), so store bits
+    F16(u16),
     /// f32 isn't hash, so store bits
     F32(u32),
     /// f64 isn't hash, so store bits
@@ -235,11 +237,12 @@ pub enum SpirvConst<'a, 'tcx> {
     Null,
     Undef,
 
-    /// Like `Undef`, but cached separately to avoid `FnAddr` zombies accidentally
-    /// applying to non-zombie `Undef`s of the same types.
-    // FIXME(eddyb) include the function ID so that multiple `fn` pointers to
-    // different functions, but of the same type, don't overlap their zombies.
-    ZombieUndefForFnAddr,
+    /// Pointer to `function`, represented as an `OpVariable` in the
+    /// `CodeSectionINTEL` storage class, and with `function` as its initializer
+    /// (the linker replaces these with integer IDs, see `linker::devirtualize`).
+    FnAddr {
+        function: Word,
+    },
 
     Composite(&'a [Word]),
 
@@ -277,12 +280,13 @@ impl<'tcx> SpirvConst<'_, 'tcx> {
             // FIXME(eddyb) these are all noop cases, could they be automated?
             SpirvConst::U32(v) => SpirvConst::U32(v),
             SpirvConst::U64(v) => SpirvConst::U64(v),
+            SpirvConst::F16(v) => SpirvConst::F16(v),
             SpirvConst::F32(v) => SpirvConst::F32(v),
             SpirvConst::F64(v) => SpirvConst::F64(v),
             SpirvConst::Bool(v) => SpirvConst::Bool(v),
             SpirvConst::Null => SpirvConst::Null,
             SpirvConst::Undef => SpirvConst::Undef,
-            SpirvConst::ZombieUndefForFnAddr => SpirvConst::ZombieUndefForFnAddr,
+            SpirvConst::FnAddr { function } => SpirvConst::FnAddr { function },
             SpirvConst::PtrTo { pointee } => SpirvConst::PtrTo { pointee },
 
             SpirvConst::Composite(fields) => SpirvConst::Composite(arena_alloc_slice(cx, fields)),
@@ -428,8 +432,13 @@ pub struct BuilderSpirv<'tcx> {
 
     debug_file_cache: RefCell<FxHashMap<DebugFileKey, DebugFileSpirv<'tcx>>>,
 
-    enabled_capabilities: FxHashSet<Capability>,
-    enabled_extensions: FxHashSet<Symbol>,
+    enabled_capabilities: RefCell<FxHashSet<Capability>>,
+    enabled_extensions: RefCell<FxHashSet<Symbol>>,
+
+    /// Capabilities and extensions that may be used without being declared,
+    /// as the linker will declare them, if needed (see `linker::capability_computation`).
+    inferable_capabilities: FxHashSet<Capability>,
+    inferable_extensions: FxHashSet<Symbol>,
 }
 
 impl<'tcx> BuilderSpirv<'tcx> {
@@ -438,76 +447,82 @@ impl<'tcx> BuilderSpirv<'tcx> {
         sym: &Symbols,
         target: &SpirvTarget,
         features: &[TargetFeature],
+        inferable: &[TargetFeature],
     ) -> Self {
         let version = target.spirv_version();
         let memory_model = target.memory_model();
+        let addressing_model = target.addressing_model();
 
         let mut builder = Builder::new();
         builder.set_version(version.0, version.1);
         builder.module_mut().header.as_mut().unwrap().generator = 0x001B_0000;
 
-        let mut enabled_capabilities = FxHashSet::default();
-        let mut enabled_extensions = FxHashSet::default();
-
-        fn add_cap(
-            builder: &mut Builder,
-            enabled_capabilities: &mut FxHashSet<Capability>,
-            cap: Capability,
-        ) {
-            // This should be the only callsite of Builder::capability (aside from tests), to make
-            // sure the hashset stays in sync.
-            builder.capability(cap);
-            enabled_capabilities.insert(cap);
-        }
-        fn add_ext(builder: &mut Builder, enabled_extensions: &mut FxHashSet<Symbol>, ext: Symbol) {
-            // This should be the only callsite of Builder::extension (aside from tests), to make
-            // sure the hashset stays in sync.
-            builder.extension(ext.as_str());
-            enabled_extensions.insert(ext);
-        }
+        let result = Self {
+            source_map: tcx.sess.source_map(),
+            dropless_arena: &tcx.arena.dropless,
+            builder: RefCell::new(builder),
+            const_to_id: Default::default(),
+            id_to_const: Default::default(),
+            debug_file_cache: Default::default(),
+            enabled_capabilities: Default::default(),
+            enabled_extensions: Default::default(),
+            inferable_capabilities: inferable
+                .iter()
+                .filter_map(|feature| match *feature {
+                    TargetFeature::Capability(cap) => Some(cap),
+                    TargetFeature::Extension(_) | TargetFeature::Profile(_) => None,
+                })
+                .collect(),
+            inferable_extensions: inferable
+                .iter()
+                .filter_map(|feature| match *feature {
+                    TargetFeature::Extension(ext) => Some(ext),
+                    TargetFeature::Capability(_) | TargetFeature::Profile(_) => None,
+                })
+                .collect(),
+        };
 
         for feature in features {
             match *feature {
-                TargetFeature::Capability(cap) => {
-                    add_cap(&mut builder, &mut enabled_capabilities, cap);
-                }
-                TargetFeature::Extension(ext) => {
-                    add_ext(&mut builder, &mut enabled_extensions, ext);
-                }
+                TargetFeature::Capability(cap) => result.add_cap(cap),
+                TargetFeature::Extension(ext) => result.add_ext(ext),
+                // Already expanded into the features it contains.
+                TargetFeature::Profile(_) => {}
             }
         }
 
-        add_cap(&mut builder, &mut enabled_capabilities, Capability::Shader);
+        if target.is_kernel() {
+            result.add_cap(Capability::Kernel);
+            result.add_cap(Capability::Addresses);
+        } else {
+            result.add_cap(Capability::Shader);
+        }
+        if addressing_model == AddressingModel::PhysicalStorageBuffer64 {
+            if version < (1, 5) {
+                result.add_ext(sym.spv_khr_physical_storage_buffer);
+            }
+            result.add_cap(Capability::PhysicalStorageBufferAddresses);
+        }
+        if target.pointer_width() == 64 {
+            // `usize` is 64-bit, so 64-bit integers are unavoidable.
+            result.add_cap(Capability::Int64);
+        }
         if memory_model == MemoryModel::Vulkan {
             if version < (1, 5) {
-                add_ext(
-                    &mut builder,
-                    &mut enabled_extensions,
-                    sym.spv_khr_vulkan_memory_model,
-                );
+                result.add_ext(sym.spv_khr_vulkan_memory_model);
             }
-            add_cap(
-                &mut builder,
-                &mut enabled_capabilities,
-                Capability::VulkanMemoryModel,
-            );
+            result.add_cap(Capability::VulkanMemoryModel);
         }
 
         // The linker will always be ran on this module
-        add_cap(&mut builder, &mut enabled_capabilities, Capability::Linkage);
+        result.add_cap(Capability::Linkage);
 
-        builder.memory_model(AddressingModel::Logical, memory_model);
+        result
+            .builder
+            .borrow_mut()
+            .memory_model(addressing_model, memory_model);
 
-        Self {
-            source_map: tcx.sess.source_map(),
-            dropless_arena: &tcx.arena.dropless,
-            builder: RefCell::new(builder),
-            const_to_id: Default::default(),
-            id_to_const: Default::default(),
-            debug_file_cache: Default::default(),
-            enabled_capabilities,
-            enabled_extensions,
-        }
+        result
     }
 
     pub fn finalize(self) -> Module {
@@ -518,9 +533,14 @@ impl<'tcx> BuilderSpirv<'tcx> {
         self.builder.borrow().module_ref().disassemble()
     }
 
+    /// The module built so far, assembled into SPIR-V words.
+    pub fn dump_module_words(&self) -> Vec<u32> {
+        self.builder.borrow().module_ref().assemble()
+    }
+
     /// Helper function useful to place right before a crash, to debug the module state.
     pub fn dump_module(&self, path: impl AsRef<Path>) {
-        let module = self.builder.borrow().module_ref().assemble();
+        let module = self.dump_module_words();
         File::create(path)
             .unwrap()
             .write_all(spirv_tools::binary::from_binary(&module))
@@ -542,11 +562,29 @@ impl<'tcx> BuilderSpirv<'tcx> {
     }
 
     pub fn has_capability(&self, capability: Capability) -> bool {
-        self.enabled_capabilities.contains(&capability)
+        self.enabled_capabilities.borrow().contains(&capability)
+            || self.inferable_capabilities.contains(&capability)
     }
 
     pub fn has_extension(&self, extension: Symbol) -> bool {
-        self.enabled_extensions.contains(&extension)
+        self.enabled_extensions.borrow().contains(&extension)
+            || self.inferable_extensions.contains(&extension)
+    }
+
+    pub fn add_cap(&self, cap: Capability) {
+        // This should be the only callsite of Builder::capability (aside from tests), to make
+        // sure the hashset stays in sync.
+        if self.enabled_capabilities.borrow_mut().insert(cap) {
+            self.builder.borrow_mut().capability(cap);
+        }
+    }
+
+    pub fn add_ext(&self, ext: Symbol) {
+        // This should be the only callsite of Builder::extension (aside from tests), to make
+        // sure the hashset stays in sync.
+        if self.enabled_extensions.borrow_mut().insert(ext) {
+            self.builder.borrow_mut().extension(ext.as_str());
+        }
     }
 
     pub fn select_function_by_id(&self, id: Word) -> BuilderCursor {
@@ -585,6 +623,8 @@ impl<'tcx> BuilderSpirv<'tcx> {
         let id = match val {
             SpirvConst::U32(v) => builder.constant_u32(ty, v),
             SpirvConst::U64(v) => builder.constant_u64(ty, v),
+            // NOTE the literal of a 16-bit float is its (zero-extended) bits.
+            SpirvConst::F16(v) => builder.constant_u32(ty, v as u32),
             SpirvConst::F32(v) => builder.constant_f32(ty, f32::from_bits(v)),
             SpirvConst::F64(v) => builder.constant_f64(ty, f64::from_bits(v)),
             SpirvConst::Bool(v) => {
@@ -596,20 +636,23 @@ impl<'tcx> BuilderSpirv<'tcx> {
             }
 
             SpirvConst::Null => builder.constant_null(ty),
-            SpirvConst::Undef
-            | SpirvConst::ZombieUndefForFnAddr
-            | SpirvConst::ConstDataFromAlloc(_) => builder.undef(ty, None),
+            SpirvConst::Undef | SpirvConst::ConstDataFromAlloc(_) => builder.undef(ty, None),
 
             SpirvConst::Composite(v) => builder.constant_composite(ty, v.iter().copied()),
 
             SpirvConst::PtrTo { pointee } => {
                 builder.variable(ty, None, StorageClass::Private, Some(pointee))
             }
+
+            SpirvConst::FnAddr { function } => {
+                builder.variable(ty, None, StorageClass::CodeSectionINTEL, Some(function))
+            }
         };
         #[allow(clippy::match_same_arms)]
         let legal = match val {
             SpirvConst::U32(_)
             | SpirvConst::U64(_)
+            | SpirvConst::F16(_)
             | SpirvConst::F32(_)
             | SpirvConst::F64(_)
             | SpirvConst::Bool(_) => Ok(()),
@@ -623,11 +666,9 @@ impl<'tcx> BuilderSpirv<'tcx> {
                 Ok(())
             }
 
-            SpirvConst::ZombieUndefForFnAddr => {
-                // This can be considered legal as it's already marked as zombie.
-                // FIXME(eddyb) is it possible for the original zombie to lack a
-                // span, and should we go through `IllegalConst` in order to be
-                // able to attach a proper usesite span?
+            SpirvConst::FnAddr { .. } => {
+                // Unlike `PtrTo`, this is allowed in `OpConstantComposite`s (e.g.
+                // vtables), as the linker turns it into an integer constant.
                 Ok(())
             }
 
@@ -343,8 +343,13 @@ impl<'tcx> ConstMethods<'tcx> for CodegenCx<'tcx> {
                         let value = self.static_addr_of(init, alloc.inner().align, None);
                         (value, AddressSpace::DATA)
                     }
+                    // NOTE function pointers in memory may not be typed as such
+                    // (e.g. the `*const ()` method entries of vtables).
                     GlobalAlloc::Function(fn_instance) => (
-                        self.get_fn_addr(fn_instance.polymorphize(self.tcx)),
+                        self.const_bitcast(
+                            self.get_fn_addr(fn_instance.polymorphize(self.tcx)),
+                            ty,
+                        ),
                         self.data_layout().instruction_address_space,
                     ),
                     GlobalAlloc::VTable(vty, trait_ref) => {
@@ -420,6 +425,18 @@ impl<'tcx> ConstMethods<'tcx> for CodegenCx<'tcx> {
             }
         }
 
+        // Function pointers are opaque until the linker replaces them with
+        // integers (see `linker::devirtualize`), so any pointer type works.
+        if let SpirvValueKind::FnAddr { function } = val.kind {
+            if let SpirvType::Pointer { .. } = self.lookup_type(ty) {
+                self.def_constant(ty, SpirvConst::FnAddr { function });
+                return SpirvValue {
+                    kind: SpirvValueKind::FnAddr { function },
+                    ty,
+                };
+            }
+        }
+
         if val.ty == ty {
             val
         } else {
@@ -1,5 +1,5 @@
 use itertools::Itertools;
-use rspirv::spirv::{FunctionControl, LinkageType, StorageClass, Word};
+use rspirv::spirv::{FunctionControl, LinkageType, MemoryModel, Scope, StorageClass, Word};
 use rustc_attr::InlineAttr;
 use rustc_codegen_ssa::traits::{PreDefineMethods, StaticMethods};
 use rustc_hir::def::DefKind;
@@ -13,7 +13,7 @@ use rustc_middle::{
         Instance, ParamEnv, TypeVisitableExt,
     },
 };
-use rustc_span::{def_id::DefId, Span};
+use rustc_span::{def_id::DefId, sym, Span};
 use rustc_target::abi::Align;
 
 use super::CodegenCx;
@@ -42,7 +42,98 @@ fn attrs_to_spirv(attrs: &CodegenFnAttrs) -> FunctionControl {
     control
 }
 
+/// Whether `path` matches `pattern`, where `*` matches any (possibly empty)
+/// sequence of characters within one path segment (i.e. not containing `::`),
+/// `**` matches any sequence of characters, and everything else has to match exactly.
+fn path_matches_pattern(path: &str, pattern: &str) -> bool {
+    let (literal, pattern) = pattern.split_at(pattern.find('*').unwrap_or(pattern.len()));
+    let Some(path) = path.strip_prefix(literal) else {
+        return false;
+    };
+    let (pattern, max_skip) = if let Some(pattern) = pattern.strip_prefix("**") {
+        (pattern, path.len())
+    } else if let Some(pattern) = pattern.strip_prefix('*') {
+        (pattern, path.find("::").unwrap_or(path.len()))
+    } else {
+        return path.is_empty();
+    };
+    (0..=max_skip)
+        .filter(|&i| path.is_char_boundary(i))
+        .any(|i| path_matches_pattern(&path[i..], pattern))
+}
+
 impl<'tcx> CodegenCx<'tcx> {
+    /// Whether `instance` can't be compiled for GPUs, and should therefore be
+    /// skipped when defining functions, with any uses turned into zombies (so
+    /// that they only cause errors if actually reachable from an entry-point).
+    ///
+    /// This is the case for functions marked `#[spirv(cpu_only)]`, or matching
+    /// one of the `--cpu-only-fn` patterns, and also for `<_ as Debug>::fmt`.
+    pub fn is_cpu_only_fn(&self, instance: Instance<'tcx>) -> bool {
+        let ty::InstanceDef::Item(def_id) = instance.def else {
+            return false;
+        };
+
+        if let Some(&cpu_only) = self.cpu_only_fns.borrow().get(&def_id) {
+            return cpu_only;
+        }
+        let cpu_only = self.is_cpu_only_def(def_id);
+        self.cpu_only_fns.borrow_mut().insert(def_id, cpu_only);
+        cpu_only
+    }
+
+    fn is_cpu_only_def(&self, def_id: DefId) -> bool {
+        // TODO: These sometimes have a constant value of an enum variant with a hole
+        if let Some(debug_trait_def_id) = self.tcx.get_diagnostic_item(sym::Debug) {
+            // Helper for detecting `<_ as core::fmt::Debug>::fmt` (in impls).
+            let is_debug_fmt_method = |def_id| match self.tcx.opt_associated_item(def_id) {
+                Some(assoc) if assoc.ident(self.tcx).name == sym::fmt => match assoc.container {
+                    ty::ImplContainer => {
+                        let impl_def_id = assoc.container_id(self.tcx);
+                        self.tcx
+                            .impl_trait_ref(impl_def_id)
+                            .map(|tr| tr.skip_binder().def_id)
+                            == Some(debug_trait_def_id)
+                    }
+                    ty::TraitContainer => false,
+                },
+                _ => false,
+            };
+
+            if is_debug_fmt_method(def_id) {
+                return true;
+            }
+
+            if self.tcx.opt_item_ident(def_id).map(|i| i.name) == Some(self.sym.fmt_decimal) {
+                if let Some(parent_def_id) = self.tcx.opt_parent(def_id) {
+                    if is_debug_fmt_method(parent_def_id) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        // See the comment on the similar workaround in `declare_fn_ext`.
+        if self.tcx.def_kind(def_id) != DefKind::Closure {
+            let attrs =
+                AggregatedSpirvAttributes::parse(self, self.tcx.get_attrs_unchecked(def_id));
+            if attrs.cpu_only.is_some() {
+                return true;
+            }
+        }
+
+        if !self.codegen_args.cpu_only_fns.is_empty() {
+            let path = self.tcx.def_path_str(def_id);
+            return self
+                .codegen_args
+                .cpu_only_fns
+                .iter()
+                .any(|pattern| path_matches_pattern(&path, pattern));
+        }
+
+        false
+    }
+
     /// Returns a function if it already exists, or declares a header if it doesn't.
     pub fn get_fn_ext(&self, instance: Instance<'tcx>) -> SpirvValue {
         assert!(!instance.args.has_infer());
@@ -79,10 +170,15 @@ impl<'tcx> CodegenCx<'tcx> {
             other => bug!("fn_abi type {}", other.debug(function_type, self)),
         };
 
-        if crate::is_blocklisted_fn(self.tcx, &self.sym, instance) {
-            // This can happen if we call a blocklisted function in another crate.
+        if self.is_cpu_only_fn(instance) {
+            // CPU-only functions are never defined (see `is_cpu_only_fn`), so
+            // any uses are replaced with a zombie, only erroring if reachable.
             let result = self.undef(function_type);
-            self.zombie_with_span(result.def_cx(self), span, "called blocklisted fn");
+            self.zombie_with_span(
+                result.def_cx(self),
+                span,
+                &format!("`{instance}` cannot be used from GPU code, as this function is CPU-only"),
+            );
             return result;
         }
         let fn_id = {
@@ -163,6 +259,24 @@ impl<'tcx> CodegenCx<'tcx> {
                 .borrow_mut()
                 .insert(fn_id, mode);
         }
+        if let Some(op) = attrs.atomic_float_intrinsic.map(|attr| attr.value) {
+            self.atomic_float_intrinsic_fn_id
+                .borrow_mut()
+                .insert(fn_id, op);
+        }
+        if let Some(attr) = attrs.atomic_scope {
+            let scope = attr.value;
+            if self.target.memory_model() == MemoryModel::Vulkan
+                && matches!(scope, Scope::CrossDevice | Scope::Invocation)
+            {
+                self.tcx.sess.psess.dcx.span_err(
+                    attr.span,
+                    format!("`{scope:?}` scope cannot be used for atomics on Vulkan"),
+                );
+            } else {
+                self.atomic_scope_fn_id.borrow_mut().insert(fn_id, scope);
+            }
+        }
 
         let instance_def_id = instance.def_id();
 
@@ -376,3 +490,52 @@ impl<'tcx> StaticMethods for CodegenCx<'tcx> {
         // TODO: Ignore for now.
     }
 }
+
+#[cfg(test)]
+mod cpu_only_fn_patterns {
+    use super::path_matches_pattern;
+
+    #[test]
+    fn exact() {
+        assert!(path_matches_pattern("core::fmt::write", "core::fmt::write"));
+        assert!(!path_matches_pattern(
+            "core::fmt::write_str",
+            "core::fmt::write"
+        ));
+        assert!(!path_matches_pattern("core::fmt", "core::fmt::write"));
+    }
+
+    #[test]
+    fn single_segment_wildcard() {
+        assert!(path_matches_pattern("core::fmt::write", "core::fmt::*"));
+        assert!(path_matches_pattern("core::fmt::write", "core::*::write"));
+        assert!(path_matches_pattern("core::fmt::write", "core::fmt::wr*e"));
+        assert!(path_matches_pattern("core::fmt::", "core::fmt::*"));
+        assert!(!path_matches_pattern(
+            "core::fmt::rt::Argument::new",
+            "core::fmt::*"
+        ));
+        assert!(!path_matches_pattern("alloc::fmt::format", "core::*"));
+    }
+
+    #[test]
+    fn multi_segment_wildcard() {
+        assert!(path_matches_pattern(
+            "core::fmt::rt::Argument::new",
+            "core::fmt::**"
+        ));
+        assert!(path_matches_pattern("core::fmt::write", "**::write"));
+        assert!(path_matches_pattern(
+            "<my_crate::Foo as core::fmt::Debug>::fmt",
+            "**Debug>::fmt"
+        ));
+        assert!(path_matches_pattern(
+            "core::fmt::rt::Argument::new",
+            "core::**::new"
+        ));
+        assert!(!path_matches_pattern(
+            "core::fmt::rt::Argument::new",
+            "alloc::**"
+        ));
+    }
+}
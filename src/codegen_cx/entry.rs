@@ -2,7 +2,10 @@ use std::assert_matches::assert_matches;
 
 use rspirv::{
     dr::Operand,
-    spirv::{Capability, Decoration, Dim, ExecutionModel, FunctionControl, StorageClass, Word},
+    spirv::{
+        BuiltIn, Capability, Decoration, Dim, ExecutionMode, ExecutionModel, FunctionControl,
+        StorageClass, Word,
+    },
 };
 use rustc_codegen_ssa::traits::{BaseTypeMethods, BuilderMethods};
 use rustc_data_structures::fx::FxHashMap;
@@ -61,6 +64,20 @@ struct EntryParamDeducedFromRustRefOrValue<'tcx> {
     read_only: bool,
 }
 
+/// Builtins which SPIR-V only allows with the `Kernel` capability.
+fn is_kernel_only_builtin(builtin: BuiltIn) -> bool {
+    matches!(
+        builtin,
+        BuiltIn::WorkDim
+            | BuiltIn::GlobalSize
+            | BuiltIn::EnqueuedWorkgroupSize
+            | BuiltIn::GlobalOffset
+            | BuiltIn::GlobalLinearId
+            | BuiltIn::SubgroupMaxSize
+            | BuiltIn::NumEnqueuedSubgroups
+    )
+}
+
 impl<'tcx> CodegenCx<'tcx> {
     // Entry points declare their "interface" (all uniforms, inputs, outputs, etc.) as parameters.
     // spir-v uses globals to declare the interface. So, we need to generate a lil stub for the
@@ -150,6 +167,9 @@ impl<'tcx> CodegenCx<'tcx> {
             name,
             entry.execution_model,
         );
+        for &(execution_mode, _) in &entry.execution_modes {
+            self.check_intel_execution_mode(span, entry.execution_model, execution_mode);
+        }
         let mut emit = self.emit_global();
         entry
             .execution_modes
@@ -159,6 +179,46 @@ impl<'tcx> CodegenCx<'tcx> {
             });
     }
 
+    /// Validates the `SPV_INTEL_kernel_attributes` execution modes, which are only
+    /// allowed on kernels, and only when the extension was enabled with e.g.
+    /// `-Ctarget-feature=+ext:SPV_INTEL_kernel_attributes`, adding the capability
+    /// each of them needs (`KernelAttributesINTEL` or `FPGAKernelAttributesINTEL`).
+    fn check_intel_execution_mode(
+        &self,
+        span: Span,
+        execution_model: ExecutionModel,
+        execution_mode: ExecutionMode,
+    ) {
+        let required_cap = match execution_mode {
+            ExecutionMode::MaxWorkgroupSizeINTEL
+            | ExecutionMode::MaxWorkDimINTEL
+            | ExecutionMode::NoGlobalOffsetINTEL => Capability::KernelAttributesINTEL,
+            ExecutionMode::NumSIMDWorkitemsINTEL => Capability::FPGAKernelAttributesINTEL,
+            _ => return,
+        };
+        let dcx = &self.tcx.sess.psess.dcx;
+        if execution_model != ExecutionModel::Kernel {
+            dcx.span_err(
+                span,
+                format!("`{execution_mode:?}` is only valid on `#[spirv(kernel)]` entry points"),
+            );
+        }
+        if !self
+            .builder
+            .has_extension(self.sym.spv_intel_kernel_attributes)
+        {
+            dcx.span_err(
+                span,
+                format!(
+                    "`{execution_mode:?}` requires the `SPV_INTEL_kernel_attributes` extension \
+                     (`-Ctarget-feature=+ext:SPV_INTEL_kernel_attributes`)"
+                ),
+            );
+        } else {
+            self.builder.add_cap(required_cap);
+        }
+    }
+
     fn shader_entry_stub(
         &self,
         span: Span,
@@ -666,6 +726,16 @@ impl<'tcx> CodegenCx<'tcx> {
                     ),
                 );
             }
+            if is_kernel_only_builtin(builtin.value) && !self.target.is_kernel() {
+                self.tcx.sess.psess.dcx.span_err(
+                    builtin.span,
+                    format!(
+                        "`{:?}` builtin is only available in `Kernel` modules (i.e. when \
+                         targeting OpenCL), not `{}`",
+                        builtin.value, self.target
+                    ),
+                );
+            }
             self.emit_global().decorate(
                 var_id.unwrap(),
                 Decoration::BuiltIn,
@@ -4,7 +4,7 @@ mod entry;
 mod type_;
 
 use std::{
-    cell::RefCell,
+    cell::{OnceCell, RefCell},
     collections::BTreeSet,
     iter::once,
     path::{Path, PathBuf},
@@ -14,7 +14,7 @@ use std::{
 
 use rspirv::{
     dr::{Module, Operand},
-    spirv::{Decoration, LinkageType, Op, Word},
+    spirv::{Capability, Decoration, LinkageType, Op, Scope, Word},
 };
 use rustc_ast::ast::{InlineAsmOptions, InlineAsmTemplatePiece};
 use rustc_codegen_ssa::{
@@ -31,7 +31,7 @@ use rustc_middle::{
     },
 };
 use rustc_session::Session;
-use rustc_span::{symbol::Symbol, SourceFile, Span, DUMMY_SP};
+use rustc_span::{def_id::DefId, symbol::Symbol, SourceFile, Span, DUMMY_SP};
 use rustc_target::{
     abi::{
         call::{FnAbi, PassMode},
@@ -41,12 +41,17 @@ use rustc_target::{
 };
 
 use crate::{
-    builder::{ExtInst, InstructionTable},
+    attr::AtomicFloatOp,
+    builder::{Builder, ExtInst, InstructionTable},
     builder_spirv::{BuilderCursor, BuilderSpirv, SpirvConst, SpirvValue, SpirvValueKind},
-    custom_decorations::{CustomDecoration, SrcLocDecoration, ZombieDecoration},
+    custom_decorations::{
+        AtomicScopeDecoration, CustomDecoration, DefaultAtomicScopeDecoration, SrcLocDecoration,
+        ZombieDecoration,
+    },
     spirv_type::{SpirvType, SpirvTypePrinter, TypeCache},
     symbols::Symbols,
     target::SpirvTarget,
+    target_feature::{expand_target_features, session_target_features, TargetFeature},
 };
 
 pub struct CodegenCx<'tcx> {
@@ -56,6 +61,9 @@ pub struct CodegenCx<'tcx> {
     pub builder: BuilderSpirv<'tcx>,
     /// Map from MIR function to spir-v function ID
     pub instances: RefCell<FxHashMap<Instance<'tcx>, SpirvValue>>,
+    /// Cache of `is_cpu_only_fn` results (which have to parse attributes and
+    /// possibly build paths), as it's checked for every declaration.
+    pub cpu_only_fns: RefCell<FxHashMap<DefId, bool>>,
     /// Map from function ID to parameter list
     pub function_parameter_values: RefCell<FxHashMap<Word, Vec<SpirvValue>>>,
     pub type_cache: TypeCache<'tcx>,
@@ -88,6 +96,15 @@ pub struct CodegenCx<'tcx> {
     pub buffer_load_intrinsic_fn_id: RefCell<FxHashMap<Word, &'tcx PassMode>>,
     /// Intrinsic for storing a <T> into a &[u32]. The PassMode is the mode of the <T>.
     pub buffer_store_intrinsic_fn_id: RefCell<FxHashMap<Word, &'tcx PassMode>>,
+    /// Intrinsics for atomic read-modify-write operations on floats.
+    pub atomic_float_intrinsic_fn_id: RefCell<FxHashMap<Word, AtomicFloatOp>>,
+
+    /// Functions with `#[spirv(atomic_scope(...))]`, using that scope for their
+    /// atomic operations (and fences), instead of the default `Device` scope.
+    pub atomic_scope_fn_id: RefCell<FxHashMap<Word, Scope>>,
+    /// The `Device` scope constant used by atomic operations (and fences) in
+    /// all other functions (see `DefaultAtomicScopeDecoration`).
+    pub default_atomic_scope_id: OnceCell<Word>,
 
     /// Some runtimes (e.g. intel-compute-runtime) disallow atomics on i8 and i16, even though it's allowed by the spec.
     /// This enables/disables them.
@@ -103,35 +120,31 @@ impl<'tcx> CodegenCx<'tcx> {
     pub fn new(tcx: TyCtxt<'tcx>, codegen_unit: &'tcx CodegenUnit<'tcx>) -> Self {
         let sym = Symbols::get();
 
-        let mut feature_names = tcx
-            .sess
-            .target_features
-            .iter()
-            .map(|s| s.as_str())
-            .collect::<Vec<_>>();
-
-        // target_features is a HashSet, not a Vec, so we need to sort to have deterministic
-        // compilation - otherwise, the order of capabilities in binaries depends on the iteration
-        // order of the hashset. Sort by the string, since that's easy.
-        feature_names.sort_unstable();
-
-        let features = feature_names
-            .into_iter()
-            .map(|s| s.parse())
-            .collect::<Result<_, String>>()
-            .unwrap_or_else(|error| {
-                tcx.sess.psess.dcx.err(error);
-                Vec::new()
-            });
-
         let codegen_args = CodegenArgs::from_session(tcx.sess);
-        let target = tcx.sess.target.llvm_target.parse().unwrap();
+        let target: SpirvTarget = tcx.sess.target.llvm_target.parse().unwrap();
+
+        let features = session_target_features(tcx.sess, &target);
+        let mut inferable = vec![];
+        if codegen_args.linker_opts.infer_capabilities {
+            // Allow using everything the linker is allowed to infer, without
+            // declaring it, the linker will later declare whatever ends up used
+            // (see `linker::capability_computation`).
+            let caps = codegen_args
+                .linker_opts
+                .inferable_capabilities(&target)
+                .iter()
+                .map(|&cap| TargetFeature::Capability(cap))
+                .collect::<Vec<_>>();
+            inferable = expand_target_features(&target, &caps);
+            inferable.retain(|feature| !features.contains(feature));
+        }
 
         Self {
             tcx,
             codegen_unit,
-            builder: BuilderSpirv::new(tcx, &sym, &target, &features),
+            builder: BuilderSpirv::new(tcx, &sym, &target, &features, &inferable),
             instances: Default::default(),
+            cpu_only_fns: Default::default(),
             function_parameter_values: Default::default(),
             type_cache: Default::default(),
             vtables: Default::default(),
@@ -146,6 +159,9 @@ impl<'tcx> CodegenCx<'tcx> {
             fmt_rt_arg_new_fn_ids_to_ty_and_spec: Default::default(),
             buffer_load_intrinsic_fn_id: Default::default(),
             buffer_store_intrinsic_fn_id: Default::default(),
+            atomic_float_intrinsic_fn_id: Default::default(),
+            atomic_scope_fn_id: Default::default(),
+            default_atomic_scope_id: Default::default(),
             i8_i16_atomics_allowed: false,
             codegen_args,
         }
@@ -223,6 +239,17 @@ impl<'tcx> CodegenCx<'tcx> {
                         .chain(src_loc.map(|src_loc| src_loc.encode_to_inst(id)))
                 },
             ));
+        result.annotations.extend(
+            self.atomic_scope_fn_id
+                .into_inner()
+                .into_iter()
+                .map(|(id, scope)| AtomicScopeDecoration { scope }.encode_to_inst(id))
+                .chain(
+                    self.default_atomic_scope_id
+                        .into_inner()
+                        .map(|id| DefaultAtomicScopeDecoration.encode_to_inst(id)),
+                ),
+        );
         result
     }
 
@@ -243,6 +270,7 @@ pub enum SpirvMetadata {
     Full,
 }
 
+#[derive(Debug)]
 pub struct CodegenArgs {
     pub module_output_type: ModuleOutputType,
     pub disassemble: bool,
@@ -267,6 +295,10 @@ pub struct CodegenArgs {
     // spirv-opt flags
     pub preserve_bindings: bool,
 
+    /// Path patterns (with `*`/`**` wildcards) of functions to treat as CPU-only,
+    /// in addition to those marked `#[spirv(cpu_only)]` (see `is_cpu_only_fn`).
+    pub cpu_only_fns: Vec<String>,
+
     /// All options pertinent to `rustc_codegen_spirv::linker` specifically.
     // FIXME(eddyb) should these be handled as `-C linker-args="..."` instead?
     pub linker_opts: crate::linker::Options,
@@ -277,6 +309,7 @@ pub struct CodegenArgs {
     pub dump_module_on_panic: Option<PathBuf>,
     pub dump_pre_link: Option<PathBuf>,
     pub dump_post_link: Option<PathBuf>,
+    pub crash_bundle: Option<PathBuf>,
 }
 
 impl CodegenArgs {
@@ -375,6 +408,14 @@ impl CodegenArgs {
             "Preserve unused descriptor bindings. Useful for reflection.",
         );
 
+        opts.optmulti(
+            "",
+            "cpu-only-fn",
+            "treat functions with paths matching PATTERN (where `*` matches within one path \
+             segment, and `**` across segments) as CPU-only, i.e. error if they're used from GPU code",
+            "PATTERN",
+        );
+
         // Linker options.
         // FIXME(eddyb) should these be handled as `-C linker-args="..."` instead?
         {
@@ -395,6 +436,17 @@ impl CodegenArgs {
                 "no-infer-storage-classes",
                 "disables SPIR-V Storage Class inference",
             );
+            opts.optflag(
+                "",
+                "no-infer-capabilities",
+                "disables inferring the SPIR-V capabilities (and extensions) the module needs",
+            );
+            opts.optmulti(
+                "",
+                "inferable-capabilities",
+                "overrides the capabilities the linker may infer for the target (comma-separated)",
+                "CAPS",
+            );
             opts.optflag("", "no-structurize", "disables CFG structurization");
 
             opts.optmulti(
@@ -457,6 +509,11 @@ impl CodegenArgs {
                 "print-zombie",
                 "prints everything removed (even transitively) due to zombies",
             );
+            opts.optflag(
+                "",
+                "print-inferred-capabilities",
+                "prints the inferred capabilities and extensions, and what required them",
+            );
         }
 
         // NOTE(eddyb) these are debugging options that used to be env vars
@@ -485,6 +542,12 @@ impl CodegenArgs {
             "dump all output modules from the linker, to files in DIR",
             "DIR",
         );
+        opts.optopt(
+            "",
+            "crash-bundle",
+            "if codegen panics, or linking fails, write a crash reproduction bundle to DIR",
+            "DIR",
+        );
 
         let matches = opts.parse(args)?;
 
@@ -557,6 +620,8 @@ impl CodegenArgs {
 
         let preserve_bindings = matches.opt_present("preserve-bindings");
 
+        let cpu_only_fns = matches.opt_strs("cpu-only-fn");
+
         let relax_block_layout = if relax_block_layout { Some(true) } else { None };
 
         let spirv_metadata = match spirv_metadata.as_deref() {
@@ -570,6 +635,23 @@ impl CodegenArgs {
             }
         };
 
+        let inferable_capabilities = if matches.opt_present("inferable-capabilities") {
+            let caps = matches
+                .opt_strs("inferable-capabilities")
+                .iter()
+                .flat_map(|s| s.split(','))
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    s.parse::<Capability>().map_err(|()| {
+                        rustc_session::getopts::Fail::UnrecognizedOption(s.to_string())
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(caps)
+        } else {
+            None
+        };
+
         let matches_opt_path = |name| matches.opt_str(name).map(PathBuf::from);
         let matches_opt_dump_dir_path = |name| {
             matches_opt_path(name).map(|path| {
@@ -587,6 +669,8 @@ impl CodegenArgs {
             compact_ids: !matches.opt_present("no-compact-ids"),
             early_report_zombies: !matches.opt_present("no-early-report-zombies"),
             infer_storage_classes: !matches.opt_present("no-infer-storage-classes"),
+            infer_capabilities: !matches.opt_present("no-infer-capabilities"),
+            inferable_capabilities,
             structurize: !matches.opt_present("no-structurize"),
             spirt_passes: matches
                 .opt_strs("spirt-passes")
@@ -615,6 +699,7 @@ impl CodegenArgs {
             specializer_dump_instances: matches_opt_path("specializer-dump-instances"),
             print_all_zombie: matches.opt_present("print-all-zombie"),
             print_zombie: matches.opt_present("print-zombie"),
+            print_inferred_capabilities: matches.opt_present("print-inferred-capabilities"),
         };
 
         Ok(Self {
@@ -639,6 +724,8 @@ impl CodegenArgs {
 
             preserve_bindings,
 
+            cpu_only_fns,
+
             linker_opts,
 
             // NOTE(eddyb) these are debugging options that used to be env vars
@@ -647,6 +734,7 @@ impl CodegenArgs {
             dump_module_on_panic: matches_opt_path("dump-module-on-panic"),
             dump_pre_link: matches_opt_dump_dir_path("dump-pre-link"),
             dump_post_link: matches_opt_dump_dir_path("dump-post-link"),
+            crash_bundle: matches_opt_path("crash-bundle"),
         })
     }
 
@@ -843,14 +931,13 @@ impl<'tcx> MiscMethods<'tcx> for CodegenCx<'tcx> {
         }
         .def(span, self);
 
-        // Create these `OpUndef`s up front, instead of on-demand in `SpirvValue::def`,
-        // because `SpirvValue::def` can't use `cx.emit()`.
-        self.def_constant(ty, SpirvConst::ZombieUndefForFnAddr);
+        // Create the pointer constant up front, instead of on-demand in
+        // `SpirvValue::def`, because `SpirvValue::def` can't use `cx.emit()`.
+        let function = function.def_cx(self);
+        self.def_constant(ty, SpirvConst::FnAddr { function });
 
         SpirvValue {
-            kind: SpirvValueKind::FnAddr {
-                function: function.def_cx(self),
-            },
+            kind: SpirvValueKind::FnAddr { function },
             ty,
         }
     }
@@ -941,11 +1028,11 @@ impl<'tcx> DebugInfoMethods<'tcx> for CodegenCx<'tcx> {
 impl<'tcx> AsmMethods<'tcx> for CodegenCx<'tcx> {
     fn codegen_global_asm(
         &self,
-        _template: &[InlineAsmTemplatePiece],
-        _operands: &[GlobalAsmOperandRef<'tcx>],
-        _options: InlineAsmOptions,
-        _line_spans: &[Span],
+        template: &[InlineAsmTemplatePiece],
+        operands: &[GlobalAsmOperandRef<'tcx>],
+        options: InlineAsmOptions,
+        line_spans: &[Span],
     ) {
-        todo!()
+        Builder::codegen_global_asm(self, template, operands, options, line_spans);
     }
 }
@@ -0,0 +1,237 @@
+//! Crash reproduction bundles, written to the `--crash-bundle=DIR` directory
+//! when codegen panics, or when linking fails, containing (hopefully) enough
+//! to reproduce the problem without the original project:
+//! - `codegen-args.txt`: all the (parsed) `CodegenArgs`
+//! - `target.txt`: the target, and its (requested and expanded) features
+//! - `<CGU>.partial.spv`: (codegen only) the partially built module
+//! - `<CGU>.mir`: (codegen only) the MIR of the item being codegen'd, if any
+//! - `inputs/`: (linking only) all the modules the linker was given
+//! - `link.rlink` and `rerun-link.sh`: (linking only) a script rerunning only
+//!   the linking step (via `rustc -Z link-only`), on the copies in `inputs/`
+//!   of this crate's own objects (and on dependencies' `.rlib`s, in place)
+//!
+//! `DIR` is only created (and written to) when something goes wrong.
+
+use std::{
+    cell::Cell,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+use rustc_codegen_ssa::CodegenResults;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_middle::mir::mono::{MonoItem, MonoItemData};
+use rustc_session::{
+    config::{Input, OutputFilenames},
+    Session,
+};
+use rustc_span::Symbol;
+
+use crate::{
+    codegen_cx::{CodegenArgs, CodegenCx},
+    target::SpirvTarget,
+    target_feature::session_target_features,
+};
+
+/// Reports the outcome of writing a crash bundle, without panicking (as that
+/// would abort, when already unwinding from a panic).
+fn report(dir: &Path, result: io::Result<()>) {
+    match result {
+        Ok(()) => eprintln!(
+            "note: crash reproduction bundle written to {}",
+            dir.display()
+        ),
+        Err(e) => eprintln!(
+            "error: failed to write crash reproduction bundle to {}: {e}",
+            dir.display()
+        ),
+    }
+}
+
+fn write_common(sess: &Session, cg_args: &CodegenArgs, dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join("codegen-args.txt"), format!("{cg_args:#?}\n"))?;
+
+    let target = &sess.target.llvm_target;
+    let features = target
+        .parse::<SpirvTarget>()
+        .map(|target| {
+            session_target_features(sess, &target)
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .unwrap_or_default();
+    std::fs::write(
+        dir.join("target.txt"),
+        format!(
+            "target: {target}\n\
+             requested features: {}\n\
+             expanded features: {features}\n",
+            sess.opts.cg.target_feature
+        ),
+    )
+}
+
+/// Handles `--dump-module-on-panic` and `--crash-bundle` for one codegen unit,
+/// if codegen panics while this is alive.
+pub struct CodegenPanicGuard<'a, 'tcx> {
+    cx: &'a CodegenCx<'tcx>,
+    cgu_name: Symbol,
+
+    /// The mono item currently being (pre)defined, to dump the MIR of.
+    pub current_item: Cell<Option<(MonoItem<'tcx>, MonoItemData)>>,
+}
+
+impl<'a, 'tcx> CodegenPanicGuard<'a, 'tcx> {
+    pub fn new(cx: &'a CodegenCx<'tcx>, cgu_name: Symbol) -> Self {
+        Self {
+            cx,
+            cgu_name,
+            current_item: Cell::new(None),
+        }
+    }
+
+    fn write_crash_bundle(&self, dir: &Path) -> io::Result<()> {
+        write_common(self.cx.tcx.sess, &self.cx.codegen_args, dir)?;
+
+        let module = self.cx.builder.dump_module_words();
+        std::fs::write(
+            dir.join(format!("{}.partial.spv", self.cgu_name)),
+            spirv_tools::binary::from_binary(&module),
+        )?;
+
+        if let Some(item) = self.current_item.get() {
+            crate::dump_mir(
+                self.cx.tcx,
+                &[item],
+                &dir.join(format!("{}.mir", self.cgu_name)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CodegenPanicGuard<'_, '_> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        if let Some(path) = &self.cx.codegen_args.dump_module_on_panic {
+            if path.has_root() {
+                self.cx.builder.dump_module(path);
+            } else {
+                println!("{}", self.cx.builder.dump_module_str());
+            }
+        }
+        if let Some(dir) = &self.cx.codegen_args.crash_bundle {
+            report(dir, self.write_crash_bundle(dir));
+        }
+    }
+}
+
+/// Handles `--crash-bundle` for the final link, if linking panics, or reports
+/// errors, while this is alive.
+pub struct LinkFailureGuard<'a> {
+    pub sess: &'a Session,
+    pub cg_args: &'a CodegenArgs,
+    pub codegen_results: &'a CodegenResults,
+    pub outputs: &'a OutputFilenames,
+    pub objects: &'a [PathBuf],
+    pub rlibs: &'a [PathBuf],
+}
+
+/// The file name (or stem) of `path`, or an error if it has none.
+fn file_name_of(
+    path: &Path,
+    component: impl FnOnce(&Path) -> Option<&OsStr>,
+) -> io::Result<&OsStr> {
+    component(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`{}` has no file name", path.display()),
+        )
+    })
+}
+
+impl LinkFailureGuard<'_> {
+    fn write_crash_bundle(&self, dir: &Path) -> io::Result<()> {
+        write_common(self.sess, self.cg_args, dir)?;
+
+        let inputs_dir = dir.join("inputs");
+        std::fs::create_dir_all(&inputs_dir)?;
+        let input_path = |file_name: &OsStr| inputs_dir.join(file_name).with_extension("spv");
+        let mut object_copies = FxHashMap::default();
+        for obj in self.objects {
+            let copy = input_path(file_name_of(obj, Path::file_name)?);
+            std::fs::copy(obj, &copy)?;
+            object_copies.insert(obj.as_path(), copy);
+        }
+        for rlib in self.rlibs {
+            let rlib_name = file_name_of(rlib, Path::file_stem)?.to_string_lossy();
+            for (file_name, bytes) in crate::link::read_rlib_modules(rlib)? {
+                let file_name = format!("{rlib_name}.{file_name}");
+                std::fs::write(input_path(OsStr::new(&file_name)), bytes)?;
+            }
+        }
+
+        // `rustc -Z link-only` takes the `.rlink` file in place of the crate
+        // root, but otherwise needs the same arguments (target, backend etc.).
+        // The original objects are temporary files, so the `.rlink` is read
+        // back, to point it to their copies in `inputs/` instead.
+        let rlink = dir.join("link.rlink");
+        CodegenResults::serialize_rlink(self.sess, &rlink, self.codegen_results, self.outputs)?;
+        let (mut codegen_results, outputs) =
+            CodegenResults::deserialize_rlink(self.sess, std::fs::read(&rlink)?).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "failed to read back `link.rlink`",
+                )
+            })?;
+        for obj in codegen_results
+            .modules
+            .iter_mut()
+            .filter_map(|m| m.object.as_mut())
+        {
+            if let Some(copy) = object_copies.get(obj.as_path()) {
+                *obj = copy.canonicalize()?;
+            }
+        }
+        CodegenResults::serialize_rlink(self.sess, &rlink, &codegen_results, &outputs)?;
+
+        let crate_root = match &self.sess.io.input {
+            Input::File(path) => Some(path.as_os_str()),
+            Input::Str { .. } => None,
+        };
+        let quote = |arg: &OsStr| format!("'{}'", arg.to_string_lossy().replace('\'', r"'\''"));
+        let rustc_args = std::env::args_os()
+            .filter(|arg| Some(arg.as_os_str()) != crate_root)
+            .map(|arg| quote(&arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        std::fs::write(
+            dir.join("rerun-link.sh"),
+            format!(
+                "#!/bin/sh\n\
+                 # Reruns only the linking step, on the copies of this crate's\n\
+                 # objects in `inputs/` (dependencies' `.rlib`s are used in place).\n\
+                 cd {} && {rustc_args} -Z link-only {}\n",
+                quote(std::env::current_dir()?.as_os_str()),
+                quote(rlink.canonicalize()?.as_os_str()),
+            ),
+        )
+    }
+}
+
+impl Drop for LinkFailureGuard<'_> {
+    fn drop(&mut self) {
+        if !(std::thread::panicking() || self.sess.psess.dcx.has_errors().is_some()) {
+            return;
+        }
+        if let Some(dir) = &self.cg_args.crash_bundle {
+            report(dir, self.write_crash_bundle(dir));
+        }
+    }
+}
@@ -7,7 +7,7 @@ use either::Either;
 use itertools::Itertools;
 use rspirv::{
     dr::{Instruction, Module, Operand},
-    spirv::{Decoration, Op, Word},
+    spirv::{Decoration, Op, Scope, Word},
 };
 use rustc_data_structures::{fx::FxIndexMap, sync::Lrc};
 use rustc_span::{source_map::SourceMap, FileName, SourceFile, Span};
@@ -124,6 +124,47 @@ impl<'a> CustomDecoration<'a> for ZombieDecoration<'a> {
     }
 }
 
+/// Scope of atomic operations (and fences) in a `#[spirv(atomic_scope(...))]`
+/// function, which the linker also applies to the functions it calls (unless
+/// they have their own), see `linker::atomic_scopes`.
+#[derive(Copy, Clone)]
+pub struct AtomicScopeDecoration {
+    pub scope: Scope,
+}
+
+impl<'a> CustomDecoration<'a> for AtomicScopeDecoration {
+    const ENCODING_PREFIX: &'static str = "S";
+
+    fn encode(self, w: &mut impl fmt::Write) -> fmt::Result {
+        let Self { scope } = self;
+        write!(w, "{scope:?}")
+    }
+
+    fn decode(s: &'a str) -> Self {
+        Self {
+            scope: s.parse().unwrap(),
+        }
+    }
+}
+
+/// Marks the (`Device` scope) `OpConstant` used by atomic operations (and
+/// fences) outside `#[spirv(atomic_scope(...))]` functions, for the linker
+/// to replace with the scope of their callers (see `AtomicScopeDecoration`).
+#[derive(Copy, Clone)]
+pub struct DefaultAtomicScopeDecoration;
+
+impl<'a> CustomDecoration<'a> for DefaultAtomicScopeDecoration {
+    const ENCODING_PREFIX: &'static str = "D";
+
+    fn encode(self, _w: &mut impl fmt::Write) -> fmt::Result {
+        Ok(())
+    }
+
+    fn decode(_s: &'a str) -> Self {
+        Self
+    }
+}
+
 /// Equivalent of `CustomInst::SetDebugSrcLoc` (see `crate::custom_insts`),
 /// for global definitions (i.e. outside functions), where limitations of
 /// `rspirv`/`spirt` prevent us from using anything other than decorations.
@@ -26,6 +26,7 @@ mod attr;
 mod builder;
 mod builder_spirv;
 mod codegen_cx;
+mod crash_bundle;
 mod custom_decorations;
 mod custom_insts;
 mod link;
@@ -33,13 +34,14 @@ mod linker;
 mod rustc_codegen_spirv_types;
 mod spirv_type;
 mod spirv_type_constraints;
+mod stats;
 mod symbols;
 mod target;
 mod target_feature;
 
 use core::any::Any;
 use std::{
-    fs::{create_dir_all, File}, io::{Cursor, Write}, path::Path, str::FromStr, sync::Arc
+    fs::{create_dir_all, File}, io::Write, path::Path, str::FromStr, sync::Arc, time::Instant
 };
 
 use builder::Builder;
@@ -61,34 +63,44 @@ use rustc_data_structures::fx::FxIndexMap;
 use rustc_errors::{DiagCtxt, FatalError};
 use rustc_metadata::EncodedMetadata;
 use rustc_middle::{
-    dep_graph::{WorkProduct, WorkProductId},
+    dep_graph::{self, WorkProduct, WorkProductId},
     mir::{
         mono::{MonoItem, MonoItemData},
         write_mir_pretty,
     },
-    ty::{self, print::with_no_trimmed_paths, Instance, InstanceDef, TyCtxt},
+    ty::{print::with_no_trimmed_paths, InstanceDef, TyCtxt},
 };
 use rustc_session::{
     config::{self, OutputFilenames, OutputType},
     Session,
 };
-use rustc_span::{sym, ErrorGuaranteed, Symbol};
-use rustc_target::{json::ToJson, spec::{Target, TargetTriple}};
-use target::{SpirvTarget, ALL_VALID_TARGETS};
-
-fn dump_mir(tcx: TyCtxt<'_>, mono_items: &[(MonoItem<'_>, MonoItemData)], path: &Path) {
-    create_dir_all(path.parent().unwrap()).unwrap();
-    let mut file = File::create(path).unwrap();
+use rustc_span::{ErrorGuaranteed, Symbol};
+use rustc_target::json::ToJson;
+use target::SpirvTarget;
+use target_feature::TargetFeature;
+pub use target::{target_spec_json, InvalidTarget, ALL_VALID_TARGETS};
+
+fn dump_mir(
+    tcx: TyCtxt<'_>,
+    mono_items: &[(MonoItem<'_>, MonoItemData)],
+    path: &Path,
+) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
     for &(mono_item, _) in mono_items {
         if let MonoItem::Fn(instance) = mono_item {
             if matches!(instance.def, InstanceDef::Item(_)) {
-                let mut mir = Cursor::new(Vec::new());
+                let mut mir = Vec::new();
                 if write_mir_pretty(tcx, Some(instance.def_id()), &mut mir).is_ok() {
-                    file.write(format!("{}\n", String::from_utf8(mir.into_inner()).unwrap()).into_bytes().as_ref()).unwrap();
+                    file.write_all(&mir)?;
+                    file.write_all(b"\n")?;
                 }
             }
         }
     }
+    Ok(())
 }
 
 // TODO: Should this store Vec or Module?
@@ -121,60 +133,14 @@ macro_rules! assert_ty_eq {
     };
 }
 
-fn is_blocklisted_fn<'tcx>(
-    tcx: TyCtxt<'tcx>,
-    sym: &symbols::Symbols,
-    instance: Instance<'tcx>,
-) -> bool {
-    // TODO: These sometimes have a constant value of an enum variant with a hole
-    if let InstanceDef::Item(def_id) = instance.def {
-        if let Some(debug_trait_def_id) = tcx.get_diagnostic_item(sym::Debug) {
-            // Helper for detecting `<_ as core::fmt::Debug>::fmt` (in impls).
-            let is_debug_fmt_method = |def_id| match tcx.opt_associated_item(def_id) {
-                Some(assoc) if assoc.ident(tcx).name == sym::fmt => match assoc.container {
-                    ty::ImplContainer => {
-                        let impl_def_id = assoc.container_id(tcx);
-                        tcx.impl_trait_ref(impl_def_id)
-                            .map(|tr| tr.skip_binder().def_id)
-                            == Some(debug_trait_def_id)
-                    }
-                    ty::TraitContainer => false,
-                },
-                _ => false,
-            };
-
-            if is_debug_fmt_method(def_id) {
-                return true;
-            }
-
-            if tcx.opt_item_ident(def_id).map(|i| i.name) == Some(sym.fmt_decimal) {
-                if let Some(parent_def_id) = tcx.opt_parent(def_id) {
-                    if is_debug_fmt_method(parent_def_id) {
-                        return true;
-                    }
-                }
-            }
-        }
-    }
-
-    false
-}
-
-struct DumpModuleOnPanic<'a, 'cx, 'tcx> {
-    cx: &'cx CodegenCx<'tcx>,
-    path: &'a Path,
-}
-
-impl Drop for DumpModuleOnPanic<'_, '_, '_> {
-    fn drop(&mut self) {
-        if std::thread::panicking() {
-            if self.path.has_root() {
-                self.cx.builder.dump_module(self.path);
-            } else {
-                println!("{}", self.cx.builder.dump_module_str());
-            }
-        }
-    }
+/// The `+feature`s enabled by the target spec and `-Ctarget-feature`.
+fn requested_target_features(sess: &Session) -> impl Iterator<Item = &str> {
+    let cmdline = sess.opts.cg.target_feature.split(',');
+    let cfg = sess.target.options.features.split(',');
+    cfg.chain(cmdline)
+        .filter(|l| l.starts_with('+'))
+        .map(|l| &l[1..])
+        .filter(|l| !l.is_empty())
 }
 
 #[derive(Clone)]
@@ -191,18 +157,18 @@ impl WriteBackendMethods for SpirCodegenBackend {
 
     fn run_link(
         _cgcx: &CodegenContext<Self>,
-        _diag_handler: &DiagCtxt,
-        _modules: Vec<ModuleCodegen<Self::Module>>,
+        diag_handler: &DiagCtxt,
+        modules: Vec<ModuleCodegen<Self::Module>>,
     ) -> Result<ModuleCodegen<Self::Module>, FatalError> {
-        todo!()
+        link::run_link(diag_handler, modules)
     }
 
     fn run_fat_lto(
-        _: &CodegenContext<Self>,
-        _: Vec<FatLtoInput<Self>>,
-        _: Vec<(SerializedModule<Self::ModuleBuffer>, WorkProduct)>,
+        cgcx: &CodegenContext<Self>,
+        modules: Vec<FatLtoInput<Self>>,
+        cached_modules: Vec<(SerializedModule<Self::ModuleBuffer>, WorkProduct)>,
     ) -> Result<LtoModuleCodegen<Self>, FatalError> {
-        todo!()
+        link::run_fat(cgcx, modules, cached_modules)
     }
 
     fn run_thin_lto(
@@ -214,11 +180,11 @@ impl WriteBackendMethods for SpirCodegenBackend {
     }
 
     fn print_pass_timings(&self) {
-        println!("TODO: Implement print_pass_timings");
+        stats::print_codegen_unit_timings();
     }
 
     fn print_statistics(&self) {
-        println!("TODO: Implement print_statistics");
+        stats::print_codegen_unit_statistics();
     }
 
     unsafe fn optimize(
@@ -249,14 +215,15 @@ impl WriteBackendMethods for SpirCodegenBackend {
         _: &CodegenContext<Self>,
         _: &mut ModuleCodegen<Self::Module>,
     ) -> Result<(), FatalError> {
-        todo!()
+        // NOTE all optimizations happen during linking (see `link::run_fat`).
+        Ok(())
     }
 
     unsafe fn codegen(
         cgcx: &CodegenContext<Self>,
-        _diag_handler: &DiagCtxt,
+        diag_handler: &DiagCtxt,
         module: ModuleCodegen<Self::Module>,
-        _config: &ModuleConfig,
+        config: &ModuleConfig,
     ) -> Result<CompiledModule, FatalError> {
         let path = cgcx
             .output_filenames
@@ -267,6 +234,28 @@ impl WriteBackendMethods for SpirCodegenBackend {
             .unwrap()
             .write_all(spirv_module)
             .unwrap();
+
+        // NOTE `rustc` expects these at its usual paths (i.e. `.s` and `.ll`),
+        // which it then renames, or keeps, depending on the number of CGUs
+        // (the linked module also gets `.spvasm`/`.spirt` files, see `link`).
+        if !module.module_llvm.is_empty() {
+            if config.emit_asm {
+                let path = cgcx
+                    .output_filenames
+                    .temp_path(OutputType::Assembly, Some(&module.name));
+                if let Err(e) = link::emit_spirv_assembly(&module.module_llvm, &path) {
+                    diag_handler.err(e);
+                }
+            }
+            if config.emit_ir {
+                let path = cgcx
+                    .output_filenames
+                    .temp_path(OutputType::LlvmAssembly, Some(&module.name));
+                if let Err(e) = link::emit_spirt_text(&module.module_llvm, &path) {
+                    diag_handler.err(e);
+                }
+            }
+        }
         Ok(CompiledModule {
             name: module.name,
             kind: module.kind,
@@ -301,65 +290,102 @@ impl ExtraBackendMethods for SpirCodegenBackend {
         tcx: TyCtxt<'_>,
         cgu_name: Symbol,
     ) -> (ModuleCodegen<Self::Module>, u64) {
-        let _timer = tcx
-            .prof
-            .verbose_generic_activity_with_arg("codegen_module", cgu_name.to_string());
+        let start = Instant::now();
+
+        // NOTE running codegen as a dep-graph task is what lets an unchanged
+        // CGU be marked green (and reused) by the next incremental session,
+        // see `rustc_codegen_ssa::base::determine_cgu_reuse`.
+        let dep_node = tcx.codegen_unit(cgu_name).codegen_dep_node(tcx);
+        let (module, _) = tcx.dep_graph.with_task(
+            dep_node,
+            tcx,
+            cgu_name,
+            module_codegen,
+            Some(dep_graph::hash_result),
+        );
 
-        // TODO: Do dep_graph stuff
-        let cgu = tcx.codegen_unit(cgu_name);
+        // Like `rustc_codegen_llvm`, assume the cost of the remaining work on
+        // this CGU is proportional to the time it took to codegen it.
+        let cost = start.elapsed().as_nanos() as u64;
 
-        let cx = CodegenCx::new(tcx, cgu);
-        let do_codegen = || {
-            let mono_items = cx.codegen_unit.items_in_deterministic_order(cx.tcx);
+        fn module_codegen(tcx: TyCtxt<'_>, cgu_name: Symbol) -> ModuleCodegen<Vec<u32>> {
+            let _timer = tcx
+                .prof
+                .verbose_generic_activity_with_arg("codegen_module", cgu_name.to_string());
 
-            if let Some(dir) = &cx.codegen_args.dump_mir {
-                dump_mir(tcx, mono_items.as_slice(), &dir.join(cgu_name.to_string()));
-            }
+            let start = Instant::now();
+
+            let cgu = tcx.codegen_unit(cgu_name);
 
-            for &(mono_item, mono_item_data) in mono_items.iter() {
-                if let MonoItem::Fn(instance) = mono_item {
-                    if is_blocklisted_fn(cx.tcx, &cx.sym, instance) {
-                        continue;
+            let cx = CodegenCx::new(tcx, cgu);
+            let panic_guard = crash_bundle::CodegenPanicGuard::new(&cx, cgu_name);
+            let do_codegen = || {
+                let mono_items = cx.codegen_unit.items_in_deterministic_order(cx.tcx);
+
+                if let Some(dir) = &cx.codegen_args.dump_mir {
+                    let path = dir.join(cgu_name.to_string());
+                    if let Err(e) = dump_mir(tcx, mono_items.as_slice(), &path) {
+                        tcx.sess
+                            .psess
+                            .dcx
+                            .err(format!("failed to dump MIR to {}: {e}", path.display()));
                     }
                 }
-                mono_item.predefine::<Builder<'_, '_>>(
-                    &cx,
-                    mono_item_data.linkage,
-                    mono_item_data.visibility,
-                );
-            }
 
-            // ... and now that we have everything pre-defined, fill out those definitions.
-            for &(mono_item, _) in mono_items.iter() {
-                if let MonoItem::Fn(instance) = mono_item {
-                    if is_blocklisted_fn(cx.tcx, &cx.sym, instance) {
-                        continue;
+                for &(mono_item, mono_item_data) in mono_items.iter() {
+                    if let MonoItem::Fn(instance) = mono_item {
+                        if cx.is_cpu_only_fn(instance) {
+                            continue;
+                        }
                     }
+                    panic_guard
+                        .current_item
+                        .set(Some((mono_item, mono_item_data)));
+                    mono_item.predefine::<Builder<'_, '_>>(
+                        &cx,
+                        mono_item_data.linkage,
+                        mono_item_data.visibility,
+                    );
                 }
-                mono_item.define::<Builder<'_, '_>>(&cx);
-            }
 
-            if let Some(_entry) = maybe_create_entry_wrapper::<Builder<'_, '_>>(&cx) {
-                // attributes::sanitize(&cx, SanitizerSet::empty(), entry);
-            }
-        };
-        if let Some(path) = &cx.codegen_args.dump_module_on_panic {
-            let module_dumper = DumpModuleOnPanic { cx: &cx, path };
-            with_no_trimmed_paths!(do_codegen());
-            drop(module_dumper);
-        } else {
+                // ... and now that we have everything pre-defined, fill out those definitions.
+                for &(mono_item, mono_item_data) in mono_items.iter() {
+                    if let MonoItem::Fn(instance) = mono_item {
+                        if cx.is_cpu_only_fn(instance) {
+                            continue;
+                        }
+                    }
+                    panic_guard
+                        .current_item
+                        .set(Some((mono_item, mono_item_data)));
+                    mono_item.define::<Builder<'_, '_>>(&cx);
+                }
+                panic_guard.current_item.set(None);
+
+                if let Some(_entry) = maybe_create_entry_wrapper::<Builder<'_, '_>>(&cx) {
+                    // attributes::sanitize(&cx, SanitizerSet::empty(), entry);
+                }
+            };
             with_no_trimmed_paths!(do_codegen());
-        }
-        let spirv_module = cx.finalize_module().assemble();
+            drop(panic_guard);
+            let spirv_module = cx.finalize_module();
+            let spirv_module_words = spirv_module.assemble();
+            if stats::enabled(tcx.sess) {
+                stats::record_codegen_unit(
+                    cgu_name.to_string(),
+                    start.elapsed(),
+                    stats::ModuleStats::collect(&spirv_module, spirv_module_words.len()),
+                );
+            }
 
-        (
             ModuleCodegen {
                 name: cgu_name.to_string(),
-                module_llvm: spirv_module,
+                module_llvm: spirv_module_words,
                 kind: ModuleKind::Regular,
-            },
-            0,
-        )
+            }
+        }
+
+        (module, cost)
     }
 
     fn target_machine_factory(
@@ -380,33 +406,56 @@ impl CodegenBackend for SpirCodegenBackend {
     }
 
     fn target_features(&self, sess: &Session, _allow_unstable: bool) -> Vec<Symbol> {
-        let cmdline = sess.opts.cg.target_feature.split(',');
-        let cfg = sess.target.options.features.split(',');
-        cfg.chain(cmdline)
-            .filter(|l| l.starts_with('+'))
-            .map(|l| &l[1..])
-            .filter(|l| !l.is_empty())
-            .map(Symbol::intern)
+        // Report everything the requested features imply (e.g. the contents of
+        // `profile:...`), so that `cfg(target_feature = "...")` sees it all.
+        // Invalid features were already reported by `init`.
+        let spirv_target: SpirvTarget = sess.target.llvm_target.parse().unwrap();
+        let features = requested_target_features(sess)
+            .filter_map(|feature| feature.parse::<TargetFeature>().ok())
+            .collect::<Vec<_>>();
+        target_feature::expand_target_features(&spirv_target, &features)
+            .iter()
+            .map(|feature| Symbol::intern(&feature.to_string()))
             .collect()
     }
 
     fn init(&self, sess: &Session) {
+        // NOTE rustc no longer lets codegen backends override the target
+        // (see rust-lang/rust#122810), so the target spec has to come from a
+        // JSON file, generated by the `spirv-target-spec` binary. Catch specs
+        // for unknown triples, or ones generated by a different version.
+        let spirv_target = match sess.target.llvm_target.parse::<SpirvTarget>() {
+            Ok(spirv_target) => spirv_target,
+            Err(e) => sess.psess.dcx.fatal(e.to_string()),
+        };
+        if sess.target.to_json() != spirv_target.rustc_target().to_json() {
+            sess.psess.dcx.warn(format!(
+                "target spec for `{spirv_target}` differs from the one built into \
+                 rustc_codegen_spir, consider regenerating it with `spirv-target-spec`"
+            ));
+        }
 
+        // Check `-Ctarget-feature` up front, so that problems are reported once,
+        // instead of by every codegen unit (or later, by `spirv-val`).
+        let features = requested_target_features(sess)
+            .filter_map(|feature| match feature.parse::<TargetFeature>() {
+                Ok(feature) => Some(feature),
+                Err(e) => {
+                    sess.psess.dcx.err(e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let features = target_feature::expand_target_features(&spirv_target, &features);
+        let (warnings, errors) = target_feature::check_target_features(&spirv_target, &features);
+        for warning in warnings {
+            sess.psess.dcx.warn(warning);
+        }
+        for error in errors {
+            sess.psess.dcx.err(error);
+        }
+        sess.psess.dcx.abort_if_errors();
     }
-//     // #122810
-//     fn target_override(&self, opts: &config::Options) -> Option<Target> {
-//         match opts.target_triple {
-//             TargetTriple::TargetTriple(ref target) => {
-//                 println!("providing target {}", target);
-//                 Some(target
-//                 .parse::<target::SpirvTarget>()
-//                 .map(|target| target.rustc_target())
-// .unwrap())
-//             },
-//             TargetTriple::TargetJson { .. } => None,
-//         }
-//         // panic!("{:#?}", opts.target_triple)
-//     }
 
     fn provide(&self, providers: &mut rustc_middle::util::Providers) {
         // FIXME(eddyb) this is currently only passed back to us, specifically
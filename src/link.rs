@@ -1,8 +1,9 @@
 use std::{
+    borrow::Cow,
     collections::BTreeMap,
     ffi::{CString, OsStr},
     fs::File,
-    io::{BufWriter, Read},
+    io::{self, BufWriter, Read},
     iter,
     path::{Path, PathBuf},
     sync::Arc,
@@ -13,13 +14,15 @@ use rspirv::{binary::Assemble, dr::Module};
 use rustc_ast::CRATE_NODE_ID;
 use rustc_codegen_ssa::{
     back::{
+        link::{are_upstream_rust_objects_already_included, ignored_for_lto},
         lto::{LtoModuleCodegen, SerializedModule, ThinModule, ThinShared},
-        write::CodegenContext,
+        write::{CodegenContext, FatLtoInput},
     },
-    CodegenResults, NativeLib,
+    traits::ModuleBufferMethods,
+    CodegenResults, ModuleCodegen, ModuleKind, NativeLib,
 };
 use rustc_data_structures::fx::FxHashSet;
-use rustc_errors::FatalError;
+use rustc_errors::{DiagCtxt, FatalError};
 use rustc_metadata::fs::METADATA_FILENAME;
 use rustc_middle::{bug, dep_graph::WorkProduct, middle::dependency_format::Linkage};
 use rustc_session::{
@@ -32,8 +35,10 @@ use rustc_span::Symbol;
 
 use crate::{
     codegen_cx::{CodegenArgs, SpirvMetadata},
-    linker,
+    crash_bundle, linker,
     rustc_codegen_spirv_types::{CompileResult, ModuleResult},
+    stats::{self, link_timer},
+    target::SpirvTarget,
     SpirCodegenBackend, SpirvModuleBuffer, SpirvThinBuffer,
 };
 
@@ -117,6 +122,8 @@ pub fn link(
             }
         }
     }
+
+    stats::print_link_report(sess);
 }
 
 fn link_rlib(sess: &Session, codegen_results: &CodegenResults, out_filename: &Path) {
@@ -174,6 +181,14 @@ fn link_exe(
     );
 
     let cg_args = CodegenArgs::from_session(sess);
+    let _failure_guard = crash_bundle::LinkFailureGuard {
+        sess,
+        cg_args: &cg_args,
+        codegen_results,
+        outputs,
+        objects: &objects,
+        rlibs: &rlibs,
+    };
 
     // HACK(eddyb) this removes the `.json` in `.spv.json`, from `out_filename`.
     let out_path_spv = out_filename.with_extension("");
@@ -280,7 +295,7 @@ fn post_link_single_module(
         || (sess.opts.debuginfo == DebugInfo::None && cg_args.spirv_metadata == SpirvMetadata::None)
     {
         if cg_args.run_spirv_opt {
-            let _timer = sess.timer("link_spirv_opt");
+            let _timer = link_timer(sess, "link_spirv_opt");
             do_spirv_opt(sess, cg_args, spv_binary, out_filename, opt_options)
         } else {
             let reason = match (sess.opts.optimize, sess.opts.debuginfo == DebugInfo::None) {
@@ -301,8 +316,28 @@ fn post_link_single_module(
         do_spirv_val(sess, &spv_binary, out_filename, val_options);
     }
 
+    // NOTE these are in addition to the per-CGU files that `rustc` itself
+    // handles (see `WriteBackendMethods::codegen`), as the linked module is
+    // the one anyone reading SPIR-V (or SPIR-T) most likely wants to see.
+    if sess.opts.output_types.contains_key(&OutputType::Assembly) {
+        let path = out_filename.with_extension("spvasm");
+        if let Err(e) = emit_spirv_assembly(&spv_binary, &path) {
+            sess.psess.dcx.err(e);
+        }
+    }
+    if sess
+        .opts
+        .output_types
+        .contains_key(&OutputType::LlvmAssembly)
     {
-        let save_modules_timer = sess.timer("link_save_modules");
+        let path = out_filename.with_extension("spirt");
+        if let Err(e) = emit_spirt_text(&spv_binary, &path) {
+            sess.psess.dcx.err(e);
+        }
+    }
+
+    {
+        let save_modules_timer = link_timer(sess, "link_save_modules");
         if let Err(e) = std::fs::write(out_filename, spirv_tools::binary::from_binary(&spv_binary))
         {
             let mut err = sess
@@ -318,6 +353,30 @@ fn post_link_single_module(
     }
 }
 
+/// Writes the SPIR-V assembly form of `spv_words` to `path` (for `--emit=asm`).
+pub(crate) fn emit_spirv_assembly(spv_words: &[u32], path: &Path) -> Result<(), String> {
+    use spirv_tools::assembler::{self, Assembler};
+
+    let text = assembler::create(None)
+        .disassemble(spv_words, assembler::DisassembleOptions::default())
+        .map_err(|e| format!("failed to disassemble `{}`: {e}", path.display()))?
+        .unwrap_or_default();
+    std::fs::write(path, text).map_err(|e| format!("failed to write `{}`: {e}", path.display()))
+}
+
+/// Writes the pretty-printed SPIR-T form of `spv_words` to `path` (for `--emit=llvm-ir`).
+pub(crate) fn emit_spirt_text(spv_words: &[u32], path: &Path) -> Result<(), String> {
+    let cx = std::rc::Rc::new(spirt::Context::new());
+    crate::custom_insts::register_to_spirt_context(&cx);
+    let spv_bytes = spirv_tools::binary::from_binary(spv_words).to_vec();
+    let module = spirt::Module::lower_from_spv_bytes(cx, spv_bytes)
+        .map_err(|e| format!("failed to lower `{}` to SPIR-T: {e}", path.display()))?;
+    let text = spirt::print::Plan::for_module(&module)
+        .pretty_print()
+        .to_string();
+    std::fs::write(path, text).map_err(|e| format!("failed to write `{}`: {e}", path.display()))
+}
+
 fn do_spirv_opt(
     sess: &Session,
     cg_args: &CodegenArgs,
@@ -330,7 +389,10 @@ fn do_spirv_opt(
         opt::{self, Optimizer},
     };
 
-    let mut optimizer = opt::create(sess.target.options.env.parse().ok());
+    let Some(target_env) = spirv_tools_target_env(sess, "spirv-opt") else {
+        return spv_binary;
+    };
+    let mut optimizer = opt::create(Some(target_env));
 
     match sess.opts.optimize {
         OptLevel::No => {}
@@ -349,7 +411,7 @@ fn do_spirv_opt(
     }
 
     let result = optimizer.optimize(
-        &spv_binary,
+        spirv_tools_binary(&spv_binary),
         &mut |msg: error::Message| {
             use error::MessageLevel as Level;
 
@@ -383,7 +445,7 @@ fn do_spirv_opt(
         Some(options),
     );
 
-    match result {
+    let mut optimized = match result {
         Ok(spirv_tools::binary::Binary::OwnedU32(words)) => words,
         Ok(binary) => binary.as_words().to_vec(),
         Err(e) => {
@@ -391,11 +453,44 @@ fn do_spirv_opt(
             err.note("spirv-opt failed, leaving as unoptimized");
             err.note(format!("module `{}`", filename.display()));
             err.emit();
-            spv_binary
+            return spv_binary;
+        }
+    };
+    // Undo `spirv_tools_binary` lowering the version.
+    optimized[1] = spv_binary[1];
+    optimized
+}
+
+/// The `spirv-tools` environment for the current target, or an error about
+/// `tool` being skipped, if the target can't be parsed.
+fn spirv_tools_target_env(sess: &Session, tool: &str) -> Option<spirv_tools::TargetEnv> {
+    match sess.target.llvm_target.parse::<SpirvTarget>() {
+        Ok(target) => Some(target.to_spirv_tools()),
+        Err(e) => {
+            let mut err = sess.psess.dcx.struct_err(e.to_string());
+            err.note(format!("`{tool}` was skipped"));
+            err.emit();
+            None
         }
     }
 }
 
+/// The newest SPIR-V version (as encoded in the module header) that
+/// `spirv-tools` supports, see `SpirvTargetEnv::to_spirv_tools`.
+const SPIRV_TOOLS_MAX_VERSION: u32 = 0x0001_0500;
+
+/// `spv_binary`, with its header version lowered to one `spirv-tools` supports,
+/// matching the environment `spirv_tools_target_env` picked for it.
+fn spirv_tools_binary(spv_binary: &[u32]) -> Cow<'_, [u32]> {
+    // The version is the second word of the header (after the magic number).
+    if spv_binary[1] <= SPIRV_TOOLS_MAX_VERSION {
+        return Cow::Borrowed(spv_binary);
+    }
+    let mut spv_binary = spv_binary.to_vec();
+    spv_binary[1] = SPIRV_TOOLS_MAX_VERSION;
+    Cow::Owned(spv_binary)
+}
+
 fn do_spirv_val(
     sess: &Session,
     spv_binary: &[u32],
@@ -404,9 +499,12 @@ fn do_spirv_val(
 ) {
     use spirv_tools::val::{self, Validator};
 
-    let validator = val::create(sess.target.options.env.parse().ok());
+    let Some(target_env) = spirv_tools_target_env(sess, "spirv-val") else {
+        return;
+    };
+    let validator = val::create(Some(target_env));
 
-    if let Err(e) = validator.validate(spv_binary, Some(options)) {
+    if let Err(e) = validator.validate(spirv_tools_binary(spv_binary), Some(options)) {
         let mut err = sess.psess.dcx.struct_err(e.to_string());
         err.note("spirv-val failed");
         err.note(format!("module `{}`", filename.display()));
@@ -454,6 +552,11 @@ fn add_upstream_rust_crates(
         let src = &codegen_results.crate_info.used_crate_source[&cnum];
         match data[cnum.as_usize() - 1] {
             Linkage::NotLinked | Linkage::IncludedFromDylib => {}
+            // With LTO, upstream crates are already included in this crate's
+            // own objects (see `run_fat` and `run_thin`).
+            Linkage::Static
+                if are_upstream_rust_objects_already_included(sess)
+                    && !ignored_for_lto(sess, &codegen_results.crate_info, cnum) => {}
             Linkage::Static => rlibs.push(src.rlib.as_ref().unwrap().0.clone()),
             // Linkage::Dynamic => rlibs.push(src.dylib.as_ref().unwrap().0.clone()),
             Linkage::Dynamic => {
@@ -558,6 +661,39 @@ fn create_archive(files: &[&Path], metadata: &[u8], out_filename: &Path) {
     builder.into_inner().unwrap();
 }
 
+/// Reads all the SPIR-V modules (i.e. everything but the metadata) from an
+/// archive created by `create_archive`, along with their file names.
+pub(crate) fn read_rlib_modules(rlib: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut modules = vec![];
+    let mut archive = Archive::new(File::open(rlib)?);
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry?;
+        if entry.header().identifier() != METADATA_FILENAME.as_bytes() {
+            // std::fs::read adds 1 to the size, so do the same here - see comment:
+            // https://github.com/rust-lang/rust/blob/72868e017bdade60603a25889e253f556305f996/library/std/src/fs.rs#L200-L202
+            let mut bytes = Vec::with_capacity(entry.header().size() as usize + 1);
+            entry.read_to_end(&mut bytes)?;
+
+            let file_name = std::str::from_utf8(entry.header().identifier())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            modules.push((file_name.to_string(), bytes));
+        }
+    }
+    Ok(modules)
+}
+
+fn parse_module_bytes(bytes: &[u8]) -> Module {
+    let mut loader = rspirv::dr::Loader::new();
+    rspirv::binary::parse_bytes(bytes, &mut loader).unwrap();
+    loader.module()
+}
+
+fn parse_module_words(words: &[u32]) -> Module {
+    let mut loader = rspirv::dr::Loader::new();
+    rspirv::binary::parse_words(words, &mut loader).unwrap();
+    loader.module()
+}
+
 /// This is the actual guts of linking: the rest of the link-related functions are just digging through rustc's
 /// shenanigans to collect all the object files we need to link.
 fn do_link(
@@ -568,15 +704,11 @@ fn do_link(
     outputs: &OutputFilenames,
     disambiguated_crate_name_for_dumps: &OsStr,
 ) -> linker::LinkResult {
-    let load_modules_timer = sess.timer("link_load_modules");
+    let load_modules_timer = link_timer(sess, "link_load_modules");
 
     let mut modules = Vec::new();
     let mut add_module = |file_name: &OsStr, bytes: &[u8]| {
-        let module = {
-            let mut loader = rspirv::dr::Loader::new();
-            rspirv::binary::parse_bytes(bytes, &mut loader).unwrap();
-            loader.module()
-        };
+        let module = parse_module_bytes(bytes);
         if let Some(dir) = &cg_args.dump_pre_link {
             // FIXME(eddyb) is it a good idea to re-`assemble` the `rspirv::dr`
             // module, or should this just save the original bytes?
@@ -597,18 +729,8 @@ fn do_link(
     // `rlibs` are archive files we've created in `create_archive`, usually produced by crates that are being
     // referenced. We need to unpack them and add the modules inside.
     for rlib in rlibs {
-        let mut archive = Archive::new(File::open(rlib).unwrap());
-        while let Some(entry) = archive.next_entry() {
-            let mut entry = entry.unwrap();
-            if entry.header().identifier() != METADATA_FILENAME.as_bytes() {
-                // std::fs::read adds 1 to the size, so do the same here - see comment:
-                // https://github.com/rust-lang/rust/blob/72868e017bdade60603a25889e253f556305f996/library/std/src/fs.rs#L200-L202
-                let mut bytes = Vec::with_capacity(entry.header().size() as usize + 1);
-                entry.read_to_end(&mut bytes).unwrap();
-
-                let file_name = std::str::from_utf8(entry.header().identifier()).unwrap();
-                add_module(OsStr::new(file_name), &bytes);
-            }
+        for (file_name, bytes) in read_rlib_modules(rlib).unwrap() {
+            add_module(OsStr::new(&file_name), &bytes);
         }
     }
 
@@ -633,7 +755,11 @@ fn do_link(
 }
 
 /// As of right now, this is essentially a no-op, just plumbing through all the files.
-// TODO: WorkProduct impl
+///
+/// Modules never import anything from each other (all of that is left to the
+/// SPIR-V linker, see `do_link`), so incrementally reused (i.e. `cached_modules`)
+/// CGUs don't need any further processing, and their previously saved objects
+/// can be reused as-is, by returning their `WorkProduct`s as "copy jobs".
 pub(crate) fn run_thin(
     cgcx: &CodegenContext<SpirCodegenBackend>,
     modules: Vec<(String, SpirvThinBuffer)>,
@@ -643,11 +769,12 @@ pub(crate) fn run_thin(
         unreachable!("We should never reach this case if the LTO step is deferred to the linker");
     }
     assert!(
-        cgcx.lto == Lto::ThinLocal,
-        "no actual LTO implemented in Rust-GPU"
+        matches!(cgcx.lto, Lto::ThinLocal | Lto::Thin),
+        "ThinLTO used with {:?}",
+        cgcx.lto
     );
     let mut thin_buffers = Vec::with_capacity(modules.len());
-    let mut module_names = Vec::with_capacity(modules.len() + cached_modules.len());
+    let mut module_names = Vec::with_capacity(modules.len());
 
     for (name, buffer) in modules {
         let cname = CString::new(name.clone()).unwrap();
@@ -655,14 +782,25 @@ pub(crate) fn run_thin(
         module_names.push(cname);
     }
 
-    let mut serialized_modules = Vec::with_capacity(cached_modules.len());
-
-    for (sm, wp) in cached_modules {
-        let _slice_u8 = sm.data();
-        serialized_modules.push(sm);
-        module_names.push(CString::new(wp.cgu_name).unwrap());
+    // With (non-local) ThinLTO, the final link skips the objects of upstream
+    // crates (see `add_upstream_rust_crates`), so they're passed through here.
+    let mut serialized_modules = vec![];
+    if cgcx.lto == Lto::Thin {
+        for (_, rlib) in &cgcx.each_linked_rlib_for_lto {
+            let rlib_name = rlib.file_stem().unwrap().to_string_lossy();
+            for (file_name, bytes) in read_rlib_modules_for_lto(cgcx, rlib)? {
+                let words = parse_module_bytes(&bytes).assemble();
+                serialized_modules.push(SerializedModule::Local(SpirvModuleBuffer(words)));
+                module_names.push(CString::new(format!("{rlib_name}.{file_name}")).unwrap());
+            }
+        }
     }
 
+    let copy_jobs = cached_modules
+        .into_iter()
+        .map(|(_, work_product)| work_product)
+        .collect();
+
     let shared = Arc::new(ThinShared {
         data: (),
         thin_buffers,
@@ -678,5 +816,99 @@ pub(crate) fn run_thin(
         }));
     }
 
-    Ok((opt_jobs, vec![]))
+    Ok((opt_jobs, copy_jobs))
+}
+
+/// Like `read_rlib_modules`, but reporting errors through `cgcx`.
+fn read_rlib_modules_for_lto(
+    cgcx: &CodegenContext<SpirCodegenBackend>,
+    rlib: &Path,
+) -> Result<Vec<(String, Vec<u8>)>, FatalError> {
+    read_rlib_modules(rlib).map_err(|e| {
+        cgcx.create_dcx()
+            .err(format!("failed to read `{}` for LTO: {e}", rlib.display()));
+        FatalError
+    })
+}
+
+/// Fat LTO: merges all the modules, including the ones from upstream crates
+/// (which the final link then skips, see `add_upstream_rust_crates`), into one,
+/// removing everything unreachable from its entry-points and exports.
+///
+/// Resolving imports, and anything depending on it (e.g. inlining across
+/// crates), is left to the final link (see `do_link`), which always works on
+/// the whole program anyway, and also has access to the linker `Options`.
+pub(crate) fn run_fat(
+    cgcx: &CodegenContext<SpirCodegenBackend>,
+    modules: Vec<FatLtoInput<SpirCodegenBackend>>,
+    cached_modules: Vec<(SerializedModule<SpirvModuleBuffer>, WorkProduct)>,
+) -> Result<LtoModuleCodegen<SpirCodegenBackend>, FatalError> {
+    // NOTE empty modules are only produced by `codegen_allocator`.
+    let mut name = None;
+    let mut inputs = vec![];
+    for module in modules {
+        match module {
+            FatLtoInput::InMemory(module) => {
+                if !module.module_llvm.is_empty() {
+                    inputs.push(parse_module_words(&module.module_llvm));
+                }
+                name.get_or_insert(module.name);
+            }
+            FatLtoInput::Serialized {
+                name: module_name,
+                buffer,
+            } => {
+                if !buffer.data().is_empty() {
+                    inputs.push(parse_module_bytes(buffer.data()));
+                }
+                name.get_or_insert(module_name);
+            }
+        }
+    }
+    for (module, work_product) in &cached_modules {
+        inputs.push(parse_module_bytes(module.data()));
+        name.get_or_insert_with(|| work_product.cgu_name.clone());
+    }
+    for (_, rlib) in &cgcx.each_linked_rlib_for_lto {
+        for (_, bytes) in read_rlib_modules_for_lto(cgcx, rlib)? {
+            inputs.push(parse_module_bytes(&bytes));
+        }
+    }
+
+    let mut module = merge_modules(&cgcx.create_dcx(), inputs)?;
+    linker::dce(&mut module);
+    Ok(LtoModuleCodegen::Fat {
+        module: ModuleCodegen {
+            name: name.unwrap(),
+            module_llvm: module.assemble(),
+            kind: ModuleKind::Regular,
+        },
+        _serialized_bitcode: vec![],
+    })
+}
+
+/// Merges all of `modules` into one (e.g. for `-Z combine-cgu`).
+pub(crate) fn run_link(
+    diag_handler: &DiagCtxt,
+    modules: Vec<ModuleCodegen<Vec<u32>>>,
+) -> Result<ModuleCodegen<Vec<u32>>, FatalError> {
+    let name = modules[0].name.clone();
+    let inputs = modules
+        .iter()
+        .filter(|module| !module.module_llvm.is_empty())
+        .map(|module| parse_module_words(&module.module_llvm))
+        .collect();
+    let module = merge_modules(diag_handler, inputs)?;
+    Ok(ModuleCodegen {
+        name,
+        module_llvm: module.assemble(),
+        kind: ModuleKind::Regular,
+    })
+}
+
+fn merge_modules(diag_handler: &DiagCtxt, inputs: Vec<Module>) -> Result<Module, FatalError> {
+    linker::merge(inputs).map_err(|e| {
+        diag_handler.err(e);
+        FatalError
+    })
 }
@@ -0,0 +1,209 @@
+//! Propagation of `#[spirv(atomic_scope(...))]` to the functions called from
+//! the function it's applied to (e.g. `core::sync::atomic` methods).
+//!
+//! Atomic operations (and fences) outside of such functions use a placeholder
+//! `Device` scope constant (see `DefaultAtomicScopeDecoration`), and for every
+//! scope, any function using that placeholder (directly, or through its own
+//! callees) gets copied, with the placeholder replaced by that scope.
+
+use rspirv::{
+    dr::{Function, Instruction, Module, Operand},
+    spirv::{Decoration, Op, Scope, Word},
+};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+
+use crate::custom_decorations::{
+    AtomicScopeDecoration, CustomDecoration, DefaultAtomicScopeDecoration,
+};
+
+pub fn propagate_atomic_scopes(module: &mut Module) {
+    let default_scope_ids: FxHashSet<Word> = DefaultAtomicScopeDecoration::decode_all(module)
+        .map(|(id, _)| id)
+        .collect();
+    let fn_scopes: FxHashMap<Word, Scope> = AtomicScopeDecoration::decode_all(module)
+        .map(|(id, decoration)| (id, decoration.decode().scope))
+        .collect();
+    DefaultAtomicScopeDecoration::remove_all(module);
+    AtomicScopeDecoration::remove_all(module);
+
+    let Some(scope_ty) = module
+        .types_global_values
+        .iter()
+        .find(|inst| {
+            inst.result_id
+                .is_some_and(|id| default_scope_ids.contains(&id))
+        })
+        .and_then(|inst| inst.result_type)
+    else {
+        return;
+    };
+
+    let func_id_to_idx: FxHashMap<Word, usize> = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(idx, func)| (func.def_id().unwrap(), idx))
+        .collect();
+
+    // Functions using the default scope, directly or through their callees
+    // (other than those with their own `#[spirv(atomic_scope(...))]`).
+    let mut uses_default_scope: FxHashSet<Word> = module
+        .functions
+        .iter()
+        .filter(|func| {
+            func.all_inst_iter().any(|inst| {
+                inst.operands.iter().any(|op| {
+                    op.id_ref_any()
+                        .is_some_and(|id| default_scope_ids.contains(&id))
+                })
+            })
+        })
+        .map(|func| func.def_id().unwrap())
+        .collect();
+    loop {
+        let mut changed = false;
+        for func in &module.functions {
+            let func_id = func.def_id().unwrap();
+            if uses_default_scope.contains(&func_id) {
+                continue;
+            }
+            let calls_any = calls(func).any(|callee| {
+                !fn_scopes.contains_key(&callee) && uses_default_scope.contains(&callee)
+            });
+            if calls_any {
+                uses_default_scope.insert(func_id);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut scope_ids = FxHashMap::default();
+    let mut copies = FxHashMap::default();
+    // NOTE `Device` is the default scope already, so it needs no copies.
+    let mut queue: Vec<(usize, Scope)> = fn_scopes
+        .iter()
+        .filter(|&(_, &scope)| scope != Scope::Device)
+        .filter_map(|(func_id, &scope)| Some((*func_id_to_idx.get(func_id)?, scope)))
+        .collect();
+    // Sorted only to keep the output deterministic.
+    queue.sort_by_key(|&(idx, _)| idx);
+    while let Some((func_idx, scope)) = queue.pop() {
+        let callees: Vec<Word> = calls(&module.functions[func_idx])
+            .filter(|callee| !fn_scopes.contains_key(callee) && uses_default_scope.contains(callee))
+            .collect();
+        for callee in callees {
+            if copies.contains_key(&(callee, scope)) {
+                continue;
+            }
+            let scope_id = *scope_ids
+                .entry(scope)
+                .or_insert_with(|| scope_constant(module, scope_ty, scope));
+            let copy_idx = copy_function(
+                module,
+                func_id_to_idx[&callee],
+                &default_scope_ids,
+                scope_id,
+            );
+            copies.insert(
+                (callee, scope),
+                module.functions[copy_idx].def_id().unwrap(),
+            );
+            queue.push((copy_idx, scope));
+        }
+
+        for inst in all_calls_mut(&mut module.functions[func_idx]) {
+            let callee = &mut inst.operands[0];
+            if let Some(&copy) = copies.get(&(callee.unwrap_id_ref(), scope)) {
+                *callee = Operand::IdRef(copy);
+            }
+        }
+    }
+}
+
+fn calls(func: &Function) -> impl Iterator<Item = Word> + '_ {
+    func.all_inst_iter()
+        .filter(|inst| inst.class.opcode == Op::FunctionCall)
+        .map(|inst| inst.operands[0].unwrap_id_ref())
+}
+
+fn all_calls_mut(func: &mut Function) -> impl Iterator<Item = &mut Instruction> {
+    func.all_inst_iter_mut()
+        .filter(|inst| inst.class.opcode == Op::FunctionCall)
+}
+
+fn next_id(module: &mut Module) -> Word {
+    let bound = &mut module.header.as_mut().unwrap().bound;
+    let id = *bound;
+    *bound += 1;
+    id
+}
+
+fn scope_constant(module: &mut Module, scope_ty: Word, scope: Scope) -> Word {
+    let id = next_id(module);
+    module.types_global_values.push(Instruction::new(
+        Op::Constant,
+        Some(scope_ty),
+        Some(id),
+        vec![Operand::LiteralInt32(scope as u32)],
+    ));
+    id
+}
+
+/// Adds a copy of `module.functions[func_idx]` (with fresh IDs, and `scope_id`
+/// replacing `default_scope_ids`), returning the index of the new function.
+fn copy_function(
+    module: &mut Module,
+    func_idx: usize,
+    default_scope_ids: &FxHashSet<Word>,
+    scope_id: Word,
+) -> usize {
+    let mut func = module.functions[func_idx].clone();
+    let rewrite_rules: FxHashMap<Word, Word> = func
+        .all_inst_iter()
+        .filter_map(|inst| inst.result_id)
+        .map(|id| (id, next_id(module)))
+        .collect();
+
+    for inst in func.all_inst_iter_mut() {
+        if let Some(id) = &mut inst.result_id {
+            *id = rewrite_rules[id];
+        }
+        for op in &mut inst.operands {
+            if let Some(id) = op.id_ref_any_mut() {
+                if let Some(&rewrite) = rewrite_rules.get(id) {
+                    *id = rewrite;
+                } else if default_scope_ids.contains(id) {
+                    *id = scope_id;
+                }
+            }
+        }
+    }
+
+    // Decorations (including zombies) and names apply to the copy as well,
+    // except for linkage, which only the original function can keep.
+    let copy_annotations = |insts: &[Instruction]| {
+        insts
+            .iter()
+            .filter(|inst| {
+                !(inst.class.opcode == Op::Decorate
+                    && inst.operands[1].unwrap_decoration() == Decoration::LinkageAttributes)
+            })
+            .filter_map(|inst| {
+                let &rewrite = rewrite_rules.get(&inst.operands.first()?.id_ref_any()?)?;
+                let mut inst = inst.clone();
+                inst.operands[0] = Operand::IdRef(rewrite);
+                Some(inst)
+            })
+            .collect::<Vec<_>>()
+    };
+    let annotations = copy_annotations(&module.annotations);
+    let debug_names = copy_annotations(&module.debug_names);
+    module.annotations.extend(annotations);
+    module.debug_names.extend(debug_names);
+
+    module.functions.push(func);
+    module.functions.len() - 1
+}
@@ -0,0 +1,339 @@
+//! Computing the capabilities (and extensions) a linked module needs, on top of
+//! the ones it declares (as codegen only declares what was explicitly enabled,
+//! or is always needed, but may use anything inferable, see `CodegenCx::new`).
+
+use rspirv::{
+    dr::{Instruction, Module, Operand},
+    spirv::{Capability, Op, Word},
+};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet, FxIndexMap, FxIndexSet};
+use rustc_session::Session;
+
+use super::{get_name, get_names, Options, Result};
+use crate::{
+    target::SpirvTarget,
+    target_feature::{core_version, enabling_extensions, session_target_features, TargetFeature},
+};
+
+/// Capabilities and extensions needed by one instruction (or, for global
+/// definitions, also by every global definition they refer to).
+#[derive(Clone, Default)]
+struct Needs {
+    caps: FxIndexSet<Capability>,
+    exts: FxIndexSet<&'static str>,
+}
+
+/// Every capability/extension needed by the module, with the functions that
+/// needed it (`None` standing for module-level definitions).
+type Users<K> = FxIndexMap<K, FxIndexSet<Option<Word>>>;
+
+struct Inference {
+    /// Capabilities that were explicitly enabled, declared, or are inferable, used
+    /// to pick between alternatives (e.g. operands enabled by several capabilities).
+    allowed_caps: FxHashSet<Capability>,
+
+    /// Extensions that were explicitly enabled, or already present in the module.
+    preferred_exts: FxHashSet<String>,
+
+    /// Widths of all `OpTypeFloat`s, for instructions needing different
+    /// capabilities depending on their type (e.g. `OpAtomicFAddEXT`).
+    float_widths: FxHashMap<Word, u32>,
+}
+
+impl Inference {
+    fn need_any_cap(&self, needs: &mut Needs, alternatives: &[Capability]) {
+        let preferred = alternatives
+            .iter()
+            .find(|cap| needs.caps.contains(*cap) || self.allowed_caps.contains(*cap));
+        if let Some(&cap) = preferred.or(alternatives.first()) {
+            needs.caps.insert(cap);
+        }
+    }
+
+    fn need_any_ext(&self, needs: &mut Needs, alternatives: &[&'static str]) {
+        let preferred = alternatives
+            .iter()
+            .find(|&&ext| needs.exts.contains(ext) || self.preferred_exts.contains(ext));
+        if let Some(&ext) = preferred.or(alternatives.first()) {
+            needs.exts.insert(ext);
+        }
+    }
+
+    fn inst_needs(&self, inst: &Instruction) -> Needs {
+        let mut needs = Needs::default();
+        let float_width = inst
+            .result_type
+            .and_then(|ty| self.float_widths.get(&ty).copied());
+        let class_caps = match (inst.class.opcode, float_width) {
+            (Op::AtomicFAddEXT, Some(32)) => &[Capability::AtomicFloat32AddEXT][..],
+            (Op::AtomicFAddEXT, Some(64)) => &[Capability::AtomicFloat64AddEXT][..],
+            _ => inst.class.capabilities,
+        };
+        self.need_any_cap(&mut needs, class_caps);
+        self.need_any_ext(&mut needs, inst.class.extensions);
+
+        // The grammar doesn't describe the capabilities needed by type widths.
+        match inst.class.opcode {
+            Op::TypeInt => match inst.operands[0].unwrap_literal_int32() {
+                8 => self.need_any_cap(&mut needs, &[Capability::Int8]),
+                16 => self.need_any_cap(&mut needs, &[Capability::Int16]),
+                64 => self.need_any_cap(&mut needs, &[Capability::Int64]),
+                _ => {}
+            },
+            Op::TypeFloat => match inst.operands[0].unwrap_literal_int32() {
+                16 => self.need_any_cap(&mut needs, &[Capability::Float16]),
+                64 => self.need_any_cap(&mut needs, &[Capability::Float64]),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        for operand in &inst.operands {
+            self.need_any_cap(&mut needs, &operand.required_capabilities());
+            self.need_any_ext(&mut needs, &operand.required_extensions());
+        }
+        needs
+    }
+}
+
+fn ids_used_by(inst: &Instruction) -> impl Iterator<Item = Word> + '_ {
+    inst.result_type
+        .into_iter()
+        .chain(inst.operands.iter().filter_map(|op| op.id_ref_any()))
+}
+
+/// Adds the capabilities and extensions `module` needs to the ones it declares
+/// (which are always kept), erroring if it needs capabilities that are neither
+/// declared, explicitly enabled, nor inferable (see `Options::inferable_capabilities`).
+pub fn infer_capabilities_and_extensions(
+    sess: &Session,
+    module: &mut Module,
+    opts: &Options,
+) -> Result<()> {
+    let print_inferred = opts.print_inferred_capabilities;
+    let target: SpirvTarget = sess.target.llvm_target.parse().unwrap();
+    let explicit = session_target_features(sess, &target);
+    let explicit_caps = explicit
+        .iter()
+        .filter_map(|feature| match *feature {
+            TargetFeature::Capability(cap) => Some(cap),
+            TargetFeature::Extension(_) | TargetFeature::Profile(_) => None,
+        })
+        .collect::<FxIndexSet<_>>();
+    let explicit_exts = explicit
+        .iter()
+        .filter_map(|feature| match *feature {
+            TargetFeature::Extension(ext) => Some(ext.as_str().to_string()),
+            TargetFeature::Capability(_) | TargetFeature::Profile(_) => None,
+        })
+        .collect::<FxIndexSet<_>>();
+    let declared_caps = module
+        .capabilities
+        .iter()
+        .map(|inst| inst.operands[0].unwrap_capability())
+        .collect::<FxIndexSet<_>>();
+
+    // Whatever the target's memory/addressing model needs, and `Linkage` (which
+    // only depends on how the module is going to be used), are always allowed.
+    let mut allowed_caps = explicit_caps
+        .iter()
+        .chain(&declared_caps)
+        .chain(opts.inferable_capabilities(&target))
+        .copied()
+        .chain([Capability::Linkage])
+        .chain(
+            module
+                .memory_model
+                .iter()
+                .flat_map(|inst| &inst.operands)
+                .flat_map(|operand| operand.required_capabilities()),
+        )
+        .collect::<Vec<_>>();
+    let mut i = 0;
+    while let Some(&cap) = allowed_caps.get(i) {
+        i += 1;
+        for implied in Operand::Capability(cap).required_capabilities() {
+            if !allowed_caps.contains(&implied) {
+                allowed_caps.push(implied);
+            }
+        }
+    }
+    let allowed_caps = allowed_caps.into_iter().collect::<FxHashSet<_>>();
+
+    let declared_exts = module
+        .extensions
+        .iter()
+        .map(|inst| inst.operands[0].unwrap_literal_string().to_string())
+        .collect::<FxIndexSet<_>>();
+    let preferred_exts = explicit_exts
+        .iter()
+        .chain(&declared_exts)
+        .cloned()
+        .collect();
+    let float_widths = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::TypeFloat)
+        .map(|inst| {
+            (
+                inst.result_id.unwrap(),
+                inst.operands[0].unwrap_literal_int32(),
+            )
+        })
+        .collect();
+    let inference = Inference {
+        allowed_caps,
+        preferred_exts,
+        float_widths,
+    };
+
+    let mut caps: Users<Capability> = FxIndexMap::default();
+    let mut exts: Users<&'static str> = FxIndexMap::default();
+    let mut add_needs = |needs: &Needs, user: Option<Word>| {
+        for &cap in &needs.caps {
+            caps.entry(cap).or_default().insert(user);
+        }
+        for &ext in &needs.exts {
+            exts.entry(ext).or_default().insert(user);
+        }
+    };
+
+    // Global definitions (types, constants, variables etc.) are attributed to
+    // the functions using them (or the module itself, if none does).
+    let mut global_needs: FxIndexMap<Word, Needs> = FxIndexMap::default();
+    let mut globals_used_by_functions = FxHashSet::default();
+    let global_insts = module
+        .ext_inst_imports
+        .iter()
+        .chain(&module.memory_model)
+        .chain(&module.entry_points)
+        .chain(&module.execution_modes)
+        .chain(&module.annotations)
+        .chain(&module.types_global_values);
+    for inst in global_insts {
+        let mut needs = inference.inst_needs(inst);
+        for id in ids_used_by(inst) {
+            if let Some(used) = global_needs.get(&id) {
+                needs.caps.extend(used.caps.iter().copied());
+                needs.exts.extend(used.exts.iter().copied());
+            }
+        }
+        match inst.result_id {
+            Some(id) => {
+                global_needs.insert(id, needs);
+            }
+            None => add_needs(&needs, None),
+        }
+    }
+    for func in &module.functions {
+        let func_id = func.def_id().unwrap();
+        for inst in func.all_inst_iter() {
+            add_needs(&inference.inst_needs(inst), Some(func_id));
+            for id in ids_used_by(inst) {
+                if let Some(used) = global_needs.get(&id) {
+                    globals_used_by_functions.insert(id);
+                    add_needs(used, Some(func_id));
+                }
+            }
+        }
+    }
+    for (id, needs) in &global_needs {
+        if !globals_used_by_functions.contains(id) {
+            add_needs(needs, None);
+        }
+    }
+
+    // Capabilities implied by the needed ones are needed by the same users.
+    let mut i = 0;
+    while let Some((&cap, users)) = caps.get_index(i) {
+        i += 1;
+        let users = users.clone();
+        for implied in Operand::Capability(cap).required_capabilities() {
+            caps.entry(implied)
+                .or_default()
+                .extend(users.iter().copied());
+        }
+    }
+
+    // Anything declared (e.g. through `global_asm!`) or explicitly enabled is
+    // kept, even if it seems unused (as not all uses of capabilities can be
+    // found, e.g. `StorageBuffer8BitAccess`).
+    for &cap in declared_caps.iter().chain(&explicit_caps) {
+        caps.entry(cap).or_default();
+    }
+
+    let names = get_names(module);
+    let describe_users = |users: &FxIndexSet<Option<Word>>| {
+        users
+            .iter()
+            .map(|&user| match user {
+                Some(func_id) => format!("`{}`", get_name(&names, func_id)),
+                None => "module-level definitions".to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut result = Ok(());
+    for (&cap, users) in &caps {
+        if !inference.allowed_caps.contains(&cap) {
+            let mut err = sess.psess.dcx.struct_err(format!(
+                "the `{cap:?}` capability is required, but target `{target}` doesn't \
+                 allow inferring it"
+            ));
+            for user in describe_users(users) {
+                err.note(format!("required by {user}"));
+            }
+            err.help(format!(
+                "enable it explicitly with `-Ctarget-feature=+{cap:?}`"
+            ));
+            result = Err(err.emit());
+        } else if print_inferred && !explicit_caps.contains(&cap) && !declared_caps.contains(&cap) {
+            eprintln!(
+                "inferred capability `{cap:?}`, required by {}",
+                describe_users(users).join(", ")
+            );
+        }
+    }
+    result?;
+
+    // Capabilities outside of the target's SPIR-V version need an extension.
+    for (&cap, users) in &caps {
+        if core_version(cap).is_some_and(|version| target.spirv_version() >= version) {
+            continue;
+        }
+        let mut needs = Needs::default();
+        inference.need_any_ext(&mut needs, &enabling_extensions(cap));
+        let users = users.clone();
+        for ext in needs.exts {
+            exts.entry(ext).or_default().extend(users.iter().copied());
+        }
+    }
+    if print_inferred {
+        for (ext, users) in &exts {
+            if !explicit_exts.contains(*ext) && !declared_exts.contains(*ext) {
+                eprintln!(
+                    "inferred extension `{ext}`, required by {}",
+                    describe_users(users).join(", ")
+                );
+            }
+        }
+    }
+
+    // Declared and explicitly requested extensions are always kept.
+    let kept_exts = declared_exts
+        .into_iter()
+        .chain(explicit_exts.iter().cloned())
+        .chain(exts.keys().map(|ext| ext.to_string()))
+        .collect::<FxIndexSet<_>>();
+
+    module.capabilities = caps
+        .keys()
+        .map(|&cap| Instruction::new(Op::Capability, None, None, vec![Operand::Capability(cap)]))
+        .collect();
+    module.extensions = kept_exts
+        .into_iter()
+        .map(|ext| Instruction::new(Op::Extension, None, None, vec![Operand::LiteralString(ext)]))
+        .collect();
+
+    Ok(())
+}
@@ -0,0 +1,818 @@
+//! Devirtualization of indirect calls, i.e. `OpFunctionCall`s with a function
+//! pointer (instead of a function) as their callee, which SPIR-V doesn't allow.
+//!
+//! Codegen represents function pointers as `OpVariable`s in the
+//! `CodeSectionINTEL` storage class, with the function as their initializer,
+//! and this pass replaces them with integer "function IDs" (of the same size as
+//! pointers, to keep the memory layout Rust expects), and every indirect call
+//! with an `OpSwitch` over the IDs of the functions it could be calling, each
+//! case being a direct call to one of those functions.
+//!
+//! The functions a pointer could be pointing to are found by unifying function
+//! pointer values that flow into each other (in the same style as the inference
+//! variables of `specializer`), with a fallback to all functions of the right
+//! type (that have their address taken, by more than just direct calls) for
+//! pointers of unknown origin (e.g. loaded from memory, such as the methods in
+//! `dyn Trait` vtables).
+//!
+//! Data pointer types that are only ever used to hold function pointers (e.g.
+//! the `*const ()` entries of vtables, which are never dereferenced) are also
+//! replaced with the same integer type, while any other casts between function
+//! pointers and data pointers require actual pointer<->integer conversions.
+//!
+//! Anything that can't be devirtualized (such as indirect calls without any
+//! possible callees) is marked as a zombie, so that it only causes errors if
+//! it's actually used (see `zombies`), as e.g. `core::fmt` is full of dead code
+//! which would otherwise prevent linking entirely.
+
+use std::mem;
+
+use rspirv::{
+    dr::{Block, Instruction, Module, ModuleHeader, Operand},
+    spirv::{AddressingModel, Decoration, Op, StorageClass, Word},
+};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet, FxIndexMap, FxIndexSet};
+use rustc_session::Session;
+
+use super::{duplicates, inline::rewrite_phi_sources};
+use crate::custom_decorations::{CustomDecoration, ZombieDecoration};
+
+fn next_id(header: &mut ModuleHeader) -> Word {
+    let result = header.bound;
+    header.bound += 1;
+    result
+}
+
+/// The functions a (set of unified) function pointer value(s) could point to.
+#[derive(Clone)]
+enum Candidates {
+    /// Exactly these functions (or none at all, e.g. for `OpConstantNull`).
+    Exactly(FxIndexSet<Word>),
+
+    /// Any function of the right type, that has its address taken anywhere.
+    AnyOfType,
+}
+
+impl Candidates {
+    fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Exactly(mut a), Self::Exactly(b)) => {
+                a.extend(b);
+                Self::Exactly(a)
+            }
+            (Self::AnyOfType, _) | (_, Self::AnyOfType) => Self::AnyOfType,
+        }
+    }
+}
+
+enum FlowValue {
+    /// Unified with another value (which may itself be `SameAs` a third one).
+    SameAs(Word),
+
+    /// Representative value for all the values unified with it.
+    Root(Candidates),
+}
+
+/// Union-find over function pointer values (and functions, standing in for
+/// the values they return), where any value missing from `values` is implicitly
+/// a `FlowValue::Root` with no `Candidates`.
+#[derive(Default)]
+struct Flow {
+    values: FxHashMap<Word, FlowValue>,
+}
+
+impl Flow {
+    /// Traverse `SameAs` chains starting at `v` and return the `Root` value.
+    /// This corresponds to `find(v)` from union-find.
+    fn resolve(&mut self, v: Word) -> Word {
+        match self.values.get(&v) {
+            None | Some(FlowValue::Root(_)) => v,
+            Some(&FlowValue::SameAs(next)) => {
+                let resolved = self.resolve(next);
+                if resolved != next {
+                    // Update the `SameAs` entry for faster lookup next time
+                    // (also known as "path compression" in union-find).
+                    self.values.insert(v, FlowValue::SameAs(resolved));
+                }
+                resolved
+            }
+        }
+    }
+
+    fn take_root_candidates(&mut self, root: Word) -> Candidates {
+        match self.values.remove(&root) {
+            None => Candidates::Exactly(FxIndexSet::default()),
+            Some(FlowValue::Root(candidates)) => candidates,
+            Some(FlowValue::SameAs(_)) => unreachable!(),
+        }
+    }
+
+    fn add_candidates(&mut self, v: Word, candidates: Candidates) {
+        let root = self.resolve(v);
+        let candidates = self.take_root_candidates(root).union(candidates);
+        self.values.insert(root, FlowValue::Root(candidates));
+    }
+
+    /// Enforce that `a` and `b` have the same `Candidates`.
+    /// This corresponds to `union(a, b)` from union-find.
+    fn equate(&mut self, a: Word, b: Word) {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        if a == b {
+            return;
+        }
+        let a_candidates = self.take_root_candidates(a);
+        let b_candidates = self.take_root_candidates(b);
+        self.values.insert(b, FlowValue::SameAs(a));
+        self.values
+            .insert(a, FlowValue::Root(a_candidates.union(b_candidates)));
+    }
+
+    fn candidates(&mut self, v: Word) -> Candidates {
+        let root = self.resolve(v);
+        match self.values.get(&root) {
+            None => Candidates::Exactly(FxIndexSet::default()),
+            Some(FlowValue::Root(candidates)) => candidates.clone(),
+            Some(FlowValue::SameAs(_)) => unreachable!(),
+        }
+    }
+}
+
+fn is_fn_addr(inst: &Instruction) -> bool {
+    inst.class.opcode == Op::Variable
+        && inst.operands[0] == Operand::StorageClass(StorageClass::CodeSectionINTEL)
+}
+
+/// Literal operand for the function ID `fn_id`, when `fn_id_width` bits wide.
+fn fn_id_literal(fn_id_width: u32, fn_id: u32) -> Operand {
+    match fn_id_width {
+        64 => Operand::LiteralInt64(fn_id.into()),
+        _ => Operand::LiteralInt32(fn_id),
+    }
+}
+
+fn zombie(id: Word, reason: &'static str) -> Instruction {
+    ZombieDecoration {
+        reason: reason.into(),
+    }
+    .encode_to_inst(id)
+}
+
+pub fn devirtualize(sess: &Session, module: &mut Module) {
+    let fn_types: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::TypeFunction)
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+    // The function type pointed to, for every function pointer type.
+    let fn_ptr_types: FxHashMap<Word, Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| {
+            inst.class.opcode == Op::TypePointer
+                && fn_types.contains(&inst.operands[1].unwrap_id_ref())
+        })
+        .map(|inst| (inst.result_id.unwrap(), inst.operands[1].unwrap_id_ref()))
+        .collect();
+    if fn_ptr_types.is_empty() {
+        return;
+    }
+
+    remove_unused_fn_addrs(module);
+
+    let fn_id_width = sess.target.pointer_width;
+    let addressing_model =
+        module.memory_model.as_ref().unwrap().operands[0].unwrap_addressing_model();
+
+    // Every function that has its address taken, with its integer ID, which
+    // start at `1` so that `0` can keep being the null function pointer.
+    let mut fn_addr_ids = FxIndexMap::default();
+    for inst in &module.types_global_values {
+        if is_fn_addr(inst) {
+            let next_fn_addr_id = fn_addr_ids.len() as u32 + 1;
+            fn_addr_ids
+                .entry(inst.operands[1].unwrap_id_ref())
+                .or_insert(next_fn_addr_id);
+        }
+    }
+    let func_types: FxHashMap<Word, Word> = module
+        .functions
+        .iter()
+        .map(|func| {
+            let def = func.def.as_ref().unwrap();
+            (def.result_id.unwrap(), def.operands[1].unwrap_id_ref())
+        })
+        .collect();
+    // NOTE `OpFunction` is excluded, as its "type" is its return type.
+    let value_types: FxHashMap<Word, Word> = module
+        .all_inst_iter()
+        .filter(|inst| inst.class.opcode != Op::Function)
+        .filter_map(|inst| Some((inst.result_id?, inst.result_type?)))
+        .collect();
+    let void_types: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::TypeVoid)
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+
+    let fn_id_types = collect_fn_id_types(module, &fn_ptr_types, &value_types);
+    let mut flow = collect_flow(module, &fn_id_types, &fn_addr_ids);
+
+    let mut zombies = vec![];
+    let header = module.header.as_mut().unwrap();
+    for func in &mut module.functions {
+        let (mut block_idx, mut inst_idx) = (0, 0);
+        while block_idx < func.blocks.len() {
+            let call_idx = func.blocks[block_idx].instructions[inst_idx..]
+                .iter()
+                .position(|inst| {
+                    inst.class.opcode == Op::FunctionCall
+                        && !func_types.contains_key(&inst.operands[0].unwrap_id_ref())
+                });
+            let call_idx = match call_idx {
+                Some(call_idx) => inst_idx + call_idx,
+                None => {
+                    block_idx += 1;
+                    inst_idx = 0;
+                    continue;
+                }
+            };
+            inst_idx = call_idx + 1;
+
+            let call = &mut func.blocks[block_idx].instructions[call_idx];
+            let callee = call.operands[0].unwrap_id_ref();
+            let fn_ty = fn_ptr_types[&value_types[&callee]];
+            let has_fn_ty = |func_id: &Word| func_types[func_id] == fn_ty;
+            let mut candidates: Vec<Word> = match flow.candidates(callee) {
+                Candidates::Exactly(funcs) => funcs.into_iter().filter(has_fn_ty).collect(),
+                Candidates::AnyOfType => fn_addr_ids.keys().copied().filter(has_fn_ty).collect(),
+            };
+            candidates.sort_by_key(|func_id| fn_addr_ids[func_id]);
+
+            match candidates[..] {
+                [] => zombies.push(zombie(
+                    call.result_id.unwrap(),
+                    "indirect call with no possible callees (no function of the right type \
+                     has its address taken, in a way that can reach the call)",
+                )),
+                // No need for an `OpSwitch` with only one possible callee.
+                [only_candidate] => call.operands[0] = Operand::IdRef(only_candidate),
+                _ => {
+                    let is_void = void_types.contains(&call.result_type.unwrap());
+                    block_idx = split_indirect_call(
+                        header,
+                        &mut func.blocks,
+                        (block_idx, call_idx),
+                        &candidates,
+                        |func_id| fn_id_literal(fn_id_width, fn_addr_ids[&func_id]),
+                        is_void,
+                    );
+                    inst_idx = 0;
+                }
+            }
+        }
+    }
+
+    rewrite_fn_id_uses(
+        module,
+        &fn_id_types,
+        &value_types,
+        fn_id_width,
+        addressing_model,
+        &mut zombies,
+    );
+
+    // Function pointers become integers, and their constants the IDs, while
+    // data pointer constants need an actual integer to pointer conversion.
+    let header = module.header.as_mut().unwrap();
+    let mut fn_id_int_type = None;
+    for mut inst in mem::take(&mut module.types_global_values) {
+        if inst.class.opcode == Op::TypePointer && fn_id_types.contains(&inst.result_id.unwrap()) {
+            inst = Instruction::new(
+                Op::TypeInt,
+                None,
+                inst.result_id,
+                vec![Operand::LiteralInt32(fn_id_width), Operand::LiteralInt32(0)],
+            );
+        } else if is_fn_addr(&inst) {
+            let fn_id = fn_id_literal(fn_id_width, fn_addr_ids[&inst.operands[1].unwrap_id_ref()]);
+            if fn_id_types.contains(&inst.result_type.unwrap()) {
+                inst =
+                    Instruction::new(Op::Constant, inst.result_type, inst.result_id, vec![fn_id]);
+            } else if let AddressingModel::Physical32 | AddressingModel::Physical64 =
+                addressing_model
+            {
+                let int_type = *fn_id_int_type.get_or_insert_with(|| {
+                    let int_type = next_id(header);
+                    module.types_global_values.push(Instruction::new(
+                        Op::TypeInt,
+                        None,
+                        Some(int_type),
+                        vec![Operand::LiteralInt32(fn_id_width), Operand::LiteralInt32(0)],
+                    ));
+                    int_type
+                });
+                let int_fn_id = next_id(header);
+                module.types_global_values.push(Instruction::new(
+                    Op::Constant,
+                    Some(int_type),
+                    Some(int_fn_id),
+                    vec![fn_id],
+                ));
+                inst = Instruction::new(
+                    Op::SpecConstantOp,
+                    inst.result_type,
+                    inst.result_id,
+                    vec![
+                        Operand::LiteralSpecConstantOpInteger(Op::ConvertUToPtr),
+                        Operand::IdRef(int_fn_id),
+                    ],
+                );
+            } else {
+                zombies.push(zombie(
+                    inst.result_id.unwrap(),
+                    "function pointers can only be used as data pointer constants with \
+                     `Physical32`/`Physical64` addressing",
+                ));
+            }
+        }
+        module.types_global_values.push(inst);
+    }
+    // NOTE integer types can't keep the `ArrayStride` of the pointer types they
+    // replaced (see `decorate_pointer_stride`), and likely duplicate existing ones.
+    module.annotations.retain(|inst| {
+        !(inst.class.opcode == Op::Decorate
+            && inst.operands[1].unwrap_decoration() == Decoration::ArrayStride
+            && fn_id_types.contains(&inst.operands[0].unwrap_id_ref()))
+    });
+    duplicates::remove_duplicate_types(module);
+
+    module.annotations.extend(zombies);
+}
+
+/// Remove every function pointer constant that isn't used by anything, as
+/// codegen creates one for every direct call as well (see `get_fn_addr`), and
+/// their functions shouldn't count as having their address taken.
+fn remove_unused_fn_addrs(module: &mut Module) {
+    let used_ids: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .chain(
+            module
+                .functions
+                .iter()
+                .flat_map(|func| func.all_inst_iter()),
+        )
+        .flat_map(|inst| inst.operands.iter().filter_map(|op| op.id_ref_any()))
+        .collect();
+    let unused: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| is_fn_addr(inst) && !used_ids.contains(&inst.result_id.unwrap()))
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+    if unused.is_empty() {
+        return;
+    }
+
+    let is_unused = |inst: &Instruction| {
+        let target = inst
+            .result_id
+            .or_else(|| inst.operands.first()?.id_ref_any());
+        target.is_some_and(|id| unused.contains(&id))
+    };
+    module.types_global_values.retain(|inst| !is_unused(inst));
+    module.annotations.retain(|inst| !is_unused(inst));
+    module.debug_names.retain(|inst| !is_unused(inst));
+}
+
+/// Find all the pointer types whose values can only be function IDs, i.e. all
+/// function pointer types, and also data pointer types that hold function
+/// pointers, and are never dereferenced (nor hold anything else).
+fn collect_fn_id_types(
+    module: &Module,
+    fn_ptr_types: &FxHashMap<Word, Word>,
+    value_types: &FxHashMap<Word, Word>,
+) -> FxHashSet<Word> {
+    let ptr_types: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::TypePointer)
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+    let insts = || {
+        module.types_global_values.iter().chain(
+            module
+                .functions
+                .iter()
+                .flat_map(|func| func.all_inst_iter()),
+        )
+    };
+    let used_ids: FxHashSet<Word> = insts()
+        .flat_map(|inst| inst.operands.iter().filter_map(|op| op.id_ref_any()))
+        .collect();
+
+    // HACK pointer casts often leave behind unused `OpBitcast`s, which
+    // get removed later, but shouldn't affect anything until then.
+    let is_unused_bitcast = |inst: &Instruction| {
+        inst.class.opcode == Op::Bitcast && !used_ids.contains(&inst.result_id.unwrap())
+    };
+
+    // Start with data pointer types that function pointers are cast to/from.
+    let mut data_ptr_types = FxHashSet::default();
+    for inst in insts() {
+        if is_fn_addr(inst) {
+            data_ptr_types.insert(inst.result_type.unwrap());
+        } else if inst.class.opcode == Op::Bitcast && !is_unused_bitcast(inst) {
+            let from = value_types[&inst.operands[0].unwrap_id_ref()];
+            let to = inst.result_type.unwrap();
+            for (a, b) in [(from, to), (to, from)] {
+                if fn_ptr_types.contains_key(&a) && ptr_types.contains(&b) {
+                    data_ptr_types.insert(b);
+                }
+            }
+        }
+    }
+    data_ptr_types.retain(|ty| ptr_types.contains(ty) && !fn_ptr_types.contains_key(ty));
+
+    // Then remove any which are used in any other way than passing values
+    // around, until none are left which could be holding data pointers.
+    loop {
+        let is_fn_id_type =
+            |ty: Word| fn_ptr_types.contains_key(&ty) || data_ptr_types.contains(&ty);
+        let mut disqualified = FxHashSet::default();
+        for inst in insts() {
+            if is_unused_bitcast(inst) {
+                continue;
+            }
+            if let Some(ty) = inst.result_type.filter(|ty| data_ptr_types.contains(ty)) {
+                let can_produce = match inst.class.opcode {
+                    Op::Variable => is_fn_addr(inst),
+                    Op::Bitcast => is_fn_id_type(value_types[&inst.operands[0].unwrap_id_ref()]),
+                    Op::Function
+                    | Op::FunctionParameter
+                    | Op::FunctionCall
+                    | Op::ConstantNull
+                    | Op::Undef
+                    | Op::Load
+                    | Op::CompositeExtract
+                    | Op::Phi
+                    | Op::Select
+                    | Op::CopyObject
+                    | Op::ConvertUToPtr => true,
+                    _ => false,
+                };
+                if !can_produce {
+                    disqualified.insert(ty);
+                }
+            }
+            for (i, operand) in inst.operands.iter().enumerate() {
+                let ty = operand.id_ref_any().and_then(|id| value_types.get(&id));
+                if let Some(&ty) = ty.filter(|&&ty| data_ptr_types.contains(&ty)) {
+                    let can_use = match inst.class.opcode {
+                        // Only the value being stored, and the initializer.
+                        Op::Store | Op::Variable => i == 1,
+                        Op::Bitcast => is_fn_id_type(inst.result_type.unwrap()),
+                        Op::FunctionCall
+                        | Op::ReturnValue
+                        | Op::ConstantComposite
+                        | Op::CompositeConstruct
+                        | Op::CompositeInsert
+                        | Op::Phi
+                        | Op::Select
+                        | Op::CopyObject
+                        | Op::PtrEqual
+                        | Op::PtrNotEqual
+                        | Op::ConvertPtrToU => true,
+                        _ => false,
+                    };
+                    if !can_use {
+                        disqualified.insert(ty);
+                    }
+                }
+            }
+        }
+        if disqualified.is_empty() {
+            break;
+        }
+        data_ptr_types.retain(|ty| !disqualified.contains(ty));
+    }
+
+    fn_ptr_types.keys().copied().chain(data_ptr_types).collect()
+}
+
+/// Unify function pointer values which can flow into each other, and collect
+/// the functions they could be pointing to.
+fn collect_flow(
+    module: &Module,
+    fn_id_types: &FxHashSet<Word>,
+    fn_addr_ids: &FxIndexMap<Word, u32>,
+) -> Flow {
+    let is_fn_id = |inst: &Instruction| {
+        inst.result_type
+            .map_or(false, |ty| fn_id_types.contains(&ty))
+    };
+    let fn_id_values: FxHashSet<Word> = module
+        .all_inst_iter()
+        .filter(|inst| inst.class.opcode != Op::Function && (is_fn_id(inst) || is_fn_addr(inst)))
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+    let func_params: FxHashMap<Word, Vec<Word>> = module
+        .functions
+        .iter()
+        .map(|func| {
+            let params = func.parameters.iter().map(|p| p.result_id.unwrap());
+            (func.def_id().unwrap(), params.collect())
+        })
+        .collect();
+
+    // Functions which can be called from elsewhere than the direct calls seen
+    // below, so their parameters could be any function pointers.
+    let mut externally_called: FxHashSet<Word> = fn_addr_ids.keys().copied().collect();
+    externally_called.extend(
+        module
+            .entry_points
+            .iter()
+            .map(|inst| inst.operands[1].unwrap_id_ref()),
+    );
+    externally_called.extend(
+        module
+            .annotations
+            .iter()
+            .filter(|inst| {
+                inst.class.opcode == Op::Decorate
+                    && inst.operands[1].unwrap_decoration() == Decoration::LinkageAttributes
+            })
+            .map(|inst| inst.operands[0].unwrap_id_ref()),
+    );
+
+    let mut flow = Flow::default();
+    for inst in &module.types_global_values {
+        let candidates = match inst.class.opcode {
+            _ if is_fn_addr(inst) => {
+                Candidates::Exactly([inst.operands[1].unwrap_id_ref()].into_iter().collect())
+            }
+            Op::ConstantNull | Op::Undef => continue,
+            _ if is_fn_id(inst) => Candidates::AnyOfType,
+            _ => continue,
+        };
+        flow.add_candidates(inst.result_id.unwrap(), candidates);
+    }
+    for func in &module.functions {
+        let func_id = func.def_id().unwrap();
+        if externally_called.contains(&func_id) {
+            for param in &func.parameters {
+                if is_fn_id(param) {
+                    flow.add_candidates(param.result_id.unwrap(), Candidates::AnyOfType);
+                }
+            }
+        }
+
+        for inst in func.blocks.iter().flat_map(|block| &block.instructions) {
+            let id_operand = |i: usize| inst.operands[i].unwrap_id_ref();
+            match inst.class.opcode {
+                Op::Phi if is_fn_id(inst) => {
+                    for value_and_source in inst.operands.chunks(2) {
+                        flow.equate(inst.result_id.unwrap(), value_and_source[0].unwrap_id_ref());
+                    }
+                }
+                Op::Select if is_fn_id(inst) => {
+                    flow.equate(inst.result_id.unwrap(), id_operand(1));
+                    flow.equate(inst.result_id.unwrap(), id_operand(2));
+                }
+                Op::CopyObject | Op::Bitcast if fn_id_values.contains(&id_operand(0)) => {
+                    if is_fn_id(inst) {
+                        flow.equate(inst.result_id.unwrap(), id_operand(0));
+                    }
+                }
+                Op::FunctionCall => match func_params.get(&id_operand(0)) {
+                    Some(params) => {
+                        for (arg, &param) in inst.operands[1..].iter().zip(params) {
+                            let arg = arg.unwrap_id_ref();
+                            if fn_id_values.contains(&arg) {
+                                flow.equate(arg, param);
+                            }
+                        }
+                        // The callee stands in for the values it returns.
+                        if is_fn_id(inst) {
+                            flow.equate(inst.result_id.unwrap(), id_operand(0));
+                        }
+                    }
+                    None => {
+                        if is_fn_id(inst) {
+                            flow.add_candidates(inst.result_id.unwrap(), Candidates::AnyOfType);
+                        }
+                    }
+                },
+                Op::ReturnValue if fn_id_values.contains(&id_operand(0)) => {
+                    flow.equate(func_id, id_operand(0));
+                }
+                Op::ConstantNull | Op::Undef => {}
+                _ if is_fn_id(inst) => {
+                    flow.add_candidates(inst.result_id.unwrap(), Candidates::AnyOfType);
+                }
+                _ => {}
+            }
+        }
+    }
+    flow
+}
+
+/// Replace the indirect call at `blocks[block_idx].instructions[call_idx]`
+/// with an `OpSwitch` on the function pointer, with direct calls to each of
+/// the `candidates` as its cases, returning the index of the block that has
+/// the instructions which were after the call.
+fn split_indirect_call(
+    header: &mut ModuleHeader,
+    blocks: &mut Vec<Block>,
+    (block_idx, call_idx): (usize, usize),
+    candidates: &[Word],
+    fn_id_literal: impl Fn(Word) -> Operand,
+    is_void: bool,
+) -> usize {
+    let post_call_insts = blocks[block_idx].instructions.split_off(call_idx + 1);
+    let call = blocks[block_idx].instructions.pop().unwrap();
+    assert!(call.class.opcode == Op::FunctionCall);
+    let args = &call.operands[1..];
+
+    let post_call_label_id = next_id(header);
+    let unreachable_label_id = next_id(header);
+    let mut switch_operands = vec![
+        call.operands[0].clone(),
+        Operand::IdRef(unreachable_label_id),
+    ];
+    let mut phi_operands = vec![];
+    let mut new_blocks = vec![];
+    for &candidate in candidates {
+        let case_label_id = next_id(header);
+        let case_result_id = next_id(header);
+        switch_operands.extend([fn_id_literal(candidate), Operand::IdRef(case_label_id)]);
+        phi_operands.extend([
+            Operand::IdRef(case_result_id),
+            Operand::IdRef(case_label_id),
+        ]);
+        new_blocks.push(Block {
+            label: Some(Instruction::new(
+                Op::Label,
+                None,
+                Some(case_label_id),
+                vec![],
+            )),
+            instructions: vec![
+                Instruction::new(
+                    Op::FunctionCall,
+                    call.result_type,
+                    Some(case_result_id),
+                    [Operand::IdRef(candidate)]
+                        .into_iter()
+                        .chain(args.iter().cloned())
+                        .collect(),
+                ),
+                Instruction::new(
+                    Op::Branch,
+                    None,
+                    None,
+                    vec![Operand::IdRef(post_call_label_id)],
+                ),
+            ],
+        });
+    }
+    new_blocks.push(Block {
+        label: Some(Instruction::new(
+            Op::Label,
+            None,
+            Some(unreachable_label_id),
+            vec![],
+        )),
+        instructions: vec![Instruction::new(Op::Unreachable, None, None, vec![])],
+    });
+    blocks[block_idx]
+        .instructions
+        .push(Instruction::new(Op::Switch, None, None, switch_operands));
+
+    let mut post_call_block = Block {
+        label: Some(Instruction::new(
+            Op::Label,
+            None,
+            Some(post_call_label_id),
+            vec![],
+        )),
+        instructions: post_call_insts,
+    };
+    if !is_void {
+        post_call_block.instructions.insert(
+            0,
+            Instruction::new(Op::Phi, call.result_type, call.result_id, phi_operands),
+        );
+    }
+    new_blocks.push(post_call_block);
+
+    let post_call_block_idx = block_idx + new_blocks.len();
+    let original_label_id = blocks[block_idx].label_id().unwrap();
+    blocks.splice((block_idx + 1)..(block_idx + 1), new_blocks);
+
+    // Adjust any `OpPhi`s in the targets of the original call block, to refer
+    // to the post-call block (the new source of those CFG edges).
+    rewrite_phi_sources(original_label_id, blocks, post_call_block_idx);
+
+    post_call_block_idx
+}
+
+/// Replace instructions that only make sense for pointers, with their integer
+/// equivalents, wherever they're used with function IDs.
+fn rewrite_fn_id_uses(
+    module: &mut Module,
+    fn_id_types: &FxHashSet<Word>,
+    value_types: &FxHashMap<Word, Word>,
+    fn_id_width: u32,
+    addressing_model: AddressingModel,
+    zombies: &mut Vec<Instruction>,
+) {
+    let is_fn_id_type = |ty: Option<Word>| ty.map_or(false, |ty| fn_id_types.contains(&ty));
+    let mut ptr_types = FxHashSet::default();
+    let mut int_types = FxHashMap::default();
+    for inst in &module.types_global_values {
+        match inst.class.opcode {
+            Op::TypePointer => {
+                ptr_types.insert(inst.result_id.unwrap());
+            }
+            Op::TypeInt => {
+                int_types.insert(
+                    inst.result_id.unwrap(),
+                    (
+                        inst.operands[0].unwrap_literal_int32(),
+                        inst.operands[1].unwrap_literal_int32(),
+                    ),
+                );
+            }
+            _ => {}
+        }
+    }
+    let is_data_ptr_type =
+        |ty: Option<Word>| ty.map_or(false, |ty| ptr_types.contains(&ty)) && !is_fn_id_type(ty);
+    // Conversions to/from unsigned integers of the same width as function IDs
+    // are noops, while other integers need `OpBitcast` or `OpUConvert`.
+    let int_conversion = |int_ty: Option<Word>| match int_ty.and_then(|ty| int_types.get(&ty)) {
+        Some(&(width, 0)) if width == fn_id_width => Op::CopyObject,
+        Some(&(width, _)) if width == fn_id_width => Op::Bitcast,
+        _ => Op::UConvert,
+    };
+
+    for func in &mut module.functions {
+        for inst in func
+            .blocks
+            .iter_mut()
+            .flat_map(|block| &mut block.instructions)
+        {
+            let operand_ty = |i: usize| {
+                inst.operands
+                    .get(i)
+                    .and_then(|operand| operand.id_ref_any())
+                    .and_then(|id| value_types.get(&id).copied())
+            };
+            let new_opcode = match inst.class.opcode {
+                Op::Bitcast if is_fn_id_type(operand_ty(0)) && is_fn_id_type(inst.result_type) => {
+                    Op::CopyObject
+                }
+                // Casts between function IDs and data pointers, only possible
+                // when pointers have addresses (i.e. not `Logical` addressing).
+                Op::Bitcast
+                    if (is_fn_id_type(operand_ty(0)) && is_data_ptr_type(inst.result_type))
+                        || (is_data_ptr_type(operand_ty(0)) && is_fn_id_type(inst.result_type)) =>
+                {
+                    if addressing_model == AddressingModel::Logical {
+                        // NOTE zombies on `OpBitcast`s are only reported
+                        // if used, and unused ones are removed later anyway.
+                        zombies.push(zombie(
+                            inst.result_id.unwrap(),
+                            "cannot cast between function pointers and data pointers \
+                             with `Logical` addressing",
+                        ));
+                        continue;
+                    }
+                    if is_fn_id_type(operand_ty(0)) {
+                        Op::ConvertUToPtr
+                    } else {
+                        Op::ConvertPtrToU
+                    }
+                }
+                Op::PtrEqual if is_fn_id_type(operand_ty(0)) => Op::IEqual,
+                Op::PtrNotEqual if is_fn_id_type(operand_ty(0)) => Op::INotEqual,
+                Op::ConvertPtrToU if is_fn_id_type(operand_ty(0)) => {
+                    int_conversion(inst.result_type)
+                }
+                Op::ConvertUToPtr if is_fn_id_type(inst.result_type) => {
+                    int_conversion(operand_ty(0))
+                }
+                _ => continue,
+            };
+            *inst = Instruction::new(
+                new_opcode,
+                inst.result_type,
+                inst.result_id,
+                inst.operands.clone(),
+            );
+        }
+    }
+}
@@ -1068,7 +1068,7 @@ fn compute_preds(blocks: &[Block]) -> Vec<Vec<usize>> {
 
 /// Helper for adjusting `OpPhi` source label IDs, when the terminator of the
 /// `original_label_id`-labeled block got moved to `blocks[original_block_idx]`.
-fn rewrite_phi_sources(original_label_id: Word, blocks: &mut [Block], new_block_idx: usize) {
+pub fn rewrite_phi_sources(original_label_id: Word, blocks: &mut [Block], new_block_idx: usize) {
     let new_label_id = blocks[new_block_idx].label_id().unwrap();
 
     // HACK(eddyb) can't keep `blocks` borrowed, the loop needs mutable access.
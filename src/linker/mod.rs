@@ -1,8 +1,11 @@
 #[cfg(test)]
 mod test;
 
+mod atomic_scopes;
+mod capability_computation;
 mod dce;
 mod destructure_composites;
+mod devirtualize;
 mod duplicates;
 mod entry_interface;
 mod import_export_link;
@@ -27,9 +30,9 @@ use either::Either;
 use rspirv::{
     binary::{Assemble, Consumer},
     dr::{Block, Instruction, Loader, Module, ModuleHeader, Operand},
-    spirv::{Op, StorageClass, Word},
+    spirv::{AddressingModel, Capability, Op, StorageClass, Word},
 };
-use rustc_data_structures::fx::FxHashMap;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_errors::ErrorGuaranteed;
 use rustc_session::{config::OutputFilenames, Session};
 
@@ -37,16 +40,22 @@ use crate::{
     codegen_cx::SpirvMetadata,
     custom_decorations::{CustomDecoration, SrcLocDecoration, ZombieDecoration},
     custom_insts,
+    stats::{self, link_timer},
+    target::SpirvTarget,
 };
 
 pub type Result<T> = std::result::Result<T, ErrorGuaranteed>;
 
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct Options {
     pub compact_ids: bool,
     pub dce: bool,
     pub early_report_zombies: bool,
     pub infer_storage_classes: bool,
+    pub infer_capabilities: bool,
+    /// Overrides the target's default for which capabilities may be inferred
+    /// (see `Options::inferable_capabilities`).
+    pub inferable_capabilities: Option<Vec<Capability>>,
     pub structurize: bool,
     pub spirt_passes: Vec<String>,
 
@@ -72,6 +81,17 @@ pub struct Options {
     pub specializer_dump_instances: Option<PathBuf>,
     pub print_all_zombie: bool,
     pub print_zombie: bool,
+    pub print_inferred_capabilities: bool,
+}
+
+impl Options {
+    /// Capabilities the linker may infer for `target`, i.e. the ones passed to
+    /// `--inferable-capabilities`, or `SpirvTarget::inferable_capabilities`.
+    pub fn inferable_capabilities(&self, target: &SpirvTarget) -> &[Capability] {
+        self.inferable_capabilities
+            .as_deref()
+            .unwrap_or(target.inferable_capabilities())
+    }
 }
 
 pub enum LinkResult {
@@ -155,46 +175,97 @@ fn get_name<'a>(names: &FxHashMap<Word, &'a str>, id: Word) -> Cow<'a, str> {
     )
 }
 
+/// Collect the pointee type of every `OpTypePointer`, and the value of every
+/// unsigned integer constant that could be used as an access chain index
+/// (i.e. of 32-bit or 64-bit type, the latter only if the value fits in `u32`).
+fn collect_pointers_and_index_constants(
+    module: &Module,
+) -> (FxHashMap<Word, Word>, FxHashMap<Word, u32>) {
+    let mut pointer_to_pointee = FxHashMap::default();
+    let mut constants = FxHashMap::default();
+    let mut uint_types = FxHashSet::default();
+    for inst in &module.types_global_values {
+        match inst.class.opcode {
+            Op::TypePointer => {
+                pointer_to_pointee
+                    .insert(inst.result_id.unwrap(), inst.operands[1].unwrap_id_ref());
+            }
+            Op::TypeInt
+                if matches!(inst.operands[0].unwrap_literal_int32(), 32 | 64)
+                    && inst.operands[1].unwrap_literal_int32() == 0 =>
+            {
+                uint_types.insert(inst.result_id.unwrap());
+            }
+            Op::Constant if uint_types.contains(&inst.result_type.unwrap()) => {
+                let value = match inst.operands[0] {
+                    Operand::LiteralInt32(value) => Some(value),
+                    Operand::LiteralInt64(value) => u32::try_from(value).ok(),
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    constants.insert(inst.result_id.unwrap(), value);
+                }
+            }
+            _ => {}
+        }
+    }
+    (pointer_to_pointee, constants)
+}
+
+/// Merges `inputs` into a single module, shifting their IDs so they don't
+/// overlap, but otherwise leaving everything (e.g. imports) as-is.
+///
+/// Also used for LTO (see `link::run_fat`), ahead of the actual linking.
+pub fn merge(mut inputs: Vec<Module>) -> std::result::Result<Module, String> {
+    // shift all the ids
+    let mut bound = inputs[0].header.as_ref().unwrap().bound - 1;
+    let version = inputs[0].header.as_ref().unwrap().version();
+
+    for module in inputs.iter_mut().skip(1) {
+        simple_passes::shift_ids(module, bound);
+        bound += module.header.as_ref().unwrap().bound - 1;
+        let this_version = module.header.as_ref().unwrap().version();
+        if version != this_version {
+            return Err(format!(
+                "cannot link two modules with different SPIR-V versions: v{}.{} and v{}.{}",
+                version.0, version.1, this_version.0, this_version.1
+            ));
+        }
+    }
+
+    // merge the binaries
+    let mut loader = Loader::new();
+
+    for module in inputs {
+        module.all_inst_iter().for_each(|inst| {
+            loader.consume_instruction(inst.clone());
+        });
+    }
+
+    let mut output = loader.module();
+    let mut header = ModuleHeader::new(bound + 1);
+    header.set_version(version.0, version.1);
+    header.generator = 0x001B_0000;
+    output.header = Some(header);
+    Ok(output)
+}
+
+/// Removes everything not reachable from entry-points or `Export`s, without
+/// needing imports to have been resolved (e.g. for LTO, see `link::run_fat`).
+pub fn dce(module: &mut Module) {
+    dce::dce(module);
+}
+
 pub fn link(
     sess: &Session,
-    mut inputs: Vec<Module>,
+    inputs: Vec<Module>,
     opts: &Options,
     outputs: &OutputFilenames,
     disambiguated_crate_name_for_dumps: &OsStr,
 ) -> Result<LinkResult> {
     let mut output = {
-        let _timer = sess.timer("link_merge");
-        // shift all the ids
-        let mut bound = inputs[0].header.as_ref().unwrap().bound - 1;
-        let version = inputs[0].header.as_ref().unwrap().version();
-
-        for module in inputs.iter_mut().skip(1) {
-            simple_passes::shift_ids(module, bound);
-            bound += module.header.as_ref().unwrap().bound - 1;
-            let this_version = module.header.as_ref().unwrap().version();
-            if version != this_version {
-                return Err(sess.psess.dcx.err(format!(
-                    "cannot link two modules with different SPIR-V versions: v{}.{} and v{}.{}",
-                    version.0, version.1, this_version.0, this_version.1
-                )));
-            }
-        }
-
-        // merge the binaries
-        let mut loader = Loader::new();
-
-        for module in inputs {
-            module.all_inst_iter().for_each(|inst| {
-                loader.consume_instruction(inst.clone());
-            });
-        }
-
-        let mut output = loader.module();
-        let mut header = ModuleHeader::new(bound + 1);
-        header.set_version(version.0, version.1);
-        header.generator = 0x001B_0000;
-        output.header = Some(header);
-        output
+        let _timer = link_timer(sess, "link_merge");
+        merge(inputs).map_err(|e| sess.psess.dcx.err(e))?
     };
 
     if let Some(dir) = &opts.dump_post_merge {
@@ -208,7 +279,7 @@ pub fn link(
 
     // remove duplicates (https://github.com/KhronosGroup/SPIRV-Tools/blob/e7866de4b1dc2a7e8672867caeb0bdca49f458d3/source/opt/remove_duplicates_pass.cpp)
     {
-        let _timer = sess.timer("link_remove_duplicates");
+        let _timer = link_timer(sess, "link_remove_duplicates");
         duplicates::remove_duplicate_extensions(&mut output);
         duplicates::remove_duplicate_capablities(&mut output);
         duplicates::remove_duplicate_ext_inst_imports(&mut output);
@@ -218,12 +289,22 @@ pub fn link(
 
     // find import / export pairs
     {
-        let _timer = sess.timer("link_find_pairs");
+        let _timer = link_timer(sess, "link_find_pairs");
         import_export_link::run(opts, sess, &mut output)?;
     }
 
     {
-        let _timer = sess.timer("link_fragment_inst_check");
+        let _timer = link_timer(sess, "link_devirtualize");
+        devirtualize::devirtualize(sess, &mut output);
+    }
+
+    {
+        let _timer = link_timer(sess, "link_propagate_atomic_scopes");
+        atomic_scopes::propagate_atomic_scopes(&mut output);
+    }
+
+    {
+        let _timer = link_timer(sess, "link_fragment_inst_check");
         simple_passes::check_fragment_insts(sess, &output)?;
     }
 
@@ -231,7 +312,7 @@ pub fn link(
     // so that any zombies that are passed as call arguments, but eventually unused,
     // won't be (incorrectly) considered used.
     {
-        let _timer = sess.timer("link_remove_unused_params");
+        let _timer = link_timer(sess, "link_remove_unused_params");
         output = param_weakening::remove_unused_params(output);
     }
 
@@ -241,25 +322,31 @@ pub fn link(
         // *not* run DCE, given SPIR-T exists and makes DCE mandatory, but we're
         // still only going to do the minimum necessary ("block ordering").
         {
-            let _timer = sess.timer("link_block_ordering_pass-before-report_and_remove_zombies");
+            let _timer = link_timer(
+                sess,
+                "link_block_ordering_pass-before-report_and_remove_zombies",
+            );
             for func in &mut output.functions {
                 simple_passes::block_ordering_pass(func);
             }
         }
 
-        let _timer = sess.timer("link_report_and_remove_zombies");
+        let _timer = link_timer(sess, "link_report_and_remove_zombies");
         zombies::report_and_remove_zombies(sess, opts, &mut output)?;
     }
 
+    let physical_storage_buffer_addressing = output.memory_model.as_ref().unwrap().operands[0]
+        .unwrap_addressing_model()
+        == AddressingModel::PhysicalStorageBuffer64;
     if opts.infer_storage_classes {
         // HACK(eddyb) this is not the best approach, but storage class inference
         // can still fail in entirely legitimate ways (i.e. mismatches in zombies).
         if !opts.early_report_zombies {
-            let _timer = sess.timer("link_dce-before-specialize_generic_storage_class");
+            let _timer = link_timer(sess, "link_dce-before-specialize_generic_storage_class");
             dce::dce(&mut output);
         }
 
-        let _timer = sess.timer("specialize_generic_storage_class");
+        let _timer = link_timer(sess, "specialize_generic_storage_class");
         // HACK(eddyb) `specializer` requires functions' blocks to be in RPO order
         // (i.e. `block_ordering_pass`) - this could be relaxed by using RPO visit
         // inside `specializer`, but this is easier.
@@ -284,42 +371,40 @@ pub fn link(
                 // TODO(eddyb) investigate whether this can end up in a pointer
                 // type that's the value of a module-scoped variable, and whether
                 // `Function` is actually invalid! (may need `Private`)
-                concrete_fallback: Operand::StorageClass(StorageClass::Function),
+                // NOTE with `PhysicalStorageBuffer64` addressing, pointers not
+                // derived from any variable are (usually) created by converting
+                // integers (i.e. buffer device addresses), so they're assumed
+                // to point into `PhysicalStorageBuffer` instead.
+                concrete_fallback: Operand::StorageClass(if physical_storage_buffer_addressing {
+                    StorageClass::PhysicalStorageBuffer
+                } else {
+                    StorageClass::Function
+                }),
             },
         );
+
+        if physical_storage_buffer_addressing {
+            let _timer = link_timer(sess, "link_check_physical_storage_buffer_casts");
+            simple_passes::check_physical_storage_buffer_casts(sess, &output)?;
+        }
+    }
+
+    if physical_storage_buffer_addressing {
+        let _timer = link_timer(sess, "link_remove_non_physical_pointer_strides");
+        simple_passes::remove_non_physical_pointer_strides(&mut output);
+        // Pointer types may now only differ in their (removed) strides.
+        duplicates::remove_duplicate_types(&mut output);
     }
 
     // NOTE(eddyb) with SPIR-T, we can do `mem2reg` before inlining, too!
     {
         if opts.dce {
-            let _timer = sess.timer("link_dce-before-inlining");
+            let _timer = link_timer(sess, "link_dce-before-inlining");
             dce::dce(&mut output);
         }
 
-        let _timer = sess.timer("link_block_ordering_pass_and_mem2reg-before-inlining");
-        let mut pointer_to_pointee = FxHashMap::default();
-        let mut constants = FxHashMap::default();
-        let mut u32 = None;
-        for inst in &output.types_global_values {
-            match inst.class.opcode {
-                Op::TypePointer => {
-                    pointer_to_pointee
-                        .insert(inst.result_id.unwrap(), inst.operands[1].unwrap_id_ref());
-                }
-                Op::TypeInt
-                    if inst.operands[0].unwrap_literal_int32() == 32
-                        && inst.operands[1].unwrap_literal_int32() == 0 =>
-                {
-                    assert!(u32.is_none());
-                    u32 = Some(inst.result_id.unwrap());
-                }
-                Op::Constant if u32.is_some() && inst.result_type == u32 => {
-                    let value = inst.operands[0].unwrap_literal_int32();
-                    constants.insert(inst.result_id.unwrap(), value);
-                }
-                _ => {}
-            }
-        }
+        let _timer = link_timer(sess, "link_block_ordering_pass_and_mem2reg-before-inlining");
+        let (pointer_to_pointee, constants) = collect_pointers_and_index_constants(&output);
         for func in &mut output.functions {
             simple_passes::block_ordering_pass(func);
             // Note: mem2reg requires functions to be in RPO order (i.e. block_ordering_pass)
@@ -335,47 +420,29 @@ pub fn link(
     }
 
     if opts.dce {
-        let _timer =
-            sess.timer("link_dce-and-remove_duplicate_debuginfo-after-mem2reg-before-inlining");
+        let _timer = link_timer(
+            sess,
+            "link_dce-and-remove_duplicate_debuginfo-after-mem2reg-before-inlining",
+        );
         dce::dce(&mut output);
         duplicates::remove_duplicate_debuginfo(&mut output);
     }
 
     {
-        let _timer = sess.timer("link_inline");
+        let _timer = link_timer(sess, "link_inline");
+        let functions_before_inlining = output.functions.len();
         inline::inline(sess, &mut output)?;
+        stats::record_inlining(functions_before_inlining, output.functions.len());
     }
 
     if opts.dce {
-        let _timer = sess.timer("link_dce-after-inlining");
+        let _timer = link_timer(sess, "link_dce-after-inlining");
         dce::dce(&mut output);
     }
 
     {
-        let _timer = sess.timer("link_block_ordering_pass_and_mem2reg-after-inlining");
-        let mut pointer_to_pointee = FxHashMap::default();
-        let mut constants = FxHashMap::default();
-        let mut u32 = None;
-        for inst in &output.types_global_values {
-            match inst.class.opcode {
-                Op::TypePointer => {
-                    pointer_to_pointee
-                        .insert(inst.result_id.unwrap(), inst.operands[1].unwrap_id_ref());
-                }
-                Op::TypeInt
-                    if inst.operands[0].unwrap_literal_int32() == 32
-                        && inst.operands[1].unwrap_literal_int32() == 0 =>
-                {
-                    assert!(u32.is_none());
-                    u32 = Some(inst.result_id.unwrap());
-                }
-                Op::Constant if u32.is_some() && inst.result_type == u32 => {
-                    let value = inst.operands[0].unwrap_literal_int32();
-                    constants.insert(inst.result_id.unwrap(), value);
-                }
-                _ => {}
-            }
-        }
+        let _timer = link_timer(sess, "link_block_ordering_pass_and_mem2reg-after-inlining");
+        let (pointer_to_pointee, constants) = collect_pointers_and_index_constants(&output);
         for func in &mut output.functions {
             simple_passes::block_ordering_pass(func);
             // Note: mem2reg requires functions to be in RPO order (i.e. block_ordering_pass)
@@ -391,8 +458,10 @@ pub fn link(
     }
 
     if opts.dce {
-        let _timer =
-            sess.timer("link_dce-and-remove_duplicate_debuginfo-after-mem2reg-after-inlining");
+        let _timer = link_timer(
+            sess,
+            "link_dce-and-remove_duplicate_debuginfo-after-mem2reg-after-inlining",
+        );
         dce::dce(&mut output);
         duplicates::remove_duplicate_debuginfo(&mut output);
     }
@@ -408,7 +477,7 @@ pub fn link(
 
         let spv_words;
         let spv_bytes = {
-            let _timer = sess.timer("assemble-to-spv_bytes-for-spirt");
+            let _timer = link_timer(sess, "assemble-to-spv_bytes-for-spirt");
             spv_words = output.assemble();
             // FIXME(eddyb) this is wastefully cloning all the bytes, but also
             // `spirt::Module` should have a method that takes `Vec<u32>`.
@@ -417,7 +486,7 @@ pub fn link(
         let cx = std::rc::Rc::new(spirt::Context::new());
         crate::custom_insts::register_to_spirt_context(&cx);
         let mut module = {
-            let _timer = sess.timer("spirt::Module::lower_from_spv_file");
+            let _timer = link_timer(sess, "spirt::Module::lower_from_spv_file");
             match spirt::Module::lower_from_spv_bytes(cx.clone(), spv_bytes) {
                 Ok(module) => module,
                 Err(e) => {
@@ -444,13 +513,13 @@ pub fn link(
 
         // NOTE(eddyb) this *must* run on unstructured CFGs, to do its job.
         {
-            let _timer = sess.timer("spirt_passes::controlflow::convert_custom_aborts_to_unstructured_returns_in_entry_points");
+            let _timer = link_timer(sess, "spirt_passes::controlflow::convert_custom_aborts_to_unstructured_returns_in_entry_points");
             spirt_passes::controlflow::convert_custom_aborts_to_unstructured_returns_in_entry_points(opts, &mut module);
         }
 
         if opts.structurize {
             {
-                let _timer = sess.timer("spirt::legalize::structurize_func_cfgs");
+                let _timer = link_timer(sess, "spirt::legalize::structurize_func_cfgs");
                 spirt::passes::legalize::structurize_func_cfgs(&mut module);
             }
             after_pass("structurize_func_cfgs", &module);
@@ -461,7 +530,7 @@ pub fn link(
             spirt_passes::run_func_passes(
                 &mut module,
                 &opts.spirt_passes,
-                |name, _module| sess.timer(name),
+                |name, _module| link_timer(sess, name),
                 |name, module, timer| {
                     drop(timer);
                     after_pass(name, module);
@@ -470,7 +539,7 @@ pub fn link(
         }
 
         let report_diagnostics_result = {
-            let _timer = sess.timer("spirt_passes::diagnostics::report_diagnostics");
+            let _timer = link_timer(sess, "spirt_passes::diagnostics::report_diagnostics");
             spirt_passes::diagnostics::report_diagnostics(sess, opts, &module)
         };
         let any_spirt_bugs = report_diagnostics_result
@@ -563,16 +632,19 @@ pub fn link(
 
         // Replace our custom debuginfo instructions just before lifting to SPIR-V.
         {
-            let _timer = sess.timer("spirt_passes::debuginfo::convert_custom_debuginfo_to_spv");
+            let _timer = link_timer(
+                sess,
+                "spirt_passes::debuginfo::convert_custom_debuginfo_to_spv",
+            );
             spirt_passes::debuginfo::convert_custom_debuginfo_to_spv(&mut module);
         }
 
         let spv_words = {
-            let _timer = sess.timer("spirt::Module::lift_to_spv_module_emitter");
+            let _timer = link_timer(sess, "spirt::Module::lift_to_spv_module_emitter");
             module.lift_to_spv_module_emitter().unwrap().words
         };
         output = {
-            let _timer = sess.timer("parse-spv_words-from-spirt");
+            let _timer = link_timer(sess, "parse-spv_words-from-spirt");
             let mut loader = Loader::new();
             rspirv::binary::parse_words(&spv_words, &mut loader).unwrap();
             loader.module()
@@ -605,7 +677,7 @@ pub fn link(
     // with a single-entry map, run `spirt::spv::lift` (or even `spirt::print`)
     // on `module`, then put back the full original `module.exports` map.
     {
-        let _timer = sess.timer("peephole_opts");
+        let _timer = link_timer(sess, "peephole_opts");
         let types = peephole_opts::collect_types(&output);
         for func in &mut output.functions {
             peephole_opts::composite_construct(&types, func);
@@ -615,17 +687,17 @@ pub fn link(
     }
 
     {
-        let _timer = sess.timer("link_gather_all_interface_vars_from_uses");
+        let _timer = link_timer(sess, "link_gather_all_interface_vars_from_uses");
         entry_interface::gather_all_interface_vars_from_uses(&mut output);
     }
 
     if opts.spirv_metadata == SpirvMetadata::NameVariables {
-        let _timer = sess.timer("link_name_variables");
+        let _timer = link_timer(sess, "link_name_variables");
         simple_passes::name_variables_pass(&mut output);
     }
 
     {
-        let _timer = sess.timer("link_sort_globals");
+        let _timer = link_timer(sess, "link_sort_globals");
         simple_passes::sort_globals(&mut output);
     }
 
@@ -705,17 +777,22 @@ pub fn link(
         // structurization and mem2reg (for perf reasons), and mem2reg may remove references to
         // invalid types, so we need to DCE again.
         if opts.dce {
-            let _timer = sess.timer("link_dce_2");
+            let _timer = link_timer(sess, "link_dce_2");
             dce::dce(output);
         }
 
         {
-            let _timer = sess.timer("link_remove_duplicate_debuginfo");
+            let _timer = link_timer(sess, "link_remove_duplicate_debuginfo");
             duplicates::remove_duplicate_debuginfo(output);
         }
 
+        if opts.infer_capabilities {
+            let _timer = link_timer(sess, "link_infer_capabilities");
+            capability_computation::infer_capabilities_and_extensions(sess, output, opts)?;
+        }
+
         if opts.compact_ids {
-            let _timer = sess.timer("link_compact_ids");
+            let _timer = link_timer(sess, "link_compact_ids");
             // compact the ids https://github.com/KhronosGroup/SPIRV-Tools/blob/e02f178a716b0c3c803ce31b9df4088596537872/source/opt/compact_ids_pass.cpp#L43
             output.header.as_mut().unwrap().bound = simple_passes::compact_ids(output);
         };
@@ -727,6 +804,18 @@ pub fn link(
 
         // FIXME(eddyb) might make more sense to rewrite these away on SPIR-T.
         ZombieDecoration::remove_all(output);
+
+        if stats::enabled(sess) {
+            let words = output.assemble().len();
+            let name = file_stem.map_or_else(
+                || disambiguated_crate_name_for_dumps.to_string_lossy(),
+                |file_stem| file_stem.to_string_lossy(),
+            );
+            stats::record_link_output(
+                name.into_owned(),
+                stats::ModuleStats::collect(output, words),
+            );
+        }
     }
 
     Ok(output)
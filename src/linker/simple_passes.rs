@@ -2,7 +2,7 @@ use std::{iter::once, mem::take};
 
 use rspirv::{
     dr::{Block, Function, Module},
-    spirv::{ExecutionModel, Op, Word},
+    spirv::{Decoration, ExecutionModel, Op, StorageClass, Word},
 };
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
 use rustc_session::Session;
@@ -159,6 +159,88 @@ pub fn name_variables_pass(module: &mut Module) {
     }
 }
 
+/// With `PhysicalStorageBuffer64` addressing, only `PhysicalStorageBuffer`
+/// pointers have addresses, but codegen can't tell them apart from others, so
+/// their conversions to/from integers are only checked after storage classes
+/// have been inferred.
+pub fn check_physical_storage_buffer_casts(sess: &Session, module: &Module) -> Result<()> {
+    let ptr_storage_classes: FxHashMap<Word, StorageClass> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::TypePointer)
+        .map(|inst| {
+            (
+                inst.result_id.unwrap(),
+                inst.operands[0].unwrap_storage_class(),
+            )
+        })
+        .collect();
+    let value_types: FxHashMap<Word, Word> = module
+        .all_inst_iter()
+        .filter_map(|inst| Some((inst.result_id?, inst.result_type?)))
+        .collect();
+
+    let mut names = None;
+    let mut any_err = None;
+    for func in &module.functions {
+        for inst in func.all_inst_iter() {
+            let (ptr_ty, message) = match inst.class.opcode {
+                Op::ConvertPtrToU => (
+                    value_types[&inst.operands[0].unwrap_id_ref()],
+                    "cannot convert pointers to integers",
+                ),
+                Op::ConvertUToPtr => (
+                    inst.result_type.unwrap(),
+                    "cannot convert integers to pointers",
+                ),
+                _ => continue,
+            };
+            let storage_class = ptr_storage_classes[&ptr_ty];
+            if storage_class == StorageClass::PhysicalStorageBuffer {
+                continue;
+            }
+            let names = names.get_or_insert_with(|| get_names(module));
+            let func_name = get_name(names, func.def_id().unwrap());
+            any_err = Some({
+                let mut err = sess.psess.dcx.struct_err(message);
+                err.note(format!(
+                    "only `PhysicalStorageBuffer` pointers have addresses, \
+                     not `{storage_class:?}` ones"
+                ));
+                err.note(format!("in function `{func_name}`"));
+                err.emit()
+            });
+            // One error per function is enough.
+            break;
+        }
+    }
+    match any_err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Remove the `ArrayStride` decorations codegen puts on all pointer types with
+/// `PhysicalStorageBuffer64` addressing, from every pointer type that didn't
+/// end up in the `PhysicalStorageBuffer` storage class (the only one which
+/// needs it, for `OpPtrAccessChain`, and also the only one allowed to have it).
+pub fn remove_non_physical_pointer_strides(module: &mut Module) {
+    let non_physical_ptr_types: FxHashSet<Word> = module
+        .types_global_values
+        .iter()
+        .filter(|inst| {
+            inst.class.opcode == Op::TypePointer
+                && inst.operands[0].unwrap_storage_class() != StorageClass::PhysicalStorageBuffer
+        })
+        .map(|inst| inst.result_id.unwrap())
+        .collect();
+    module.annotations.retain(|inst| {
+        !(inst.class.opcode == Op::Decorate
+            && inst.operands[1].unwrap_decoration() == Decoration::ArrayStride
+            && non_physical_ptr_types.contains(&inst.operands[0].unwrap_id_ref()))
+    });
+}
+
 // Some instructions are only valid in fragment shaders. Check them.
 pub fn check_fragment_insts(sess: &Session, module: &Module) -> Result<()> {
     let mut visited = vec![false; module.functions.len()];
@@ -667,3 +667,484 @@ fn names_and_decorations() {
 
     without_header_eq(result, expect);
 }
+
+#[test]
+fn physical_storage_buffer_cast_of_function_variable() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpCapability Int64
+            OpCapability PhysicalStorageBufferAddresses
+            OpMemoryModel PhysicalStorageBuffer64 GLSL450
+            OpName %1 "foo"
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeInt 64 0
+            %4 = OpTypePointer Function %3
+            %5 = OpTypeFunction %2
+            %1 = OpFunction %2 None %5
+            %6 = OpLabel
+            %7 = OpVariable %4 Function
+            %8 = OpConvertPtrToU %3 %7
+            OpStore %7 %8
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(
+        &[&a],
+        &crate::linker::Options {
+            dce: true,
+            infer_storage_classes: true,
+            keep_link_exports: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        result.err().as_deref(),
+        Some(
+            "error: cannot convert pointers to integers\n  |\n  = note: only \
+             `PhysicalStorageBuffer` pointers have addresses, not `Function` ones\n  = \
+             note: in function `foo`"
+        )
+    );
+}
+
+#[test]
+fn physical_storage_buffer_cast_of_integer() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpCapability Int64
+            OpCapability PhysicalStorageBufferAddresses
+            OpMemoryModel PhysicalStorageBuffer64 GLSL450
+            OpName %1 "foo"
+            OpDecorate %1 LinkageAttributes "foo" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeInt 64 0
+            %4 = OpTypePointer Generic %3
+            %5 = OpTypeFunction %2 %3
+            %1 = OpFunction %2 None %5
+            %6 = OpFunctionParameter %3
+            %7 = OpLabel
+            %8 = OpConvertUToPtr %4 %6
+            %9 = OpConvertPtrToU %3 %8
+            OpStore %8 %9 Aligned 8
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(
+        &[&a],
+        &crate::linker::Options {
+            dce: true,
+            infer_storage_classes: true,
+            keep_link_exports: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let ptr_storage_classes: Vec<_> = result
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == rspirv::spirv::Op::TypePointer)
+        .map(|inst| inst.operands[0].unwrap_storage_class())
+        .collect();
+    assert_eq!(
+        ptr_storage_classes,
+        [rspirv::spirv::StorageClass::PhysicalStorageBuffer]
+    );
+}
+
+#[test]
+fn physical_storage_buffer_pointer_strides() {
+    // The same (generic) pointer type is used both for a `Function` variable
+    // and an address, but only the latter can keep its `ArrayStride`.
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpCapability Int64
+            OpCapability PhysicalStorageBufferAddresses
+            OpMemoryModel PhysicalStorageBuffer64 GLSL450
+            OpName %1 "foo"
+            OpDecorate %1 LinkageAttributes "foo" Export
+            OpDecorate %4 ArrayStride 8
+            %2 = OpTypeVoid
+            %3 = OpTypeInt 64 0
+            %4 = OpTypePointer Generic %3
+            %5 = OpTypeFunction %2 %3
+            %6 = OpConstant %3 1
+            %1 = OpFunction %2 None %5
+            %7 = OpFunctionParameter %3
+            %8 = OpLabel
+            %9 = OpVariable %4 Function
+            %10 = OpConvertUToPtr %4 %7
+            %11 = OpPtrAccessChain %4 %10 %6
+            %12 = OpLoad %3 %11 Aligned 8
+            OpStore %9 %12
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(
+        &[&a],
+        &crate::linker::Options {
+            dce: true,
+            infer_storage_classes: true,
+            keep_link_exports: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let strided_types: Vec<_> = result
+        .annotations
+        .iter()
+        .filter(|inst| {
+            inst.operands[1].unwrap_decoration() == rspirv::spirv::Decoration::ArrayStride
+        })
+        .map(|inst| inst.operands[0].unwrap_id_ref())
+        .collect();
+    let mut ptr_types: Vec<_> = result
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == rspirv::spirv::Op::TypePointer)
+        .map(|inst| {
+            (
+                inst.operands[0].unwrap_storage_class(),
+                strided_types.contains(&inst.result_id.unwrap()),
+            )
+        })
+        .collect();
+    ptr_types.sort_by_key(|&(storage_class, _)| storage_class as u32);
+    assert_eq!(
+        ptr_types,
+        [
+            (rspirv::spirv::StorageClass::Function, false),
+            (rspirv::spirv::StorageClass::PhysicalStorageBuffer, true),
+        ]
+    );
+}
+
+/// Check that no indirect calls are left in `module`, and collect the number
+/// of cases of each `OpSwitch`, as well as the number of `OpPhi`s.
+#[track_caller]
+fn all_calls_direct_and_switch_cases(module: &Module) -> (Vec<usize>, usize) {
+    use rspirv::spirv::Op;
+
+    let func_ids: Vec<_> = module
+        .functions
+        .iter()
+        .map(|func| func.def_id().unwrap())
+        .collect();
+    let mut switch_cases = vec![];
+    let mut phis = 0;
+    for inst in module.all_inst_iter() {
+        match inst.class.opcode {
+            Op::FunctionCall => assert!(func_ids.contains(&inst.operands[0].unwrap_id_ref())),
+            Op::Switch => switch_cases.push((inst.operands.len() - 2) / 2),
+            Op::Phi => phis += 1,
+            _ => {}
+        }
+    }
+    (switch_cases, phis)
+}
+
+fn devirtualize_linker_opts() -> crate::linker::Options {
+    crate::linker::Options {
+        dce: true,
+        early_report_zombies: true,
+        keep_link_exports: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn devirtualize_single_callee() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %4 = OpTypePointer Generic %3
+            %5 = OpVariable %4 CodeSectionINTEL %6
+            %1 = OpFunction %2 None %3
+            %7 = OpLabel
+            %8 = OpFunctionCall %2 %5
+            OpReturn
+            OpFunctionEnd
+            %6 = OpFunction %2 None %3
+            %9 = OpLabel
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts()).unwrap();
+    assert_eq!(all_calls_direct_and_switch_cases(&result), (vec![], 0));
+}
+
+#[test]
+fn devirtualize_multiple_callees() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeInt 32 0
+            %3 = OpTypeBool
+            %4 = OpTypeFunction %2
+            %5 = OpTypeFunction %2 %3
+            %6 = OpTypePointer Generic %4
+            %7 = OpConstant %2 1
+            %8 = OpConstant %2 2
+            %9 = OpVariable %6 CodeSectionINTEL %10
+            %11 = OpVariable %6 CodeSectionINTEL %12
+            %1 = OpFunction %2 None %5
+            %13 = OpFunctionParameter %3
+            %14 = OpLabel
+            %15 = OpSelect %6 %13 %9 %11
+            %16 = OpFunctionCall %2 %15
+            OpReturnValue %16
+            OpFunctionEnd
+            %10 = OpFunction %2 None %4
+            %17 = OpLabel
+            OpReturnValue %7
+            OpFunctionEnd
+            %12 = OpFunction %2 None %4
+            %18 = OpLabel
+            OpReturnValue %8
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts()).unwrap();
+    assert_eq!(all_calls_direct_and_switch_cases(&result), (vec![2], 1));
+}
+
+#[test]
+fn devirtualize_any_callee_of_type() {
+    // NOTE the callee is a parameter of an exported function, so it could be
+    // any function of the right type, that has its address used (by `%22`),
+    // i.e. not `%13` (only "taken" for a direct call), nor `%15` (another type).
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpDecorate %1 LinkageAttributes "call" Export
+            %2 = OpTypeInt 32 0
+            %3 = OpTypeVoid
+            %4 = OpTypeFunction %2
+            %5 = OpTypeFunction %3
+            %6 = OpTypePointer Generic %4
+            %7 = OpTypePointer Generic %5
+            %8 = OpTypeFunction %2 %6
+            %9 = OpConstant %2 1
+            %10 = OpVariable %6 CodeSectionINTEL %11
+            %12 = OpVariable %6 CodeSectionINTEL %13
+            %14 = OpVariable %7 CodeSectionINTEL %15
+            %23 = OpVariable %6 CodeSectionINTEL %24
+            %25 = OpTypeStruct %6 %6 %7
+            %22 = OpConstantComposite %25 %10 %23 %14
+            %1 = OpFunction %2 None %8
+            %16 = OpFunctionParameter %6
+            %17 = OpLabel
+            %18 = OpFunctionCall %2 %16
+            %26 = OpFunctionCall %2 %13
+            OpReturnValue %18
+            OpFunctionEnd
+            %11 = OpFunction %2 None %4
+            %19 = OpLabel
+            OpReturnValue %9
+            OpFunctionEnd
+            %13 = OpFunction %2 None %4
+            %20 = OpLabel
+            OpReturnValue %9
+            OpFunctionEnd
+            %24 = OpFunction %2 None %4
+            %27 = OpLabel
+            OpReturnValue %9
+            OpFunctionEnd
+            %15 = OpFunction %3 None %5
+            %21 = OpLabel
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts()).unwrap();
+    assert_eq!(all_calls_direct_and_switch_cases(&result), (vec![2], 1));
+}
+
+#[test]
+fn devirtualize_vtable_entries() {
+    // NOTE like `dyn Trait` vtables, `%13` holds function pointers as `*const ()`
+    // (`%6`) entries, which get loaded and cast back to function pointers.
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpDecorate %1 LinkageAttributes "call" Export
+            %2 = OpTypeInt 32 0
+            %3 = OpTypeInt 8 0
+            %4 = OpTypeFunction %2
+            %5 = OpTypePointer Generic %4
+            %6 = OpTypePointer Generic %3
+            %7 = OpConstant %2 2
+            %8 = OpTypeArray %6 %7
+            %9 = OpTypePointer Private %8
+            %10 = OpTypePointer Private %6
+            %11 = OpVariable %6 CodeSectionINTEL %12
+            %14 = OpVariable %6 CodeSectionINTEL %15
+            %13 = OpConstantComposite %8 %11 %14
+            %16 = OpVariable %9 Private %13
+            %17 = OpTypeFunction %2 %2
+            %1 = OpFunction %2 None %17
+            %18 = OpFunctionParameter %2
+            %19 = OpLabel
+            %20 = OpAccessChain %10 %16 %18
+            %21 = OpLoad %6 %20
+            %22 = OpBitcast %5 %21
+            %23 = OpFunctionCall %2 %22
+            OpReturnValue %23
+            OpFunctionEnd
+            %12 = OpFunction %2 None %4
+            %24 = OpLabel
+            OpReturnValue %7
+            OpFunctionEnd
+            %15 = OpFunction %2 None %4
+            %25 = OpLabel
+            OpReturnValue %7
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts()).unwrap();
+    assert_eq!(all_calls_direct_and_switch_cases(&result), (vec![2], 1));
+
+    // Both the function pointers and the `*const ()` entries are now integers.
+    use rspirv::{
+        dr::Operand,
+        spirv::{Op, StorageClass},
+    };
+    assert!(!result.all_inst_iter().any(|inst| {
+        inst.class.opcode == Op::Variable
+            && inst.operands[0] == Operand::StorageClass(StorageClass::CodeSectionINTEL)
+    }));
+    assert!(!result.all_inst_iter().any(|inst| {
+        inst.class.opcode == Op::TypePointer
+            && inst.operands[0] == Operand::StorageClass(StorageClass::Generic)
+    }));
+}
+
+#[test]
+fn devirtualize_no_callees() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpName %1 "main"
+            OpDecorate %1 LinkageAttributes "main" Export
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %4 = OpTypePointer Generic %3
+            %5 = OpConstantNull %4
+            %1 = OpFunction %2 None %3
+            %6 = OpLabel
+            %7 = OpFunctionCall %2 %5
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts());
+    let err = result.err().unwrap();
+    assert!(
+        err.contains("indirect call with no possible callees"),
+        "unexpected error: {err:?}"
+    );
+}
+
+#[test]
+fn devirtualize_no_callees_in_dead_function() {
+    // NOTE unlike `devirtualize_no_callees`, this function is never used, so
+    // it should be removed without any errors (like any other zombie).
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            %2 = OpTypeVoid
+            %3 = OpTypeFunction %2
+            %4 = OpTypePointer Generic %3
+            %5 = OpConstantNull %4
+            %1 = OpFunction %2 None %3
+            %6 = OpLabel
+            %7 = OpFunctionCall %2 %5
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(&[&a], &devirtualize_linker_opts()).unwrap();
+    assert!(result.functions.is_empty());
+}
+
+#[test]
+fn atomic_scope_propagates_to_callees() {
+    let a = assemble_spirv(
+        r#"OpCapability Linkage
+            OpMemoryModel Logical OpenCL
+            OpName %1 "workgroup_scope"
+            OpName %2 "default_scope"
+            OpName %3 "helper"
+            OpDecorate %1 LinkageAttributes "workgroup_scope" Export
+            OpDecorate %2 LinkageAttributes "default_scope" Export
+            OpDecorateString %1 UserTypeGOOGLE "SWorkgroup"
+            OpDecorateString %4 UserTypeGOOGLE "D"
+            %5 = OpTypeVoid
+            %6 = OpTypeInt 32 0
+            %7 = OpTypePointer Workgroup %6
+            %8 = OpTypeFunction %5 %7
+            %4 = OpConstant %6 1
+            %9 = OpConstant %6 0
+            %3 = OpFunction %5 None %8
+            %10 = OpFunctionParameter %7
+            %11 = OpLabel
+            %12 = OpAtomicIIncrement %6 %10 %4 %9
+            OpReturn
+            OpFunctionEnd
+            %1 = OpFunction %5 None %8
+            %13 = OpFunctionParameter %7
+            %14 = OpLabel
+            OpFunctionCall %5 %3 %13
+            OpReturn
+            OpFunctionEnd
+            %2 = OpFunction %5 None %8
+            %15 = OpFunctionParameter %7
+            %16 = OpLabel
+            OpFunctionCall %5 %3 %15
+            OpReturn
+            OpFunctionEnd"#,
+    );
+
+    let result = link_with_linker_opts(
+        &[&a],
+        &crate::linker::Options {
+            dce: true,
+            keep_link_exports: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    use rspirv::spirv::{Op, Scope};
+    let constants: std::collections::HashMap<_, _> = result
+        .types_global_values
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::Constant)
+        .map(|inst| {
+            (
+                inst.result_id.unwrap(),
+                inst.operands[0].unwrap_literal_int32(),
+            )
+        })
+        .collect();
+    let mut scopes: Vec<_> = result
+        .all_inst_iter()
+        .filter(|inst| inst.class.opcode == Op::AtomicIIncrement)
+        .map(|inst| constants[&inst.operands[1].unwrap_id_ref()])
+        .collect();
+    scopes.sort();
+    assert_eq!(result.functions.len(), 4);
+    assert_eq!(scopes, [Scope::Device as u32, Scope::Workgroup as u32]);
+    assert!(result
+        .annotations
+        .iter()
+        .all(|inst| inst.class.opcode != Op::DecorateString));
+}
@@ -14,6 +14,7 @@ use super::{get_name, get_names};
 use crate::{
     custom_decorations::{CustomDecoration, SpanRegenerator, ZombieDecoration},
     custom_insts::{self, CustomOp},
+    stats,
 };
 
 #[derive(Copy, Clone)]
@@ -407,6 +408,7 @@ pub fn report_and_remove_zombies(
     {
         // HACK(eddyb) cannot use the original map because it borrows the `Module`.
         let all_zombies: FxHashSet<_> = zombies.id_to_zombie_kind.into_keys().collect();
+        stats::record_zombies_removed(all_zombies.len());
         let keep = |inst: &Instruction| {
             if let Some(result_id) = inst.result_id {
                 !all_zombies.contains(&result_id)
@@ -7,7 +7,7 @@ use std::{
 use indexmap::IndexSet;
 use rspirv::{
     dr::Operand,
-    spirv::{Capability, Decoration, Dim, ImageFormat, StorageClass, Word},
+    spirv::{AddressingModel, Capability, Decoration, Dim, ImageFormat, StorageClass, Word},
 };
 use rustc_data_structures::fx::FxHashMap;
 use rustc_middle::span_bug;
@@ -95,6 +95,25 @@ pub enum SpirvType<'tcx> {
     RayQueryKhr,
 }
 
+/// With `PhysicalStorageBuffer64` addressing, `OpPtrAccessChain` on pointers
+/// that end up in the `PhysicalStorageBuffer` storage class requires their
+/// type to have an `ArrayStride` (the size of the pointee), but as storage
+/// classes are only inferred later, every pointer type gets one, and the
+/// linker removes it from all others (see `remove_non_physical_pointer_strides`).
+fn decorate_pointer_stride(cx: &CodegenCx<'_>, pointer: Word, pointee: Word) {
+    if cx.target.addressing_model() != AddressingModel::PhysicalStorageBuffer64 {
+        return;
+    }
+    let stride = cx.lookup_type(pointee).sizeof(cx);
+    if let Some(stride) = stride.filter(|stride| stride.bytes() > 0) {
+        cx.emit_global().decorate(
+            pointer,
+            Decoration::ArrayStride,
+            iter::once(Operand::LiteralInt32(stride.bytes() as u32)),
+        );
+    }
+}
+
 impl SpirvType<'_> {
     /// Note: `Builder::type_*` should be called *nowhere else* but here, to ensure
     /// `CodegenCx::type_defs` stays up-to-date
@@ -216,15 +235,12 @@ impl SpirvType<'_> {
             Self::Pointer { pointee } => {
                 // NOTE(eddyb) we emit `StorageClass::Generic` here, but later
                 // the linker will specialize the entire SPIR-V module to use
-                // storage classes inferred from `OpVariable`s.
+                // storage classes inferred from `OpVariable`s (or, for pointers
+                // to functions, integer IDs, see `linker::devirtualize`).
                 let result = cx
                     .emit_global()
                     .type_pointer(id, StorageClass::Generic, pointee);
-                // no pointers to functions
-                if let SpirvType::Function { .. } = cx.lookup_type(pointee) {
-                    // FIXME(eddyb) use the `SPV_INTEL_function_pointers` extension.
-                    cx.zombie_with_span(result, def_span, "function pointer types are not allowed");
-                }
+                decorate_pointer_stride(cx, result, pointee);
                 result
             }
             Self::Function {
@@ -289,15 +305,12 @@ impl SpirvType<'_> {
             Self::Pointer { pointee } => {
                 // NOTE(eddyb) we emit `StorageClass::Generic` here, but later
                 // the linker will specialize the entire SPIR-V module to use
-                // storage classes inferred from `OpVariable`s.
+                // storage classes inferred from `OpVariable`s (or, for pointers
+                // to functions, integer IDs, see `linker::devirtualize`).
                 let result =
                     cx.emit_global()
                         .type_pointer(Some(id), StorageClass::Generic, pointee);
-                // no pointers to functions
-                if let SpirvType::Function { .. } = cx.lookup_type(pointee) {
-                    // FIXME(eddyb) use the `SPV_INTEL_function_pointers` extension.
-                    cx.zombie_with_span(result, def_span, "function pointer types are not allowed");
-                }
+                decorate_pointer_stride(cx, result, pointee);
                 result
             }
             ref other => cx
@@ -0,0 +1,200 @@
+//! Statistics and timings collected while compiling and linking, reported by
+//! `-Z print-codegen-stats` and `-Z time-passes` (or `-Z time-llvm-passes`).
+//!
+//! `rustc` asks for the report (through `WriteBackendMethods::print_statistics`
+//! and `print_pass_timings`) once all codegen units are done, but before linking,
+//! so the linker part of the report is printed separately, by `link::link`.
+
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rspirv::{dr::Module, spirv::Op};
+use rustc_session::Session;
+
+/// Sizes and instruction counts of one SPIR-V module.
+pub struct ModuleStats {
+    pub words: usize,
+    pub functions: usize,
+    pub opcodes: BTreeMap<Op, usize>,
+}
+
+impl ModuleStats {
+    /// `words` is the size of `module` once assembled (which the caller
+    /// usually has to do anyway, so it's not repeated here).
+    pub fn collect(module: &Module, words: usize) -> Self {
+        let mut opcodes = BTreeMap::new();
+        for inst in module.all_inst_iter() {
+            *opcodes.entry(inst.class.opcode).or_default() += 1;
+        }
+        Self {
+            words,
+            functions: module.functions.len(),
+            opcodes,
+        }
+    }
+
+    fn print(&self, name: &str) {
+        let instructions = self.opcodes.values().sum::<usize>();
+        eprintln!(
+            "{name}: {} bytes, {} functions, {instructions} instructions",
+            self.words * 4,
+            self.functions,
+        );
+
+        let mut opcodes = self.opcodes.iter().collect::<Vec<_>>();
+        opcodes.sort_by_key(|&(_, &count)| std::cmp::Reverse(count));
+        for (opcode, count) in opcodes {
+            eprintln!("    {count:>8} Op{opcode:?}");
+        }
+    }
+}
+
+struct CodegenUnitStats {
+    name: String,
+    time: Duration,
+    module: ModuleStats,
+}
+
+#[derive(Default)]
+struct LinkStats {
+    functions_before_inlining: Option<usize>,
+    functions_after_inlining: Option<usize>,
+    zombies_removed: Option<usize>,
+    outputs: Vec<(String, ModuleStats)>,
+
+    /// Time spent in each phase (see `link_timer`), in the order the phases
+    /// were first entered (phases running more than once are summed up).
+    phases: Vec<(&'static str, Duration)>,
+}
+
+struct Stats {
+    codegen_units: Vec<CodegenUnitStats>,
+    link: Option<LinkStats>,
+}
+
+impl Stats {
+    const EMPTY: Self = Self {
+        codegen_units: Vec::new(),
+        link: None,
+    };
+}
+
+/// Everything recorded since the last report (see `print_link_report`).
+static STATS: Mutex<Stats> = Mutex::new(Stats::EMPTY);
+
+fn with_link_stats(f: impl FnOnce(&mut LinkStats)) {
+    f(STATS
+        .lock()
+        .unwrap()
+        .link
+        .get_or_insert_with(Default::default));
+}
+
+/// Whether statistics (or timings) should be collected at all, as collecting
+/// them isn't free (e.g. counting instructions in every module).
+pub fn enabled(sess: &Session) -> bool {
+    sess.print_llvm_stats() || sess.time_passes() || sess.opts.unstable_opts.time_llvm_passes
+}
+
+pub fn record_codegen_unit(name: String, time: Duration, module: ModuleStats) {
+    STATS
+        .lock()
+        .unwrap()
+        .codegen_units
+        .push(CodegenUnitStats { name, time, module });
+}
+
+pub fn record_inlining(functions_before: usize, functions_after: usize) {
+    with_link_stats(|link| {
+        link.functions_before_inlining = Some(functions_before);
+        link.functions_after_inlining = Some(functions_after);
+    });
+}
+
+pub fn record_zombies_removed(count: usize) {
+    with_link_stats(|link| link.zombies_removed = Some(count));
+}
+
+pub fn record_link_output(name: String, module: ModuleStats) {
+    with_link_stats(|link| link.outputs.push((name, module)));
+}
+
+/// Like `Session::timer`, but also recording the time spent in the phase
+/// `what`, for the linker timings report (see `print_link_report`).
+pub fn link_timer<'a>(sess: &'a Session, what: &'static str) -> impl Sized + 'a {
+    struct PhaseTimer {
+        what: &'static str,
+        start: Instant,
+    }
+
+    impl Drop for PhaseTimer {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed();
+            with_link_stats(|link| {
+                match link.phases.iter_mut().find(|(what, _)| *what == self.what) {
+                    Some((_, time)) => *time += elapsed,
+                    None => link.phases.push((self.what, elapsed)),
+                }
+            });
+        }
+    }
+
+    let phase_timer = enabled(sess).then(|| PhaseTimer {
+        what,
+        start: Instant::now(),
+    });
+    (sess.timer(what), phase_timer)
+}
+
+pub fn print_codegen_unit_timings() {
+    let stats = STATS.lock().unwrap();
+    eprintln!("=== rustc_codegen_spir codegen unit timings ===");
+    for cgu in &stats.codegen_units {
+        eprintln!("{:>10.3}s {}", cgu.time.as_secs_f64(), cgu.name);
+    }
+}
+
+pub fn print_codegen_unit_statistics() {
+    let stats = STATS.lock().unwrap();
+    eprintln!("=== rustc_codegen_spir codegen unit statistics ===");
+    for cgu in &stats.codegen_units {
+        cgu.module.print(&format!("codegen unit `{}`", cgu.name));
+    }
+}
+
+/// Prints everything recorded while linking, i.e. statistics with
+/// `-Z print-codegen-stats`, and timings with `-Z time-passes`, then resets
+/// all statistics (including the codegen unit ones, already reported by now),
+/// so that they don't leak into later compilations in the same process.
+pub fn print_link_report(sess: &Session) {
+    let stats = std::mem::replace(&mut *STATS.lock().unwrap(), Stats::EMPTY);
+    let Some(link) = stats.link else {
+        return;
+    };
+
+    if sess.print_llvm_stats() {
+        eprintln!("=== rustc_codegen_spir link statistics ===");
+        if let (Some(before), Some(after)) = (
+            link.functions_before_inlining,
+            link.functions_after_inlining,
+        ) {
+            eprintln!("functions: {before} before inlining, {after} after inlining");
+        }
+        if let Some(zombies_removed) = link.zombies_removed {
+            eprintln!("zombies removed: {zombies_removed}");
+        }
+        for (name, module) in &link.outputs {
+            module.print(&format!("output module `{name}`"));
+        }
+    }
+
+    if sess.time_passes() || sess.opts.unstable_opts.time_llvm_passes {
+        eprintln!("=== rustc_codegen_spir link timings ===");
+        for (what, time) in link.phases {
+            eprintln!("{:>10.3}s {what}", time.as_secs_f64());
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use rspirv::spirv::{BuiltIn, ExecutionMode, ExecutionModel, StorageClass};
+use rspirv::spirv::{BuiltIn, ExecutionMode, ExecutionModel, Scope, StorageClass};
 use rustc_ast::ast::{AttrKind, Attribute, LitIntType, LitKind, MetaItemLit, NestedMetaItem};
 use rustc_data_structures::fx::FxHashMap;
 use rustc_span::{
@@ -9,7 +9,7 @@ use rustc_span::{
 };
 
 use crate::{
-    attr::{Entry, ExecutionModeExtra, IntrinsicType, SpecConstant, SpirvAttribute},
+    attr::{AtomicFloatOp, Entry, ExecutionModeExtra, IntrinsicType, SpecConstant, SpirvAttribute},
     builder::libm_intrinsics,
 };
 
@@ -19,7 +19,7 @@ use crate::{
 /// already exists, deduplicating it if so. This makes things like comparison and cloning really cheap. So, this struct
 /// is to allocate all our keywords up front and intern them all, so we can do comparisons really easily and fast.
 pub struct Symbols {
-    // Used by `is_blocklisted_fn`.
+    // Used by `CodegenCx::is_cpu_only_fn`.
     pub fmt_decimal: Symbol,
 
     pub discriminant: Symbol,
@@ -30,7 +30,9 @@ pub struct Symbols {
     pub num_traits: Symbol,
     pub entry_point_name: Symbol,
     pub spv_intel_shader_integer_functions2: Symbol,
+    pub spv_intel_kernel_attributes: Symbol,
     pub spv_khr_vulkan_memory_model: Symbol,
+    pub spv_khr_physical_storage_buffer: Symbol,
 
     descriptor_set: Symbol,
     binding: Symbol,
@@ -40,8 +42,11 @@ pub struct Symbols {
     id: Symbol,
     default: Symbol,
 
+    atomic_scope: Symbol,
+
     attributes: FxHashMap<Symbol, SpirvAttribute>,
     execution_modes: FxHashMap<Symbol, (ExecutionMode, ExecutionModeExtraDim)>,
+    atomic_scopes: FxHashMap<Symbol, Scope>,
     pub libm_intrinsics: FxHashMap<Symbol, libm_intrinsics::LibmIntrinsic>,
 }
 
@@ -76,15 +81,15 @@ const BUILTINS: &[(&str, BuiltIn)] = {
         ("local_invocation_id", LocalInvocationId),
         ("global_invocation_id", GlobalInvocationId),
         ("local_invocation_index", LocalInvocationIndex),
-        // ("work_dim", WorkDim), -- Kernel-only
-        // ("global_size", GlobalSize), -- Kernel-only
-        // ("enqueued_workgroup_size", EnqueuedWorkgroupSize), -- Kernel-only
-        // ("global_offset", GlobalOffset), -- Kernel-only
-        // ("global_linear_id", GlobalLinearId), -- Kernel-only
+        ("work_dim", WorkDim),                              // Kernel-only
+        ("global_size", GlobalSize),                        // Kernel-only
+        ("enqueued_workgroup_size", EnqueuedWorkgroupSize), // Kernel-only
+        ("global_offset", GlobalOffset),                    // Kernel-only
+        ("global_linear_id", GlobalLinearId),               // Kernel-only
         ("subgroup_size", SubgroupSize),
-        // ("subgroup_max_size", SubgroupMaxSize), -- Kernel-only
+        ("subgroup_max_size", SubgroupMaxSize), // Kernel-only
         ("num_subgroups", NumSubgroups),
-        // ("num_enqueued_subgroups", NumEnqueuedSubgroups), -- Kernel-only
+        ("num_enqueued_subgroups", NumEnqueuedSubgroups), // Kernel-only
         ("subgroup_id", SubgroupId),
         ("subgroup_local_invocation_id", SubgroupLocalInvocationId),
         ("vertex_index", VertexIndex),
@@ -178,6 +183,19 @@ const STORAGE_CLASSES: &[(&str, StorageClass)] = {
     ]
 };
 
+// Used by `#[spirv(atomic_scope(...))]`.
+const ATOMIC_SCOPES: &[(&str, Scope)] = {
+    use Scope::*;
+    &[
+        ("cross_device", CrossDevice),
+        ("device", Device),
+        ("queue_family", QueueFamily),
+        ("workgroup", Workgroup),
+        ("subgroup", Subgroup),
+        ("invocation", Invocation),
+    ]
+};
+
 const EXECUTION_MODELS: &[(&str, ExecutionModel)] = {
     use ExecutionModel::*;
     &[
@@ -187,6 +205,7 @@ const EXECUTION_MODELS: &[(&str, ExecutionModel)] = {
         ("geometry", Geometry),
         ("fragment", Fragment),
         ("compute", GLCompute),
+        ("kernel", Kernel),
         ("task_nv", TaskNV),
         ("mesh_nv", MeshNV),
         ("ray_generation", ExecutionModel::RayGenerationKHR),
@@ -299,13 +318,13 @@ const EXECUTION_MODES: &[(&str, ExecutionMode, ExecutionModeExtraDim)] = {
             ShadingRateInterlockUnorderedEXT,
             None,
         ),
-        // Reserved
-        // ("max_workgroup_size_intel_x", MaxWorkgroupSizeINTEL, X),
-        // ("max_workgroup_size_intel_y", MaxWorkgroupSizeINTEL, Y),
-        // ("max_workgroup_size_intel_z", MaxWorkgroupSizeINTEL, Z),
-        // ("max_work_dim_intel", MaxWorkDimINTEL, Value),
-        // ("no_global_offset_intel", NoGlobalOffsetINTEL, None),
-        // ("num_simd_workitems_intel", NumSIMDWorkitemsINTEL, Value),
+        // Require `SPV_INTEL_kernel_attributes` (see `CodegenCx::check_intel_execution_mode`).
+        ("max_workgroup_size_intel_x", MaxWorkgroupSizeINTEL, X),
+        ("max_workgroup_size_intel_y", MaxWorkgroupSizeINTEL, Y),
+        ("max_workgroup_size_intel_z", MaxWorkgroupSizeINTEL, Z),
+        ("max_work_dim_intel", MaxWorkDimINTEL, Value),
+        ("no_global_offset_intel", NoGlobalOffsetINTEL, None),
+        ("num_simd_workitems_intel", NumSIMDWorkitemsINTEL, Value),
     ]
 };
 
@@ -352,11 +371,24 @@ impl Symbols {
                 "matrix",
                 SpirvAttribute::IntrinsicType(IntrinsicType::Matrix),
             ),
+            ("cpu_only", SpirvAttribute::CpuOnly),
             ("buffer_load_intrinsic", SpirvAttribute::BufferLoadIntrinsic),
             (
                 "buffer_store_intrinsic",
                 SpirvAttribute::BufferStoreIntrinsic,
             ),
+            (
+                "atomic_float_add_intrinsic",
+                SpirvAttribute::AtomicFloatIntrinsic(AtomicFloatOp::Add),
+            ),
+            (
+                "atomic_float_min_intrinsic",
+                SpirvAttribute::AtomicFloatIntrinsic(AtomicFloatOp::Min),
+            ),
+            (
+                "atomic_float_max_intrinsic",
+                SpirvAttribute::AtomicFloatIntrinsic(AtomicFloatOp::Max),
+            ),
         ]
         .iter()
         .cloned();
@@ -377,6 +409,11 @@ impl Symbols {
             let old = execution_modes.insert(Symbol::intern(key), (mode, dim));
             assert!(old.is_none());
         }
+        let mut atomic_scopes = FxHashMap::default();
+        for &(key, scope) in ATOMIC_SCOPES {
+            let old = atomic_scopes.insert(Symbol::intern(key), scope);
+            assert!(old.is_none());
+        }
 
         let mut libm_intrinsics = FxHashMap::default();
         for &(a, b) in libm_intrinsics::TABLE {
@@ -396,7 +433,9 @@ impl Symbols {
             spv_intel_shader_integer_functions2: Symbol::intern(
                 "SPV_INTEL_shader_integer_functions2",
             ),
+            spv_intel_kernel_attributes: Symbol::intern("SPV_INTEL_kernel_attributes"),
             spv_khr_vulkan_memory_model: Symbol::intern("SPV_KHR_vulkan_memory_model"),
+            spv_khr_physical_storage_buffer: Symbol::intern("SPV_KHR_physical_storage_buffer"),
 
             descriptor_set: Symbol::intern("descriptor_set"),
             binding: Symbol::intern("binding"),
@@ -406,8 +445,11 @@ impl Symbols {
             id: Symbol::intern("id"),
             default: Symbol::intern("default"),
 
+            atomic_scope: Symbol::intern("atomic_scope"),
+
             attributes,
             execution_modes,
+            atomic_scopes,
             libm_intrinsics,
         }
     }
@@ -482,6 +524,8 @@ pub(crate) fn parse_attrs_for_checking<'a>(
                     SpirvAttribute::InputAttachmentIndex(parse_attr_int_value(arg)?)
                 } else if arg.has_name(sym.spec_constant) {
                     SpirvAttribute::SpecConstant(parse_spec_constant_attr(sym, arg)?)
+                } else if arg.has_name(sym.atomic_scope) {
+                    SpirvAttribute::AtomicScope(parse_atomic_scope_attr(sym, arg)?)
                 } else {
                     let name = match arg.ident() {
                         Some(i) => i,
@@ -542,6 +586,24 @@ fn parse_spec_constant_attr(
     })
 }
 
+fn parse_atomic_scope_attr(sym: &Symbols, arg: &NestedMetaItem) -> Result<Scope, ParseAttrError> {
+    let expected = || {
+        let names = ATOMIC_SCOPES
+            .iter()
+            .map(|&(name, _)| name)
+            .collect::<Vec<_>>()
+            .join("|");
+        (arg.span(), format!("expected `atomic_scope({names})`"))
+    };
+    match arg.meta_item_list() {
+        Some([scope]) => scope
+            .ident()
+            .and_then(|name| sym.atomic_scopes.get(&name.name).copied())
+            .ok_or_else(expected),
+        _ => Err(expected()),
+    }
+}
+
 fn parse_attr_int_value(arg: &NestedMetaItem) -> Result<u32, ParseAttrError> {
     let arg = match arg.meta_item() {
         Some(arg) => arg,
@@ -610,8 +672,7 @@ fn parse_entry_attrs(
     let mut origin_mode: Option<ExecutionMode> = None;
     let mut local_size: Option<[u32; 3]> = None;
     let mut local_size_hint: Option<[u32; 3]> = None;
-    // Reserved
-    // let mut max_workgroup_size_intel: Option<[u32; 3]> = None;
+    let mut max_workgroup_size_intel: Option<[u32; 3]> = None;
     if let Some(attrs) = arg.meta_item_list() {
         for attr in attrs {
             if let Some(attr_name) = attr.ident() {
@@ -657,27 +718,26 @@ fn parse_entry_attrs(
                                 _ => unreachable!(),
                             }
                         }
-                        // Reserved
-                        // MaxWorkgroupSizeINTEL => {
-                        // let val = val.unwrap();
-                        // if max_workgroup_size_intel.is_none() {
-                        // max_workgroup_size_intel.replace([1, 1, 1]);
-                        // }
-                        // let max_workgroup_size_intel = max_workgroup_size_intel.as_mut()
-                        // .unwrap();
-                        // match extra_dim {
-                        // X => {
-                        // max_workgroup_size_intel[0] = val;
-                        // },
-                        // Y => {
-                        // max_workgroup_size_intel[1] = val;
-                        // },
-                        // Z => {
-                        // max_workgroup_size_intel[2] = val;
-                        // },
-                        // _ => unreachable!(),
-                        // }
-                        // },
+                        MaxWorkgroupSizeINTEL => {
+                            let val = val.unwrap();
+                            if max_workgroup_size_intel.is_none() {
+                                max_workgroup_size_intel.replace([1, 1, 1]);
+                            }
+                            let max_workgroup_size_intel =
+                                max_workgroup_size_intel.as_mut().unwrap();
+                            match extra_dim {
+                                X => {
+                                    max_workgroup_size_intel[0] = val;
+                                }
+                                Y => {
+                                    max_workgroup_size_intel[1] = val;
+                                }
+                                Z => {
+                                    max_workgroup_size_intel[2] = val;
+                                }
+                                _ => unreachable!(),
+                            }
+                        }
                         _ => {
                             if let Some(val) = val {
                                 entry
@@ -718,6 +778,12 @@ fn parse_entry_attrs(
             }
         }
     }
+    if let Some(max_workgroup_size_intel) = max_workgroup_size_intel {
+        entry.execution_modes.push((
+            MaxWorkgroupSizeINTEL,
+            ExecutionModeExtra::new(max_workgroup_size_intel),
+        ));
+    }
     match entry.execution_model {
         Fragment => {
             let origin_mode = origin_mode.unwrap_or(OriginUpperLeft);
@@ -1,76 +1,378 @@
-use rspirv::spirv::MemoryModel;
-use rustc_target::spec::{Cc, LinkerFlavor, PanicStrategy, Target, TargetMetadata, TargetOptions};
-use spirv_tools::TargetEnv;
+use rspirv::spirv::{AddressingModel, Capability, MemoryModel};
+use rustc_span::edit_distance::edit_distance;
+use rustc_target::{
+    json::ToJson,
+    spec::{Cc, LinkerFlavor, PanicStrategy, Target, TargetMetadata, TargetOptions},
+};
+
 const ARCH: &str = "spirv";
+const ARCH_64: &str = "spirv64";
+
+/// The client API / SPIR-V environment a target is for (the last component of
+/// a `spirv-unknown-*` target triple).
+///
+/// This is intentionally independent from `spirv_tools::TargetEnv`, which only
+/// gets used (see `to_spirv_tools`) when invoking `spirv-val`/`spirv-opt`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub enum SpirvTargetEnv {
+    Universal_1_0,
+    Universal_1_1,
+    Universal_1_2,
+    Universal_1_3,
+    Universal_1_4,
+    Universal_1_5,
+    Universal_1_6,
+
+    OpenGL_4_0,
+    OpenGL_4_1,
+    OpenGL_4_2,
+    OpenGL_4_3,
+    OpenGL_4_5,
+
+    OpenCL_1_2,
+    OpenCLEmbedded_1_2,
+    OpenCL_2_0,
+    OpenCLEmbedded_2_0,
+    OpenCL_2_1,
+    OpenCLEmbedded_2_1,
+    OpenCL_2_2,
+    OpenCLEmbedded_2_2,
+
+    Vulkan_1_0,
+    Vulkan_1_1,
+    Vulkan_1_1_Spirv_1_4,
+    Vulkan_1_2,
+    Vulkan_1_3,
+
+    WebGPU_0,
+}
+
+impl SpirvTargetEnv {
+    pub const ALL: [Self; 26] = [
+        Self::Universal_1_0,
+        Self::Universal_1_1,
+        Self::Universal_1_2,
+        Self::Universal_1_3,
+        Self::Universal_1_4,
+        Self::Universal_1_5,
+        Self::Universal_1_6,
+        Self::OpenGL_4_0,
+        Self::OpenGL_4_1,
+        Self::OpenGL_4_2,
+        Self::OpenGL_4_3,
+        Self::OpenGL_4_5,
+        Self::OpenCL_1_2,
+        Self::OpenCLEmbedded_1_2,
+        Self::OpenCL_2_0,
+        Self::OpenCLEmbedded_2_0,
+        Self::OpenCL_2_1,
+        Self::OpenCLEmbedded_2_1,
+        Self::OpenCL_2_2,
+        Self::OpenCLEmbedded_2_2,
+        Self::Vulkan_1_0,
+        Self::Vulkan_1_1,
+        Self::Vulkan_1_1_Spirv_1_4,
+        Self::Vulkan_1_2,
+        Self::Vulkan_1_3,
+        Self::WebGPU_0,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Universal_1_0 => "spv1.0",
+            Self::Universal_1_1 => "spv1.1",
+            Self::Universal_1_2 => "spv1.2",
+            Self::Universal_1_3 => "spv1.3",
+            Self::Universal_1_4 => "spv1.4",
+            Self::Universal_1_5 => "spv1.5",
+            Self::Universal_1_6 => "spv1.6",
+
+            Self::OpenGL_4_0 => "opengl4.0",
+            Self::OpenGL_4_1 => "opengl4.1",
+            Self::OpenGL_4_2 => "opengl4.2",
+            Self::OpenGL_4_3 => "opengl4.3",
+            Self::OpenGL_4_5 => "opengl4.5",
+
+            Self::OpenCL_1_2 => "opencl1.2",
+            Self::OpenCLEmbedded_1_2 => "opencl1.2embedded",
+            Self::OpenCL_2_0 => "opencl2.0",
+            Self::OpenCLEmbedded_2_0 => "opencl2.0embedded",
+            Self::OpenCL_2_1 => "opencl2.1",
+            Self::OpenCLEmbedded_2_1 => "opencl2.1embedded",
+            Self::OpenCL_2_2 => "opencl2.2",
+            Self::OpenCLEmbedded_2_2 => "opencl2.2embedded",
+
+            Self::Vulkan_1_0 => "vulkan1.0",
+            Self::Vulkan_1_1 => "vulkan1.1",
+            Self::Vulkan_1_1_Spirv_1_4 => "vulkan1.1spv1.4",
+            Self::Vulkan_1_2 => "vulkan1.2",
+            Self::Vulkan_1_3 => "vulkan1.3",
+
+            Self::WebGPU_0 => "webgpu0",
+        }
+    }
+
+    pub fn memory_model(self) -> MemoryModel {
+        match self {
+            Self::Universal_1_0
+            | Self::Universal_1_1
+            | Self::Universal_1_2
+            | Self::Universal_1_3
+            | Self::Universal_1_4
+            | Self::Universal_1_5
+            | Self::Universal_1_6 => MemoryModel::Simple,
+
+            Self::OpenGL_4_0
+            | Self::OpenGL_4_1
+            | Self::OpenGL_4_2
+            | Self::OpenGL_4_3
+            | Self::OpenGL_4_5 => MemoryModel::GLSL450,
+
+            Self::OpenCL_1_2
+            | Self::OpenCLEmbedded_1_2
+            | Self::OpenCL_2_0
+            | Self::OpenCLEmbedded_2_0
+            | Self::OpenCL_2_1
+            | Self::OpenCLEmbedded_2_1
+            | Self::OpenCL_2_2
+            | Self::OpenCLEmbedded_2_2 => MemoryModel::OpenCL,
+
+            Self::Vulkan_1_0
+            | Self::Vulkan_1_1
+            | Self::Vulkan_1_1_Spirv_1_4
+            | Self::Vulkan_1_2
+            | Self::Vulkan_1_3
+            | Self::WebGPU_0 => MemoryModel::Vulkan,
+        }
+    }
+
+    pub fn spirv_version(self) -> (u8, u8) {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            Self::Universal_1_0 => (1, 0),
+            Self::Universal_1_1 => (1, 1),
+            Self::Universal_1_2 => (1, 2),
+            Self::Universal_1_3 => (1, 3),
+            Self::Universal_1_4 => (1, 4),
+            Self::Universal_1_5 => (1, 5),
+            Self::Universal_1_6 => (1, 6),
+
+            Self::OpenGL_4_0 => (1, 0),
+            Self::OpenGL_4_1 => (1, 0),
+            Self::OpenGL_4_2 => (1, 0),
+            Self::OpenGL_4_3 => (1, 0),
+            Self::OpenGL_4_5 => (1, 0),
+
+            Self::OpenCL_1_2 => (1, 0),
+            Self::OpenCLEmbedded_1_2 => (1, 0),
+            Self::OpenCL_2_0 => (1, 0),
+            Self::OpenCLEmbedded_2_0 => (1, 0),
+            Self::OpenCL_2_1 => (1, 0),
+            Self::OpenCLEmbedded_2_1 => (1, 0),
+            Self::OpenCL_2_2 => (1, 2),
+            Self::OpenCLEmbedded_2_2 => (1, 2),
+
+            Self::Vulkan_1_0 => (1, 0),
+            Self::Vulkan_1_1 => (1, 3),
+            Self::Vulkan_1_1_Spirv_1_4 => (1, 4),
+            Self::Vulkan_1_2 => (1, 5),
+            Self::Vulkan_1_3 => (1, 6),
+
+            Self::WebGPU_0 => (1, 3),
+        }
+    }
+
+    /// The `spirv-tools` environment (for `spirv-val` and `spirv-opt`).
+    //
+    // FIXME `spirv-tools` doesn't know about SPIR-V 1.6 yet, so the 1.6
+    // environments use the closest 1.5 one instead (and `link` presents their
+    // modules to `spirv-tools` as 1.5 ones). Skipping `spirv-val`/`spirv-opt`
+    // entirely would be worse, and 1.6 mostly deprecates or promotes (from
+    // extensions) existing features, so only its own new rules go unchecked.
+    pub fn to_spirv_tools(self) -> spirv_tools::TargetEnv {
+        use spirv_tools::TargetEnv;
+
+        match self {
+            Self::Universal_1_0 => TargetEnv::Universal_1_0,
+            Self::Universal_1_1 => TargetEnv::Universal_1_1,
+            Self::Universal_1_2 => TargetEnv::Universal_1_2,
+            Self::Universal_1_3 => TargetEnv::Universal_1_3,
+            Self::Universal_1_4 => TargetEnv::Universal_1_4,
+            Self::Universal_1_5 => TargetEnv::Universal_1_5,
+            Self::Universal_1_6 => TargetEnv::Universal_1_5,
+
+            Self::OpenGL_4_0 => TargetEnv::OpenGL_4_0,
+            Self::OpenGL_4_1 => TargetEnv::OpenGL_4_1,
+            Self::OpenGL_4_2 => TargetEnv::OpenGL_4_2,
+            Self::OpenGL_4_3 => TargetEnv::OpenGL_4_3,
+            Self::OpenGL_4_5 => TargetEnv::OpenGL_4_5,
+
+            Self::OpenCL_1_2 => TargetEnv::OpenCL_1_2,
+            Self::OpenCLEmbedded_1_2 => TargetEnv::OpenCLEmbedded_1_2,
+            Self::OpenCL_2_0 => TargetEnv::OpenCL_2_0,
+            Self::OpenCLEmbedded_2_0 => TargetEnv::OpenCLEmbedded_2_0,
+            Self::OpenCL_2_1 => TargetEnv::OpenCL_2_1,
+            Self::OpenCLEmbedded_2_1 => TargetEnv::OpenCLEmbedded_2_1,
+            Self::OpenCL_2_2 => TargetEnv::OpenCL_2_2,
+            Self::OpenCLEmbedded_2_2 => TargetEnv::OpenCLEmbedded_2_2,
+
+            Self::Vulkan_1_0 => TargetEnv::Vulkan_1_0,
+            Self::Vulkan_1_1 => TargetEnv::Vulkan_1_1,
+            Self::Vulkan_1_1_Spirv_1_4 => TargetEnv::Vulkan_1_1_Spirv_1_4,
+            Self::Vulkan_1_2 => TargetEnv::Vulkan_1_2,
+            Self::Vulkan_1_3 => TargetEnv::Vulkan_1_2,
+
+            Self::WebGPU_0 => TargetEnv::WebGPU_0,
+        }
+    }
+}
+
+impl std::str::FromStr for SpirvTargetEnv {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|env| env.as_str() == s)
+            .ok_or(())
+    }
+}
+
+impl std::fmt::Display for SpirvTargetEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 pub struct SpirvTarget {
-    env: TargetEnv,
+    env: SpirvTargetEnv,
+    /// Explicit `spvX.Y` suffix on the environment (e.g. `vulkan1.2spv1.6`),
+    /// only kept when it differs from the environment's own SPIR-V version.
+    spirv_version: Option<(u8, u8)>,
     vendor: String,
+    pointer_width: u32,
 }
 
 impl SpirvTarget {
     pub fn memory_model(&self) -> MemoryModel {
+        self.env.memory_model()
+    }
+
+    /// Whether this target produces OpenCL-style compute kernels (i.e. the
+    /// `Kernel` capability and physical addressing), instead of shader modules.
+    pub fn is_kernel(&self) -> bool {
+        self.memory_model() == MemoryModel::OpenCL
+    }
+
+    /// Capabilities the linker may enable on its own, when the module ends up
+    /// needing them (see `linker::capability_computation`), i.e. without them
+    /// having to be enabled through `-Ctarget-feature`.
+    ///
+    /// Anything else the module needs is an error, unless explicitly enabled.
+    /// This is only the default, which `--inferable-capabilities` overrides.
+    pub fn inferable_capabilities(&self) -> &'static [Capability] {
+        use Capability::*;
+        use SpirvTargetEnv::*;
+
         match self.env {
-            TargetEnv::Universal_1_0
-            | TargetEnv::Universal_1_1
-            | TargetEnv::Universal_1_2
-            | TargetEnv::Universal_1_3
-            | TargetEnv::Universal_1_4
-            | TargetEnv::Universal_1_5 => MemoryModel::Simple,
-
-            TargetEnv::OpenGL_4_0
-            | TargetEnv::OpenGL_4_1
-            | TargetEnv::OpenGL_4_2
-            | TargetEnv::OpenGL_4_3
-            | TargetEnv::OpenGL_4_5 => MemoryModel::GLSL450,
-
-            TargetEnv::OpenCL_2_1
-            | TargetEnv::OpenCL_2_2
-            | TargetEnv::OpenCL_1_2
-            | TargetEnv::OpenCLEmbedded_1_2
-            | TargetEnv::OpenCL_2_0
-            | TargetEnv::OpenCLEmbedded_2_0
-            | TargetEnv::OpenCLEmbedded_2_1
-            | TargetEnv::OpenCLEmbedded_2_2 => MemoryModel::OpenCL,
-
-            TargetEnv::Vulkan_1_0
-            | TargetEnv::Vulkan_1_1
-            | TargetEnv::WebGPU_0
-            | TargetEnv::Vulkan_1_1_Spirv_1_4
-            | TargetEnv::Vulkan_1_2 => MemoryModel::Vulkan,
+            Universal_1_0 | Universal_1_1 | Universal_1_2 | Universal_1_3 | Universal_1_4
+            | Universal_1_5 | Universal_1_6 | Vulkan_1_0 | Vulkan_1_1 | Vulkan_1_1_Spirv_1_4
+            | Vulkan_1_2 | Vulkan_1_3 => &[
+                Shader,
+                Matrix,
+                Int8,
+                Int16,
+                Int64,
+                Float16,
+                Float64,
+                Int64Atomics,
+                ImageQuery,
+                DerivativeControl,
+                StorageImageReadWithoutFormat,
+                StorageImageWriteWithoutFormat,
+            ],
+            OpenGL_4_0 | OpenGL_4_1 | OpenGL_4_2 | OpenGL_4_3 | OpenGL_4_5 => &[
+                Shader,
+                Matrix,
+                Int64,
+                Float64,
+                ImageQuery,
+                DerivativeControl,
+            ],
+            WebGPU_0 => &[Shader, Matrix, ImageQuery, DerivativeControl],
+            OpenCL_1_2 | OpenCLEmbedded_1_2 | OpenCL_2_0 | OpenCLEmbedded_2_0 | OpenCL_2_1
+            | OpenCLEmbedded_2_1 | OpenCL_2_2 | OpenCLEmbedded_2_2 => &[
+                Kernel,
+                Addresses,
+                Int8,
+                Int16,
+                Int64,
+                Float16Buffer,
+                Float64,
+                Vector16,
+                GenericPointer,
+            ],
+        }
+    }
+
+    pub fn pointer_width(&self) -> u32 {
+        self.pointer_width
+    }
+
+    pub fn addressing_model(&self) -> AddressingModel {
+        match (self.is_kernel(), self.pointer_width) {
+            (true, 64) => AddressingModel::Physical64,
+            (true, _) => AddressingModel::Physical32,
+            // NOTE 64-bit shaders can only use physical pointers for
+            // `PhysicalStorageBuffer` (i.e. Vulkan's "buffer device address"),
+            // all other storage classes remain logical.
+            (false, 64) => AddressingModel::PhysicalStorageBuffer64,
+            (false, _) => AddressingModel::Logical,
+        }
+    }
+
+    fn arch(&self) -> &'static str {
+        match self.pointer_width {
+            64 => ARCH_64,
+            _ => ARCH,
+        }
+    }
+
+    fn data_layout(&self) -> &'static str {
+        match self.pointer_width {
+            64 => "e-m:e-p:64:64:64-i64:64-n8:16:32:64",
+            _ => "e-m:e-p:32:32:32-i64:64-n8:16:32:64",
         }
     }
 
     pub fn spirv_version(&self) -> (u8, u8) {
-        #[allow(clippy::match_same_arms)]
-        match self.env {
-            TargetEnv::Universal_1_0 => (1, 0),
-            TargetEnv::Universal_1_1 => (1, 1),
-            TargetEnv::Universal_1_2 => (1, 2),
-            TargetEnv::Universal_1_3 => (1, 3),
-            TargetEnv::Universal_1_4 => (1, 4),
-            TargetEnv::Universal_1_5 => (1, 5),
-
-            TargetEnv::OpenGL_4_0 => (1, 0),
-            TargetEnv::OpenGL_4_1 => (1, 0),
-            TargetEnv::OpenGL_4_2 => (1, 0),
-            TargetEnv::OpenGL_4_3 => (1, 0),
-            TargetEnv::OpenGL_4_5 => (1, 0),
-
-            TargetEnv::OpenCL_1_2 => (1, 0),
-            TargetEnv::OpenCL_2_0 => (1, 0),
-            TargetEnv::OpenCL_2_1 => (1, 0),
-            TargetEnv::OpenCL_2_2 => (1, 2),
-            TargetEnv::OpenCLEmbedded_1_2 => (1, 0),
-            TargetEnv::OpenCLEmbedded_2_0 => (1, 0),
-            TargetEnv::OpenCLEmbedded_2_1 => (1, 0),
-            TargetEnv::OpenCLEmbedded_2_2 => (1, 2),
-
-            TargetEnv::Vulkan_1_0 => (1, 0),
-            TargetEnv::Vulkan_1_1 => (1, 3),
-            TargetEnv::WebGPU_0 => (1, 3),
-            TargetEnv::Vulkan_1_1_Spirv_1_4 => (1, 4),
-            TargetEnv::Vulkan_1_2 => (1, 5),
+        self.spirv_version
+            .unwrap_or_else(|| self.env.spirv_version())
+    }
+
+    /// The `spirv-tools` environment to validate and optimize with.
+    ///
+    /// An explicit `spvX.Y` suffix can't just be dropped (the module header
+    /// still uses that SPIR-V version), so the oldest environment that allows
+    /// it is used instead (with SPIR-V 1.6 treated as 1.5, as explained in
+    /// `SpirvTargetEnv::to_spirv_tools`).
+    pub fn to_spirv_tools(&self) -> spirv_tools::TargetEnv {
+        use spirv_tools::TargetEnv;
+
+        let Some(version) = self.spirv_version else {
+            return self.env.to_spirv_tools();
+        };
+        match (self.memory_model(), version) {
+            (MemoryModel::Vulkan, (1, 4)) => TargetEnv::Vulkan_1_1_Spirv_1_4,
+            (MemoryModel::Vulkan, (1, 5) | (1, 6)) => TargetEnv::Vulkan_1_2,
+            (_, (1, 0)) => TargetEnv::Universal_1_0,
+            (_, (1, 1)) => TargetEnv::Universal_1_1,
+            (_, (1, 2)) => TargetEnv::Universal_1_2,
+            (_, (1, 3)) => TargetEnv::Universal_1_3,
+            (_, (1, 4)) => TargetEnv::Universal_1_4,
+            (_, (1, 5) | (1, 6)) => TargetEnv::Universal_1_5,
+            (_, (major, minor)) => unreachable!("unsupported SPIR-V version {major}.{minor}"),
         }
     }
 
@@ -96,52 +398,131 @@ impl SpirvTarget {
     pub fn rustc_target(&self) -> Target {
         Target {
             llvm_target: self.to_string().into(),
-            pointer_width: 32,
-            data_layout: "e-m:e-p:32:32:32-i64:64-n8:16:32:64".into(),
-            arch: ARCH.into(),
+            pointer_width: self.pointer_width(),
+            data_layout: self.data_layout().into(),
+            arch: self.arch().into(),
             options: self.init_target_opts(),
             metadata: TargetMetadata::default(),
         }
     }
 }
 
+/// Parses a `major.minor` SPIR-V version, as found after `spv` in triples.
+fn parse_spirv_version(s: &str) -> Option<(u8, u8)> {
+    let (major, minor) = s.split_once('.')?;
+    let version = (major.parse().ok()?, minor.parse().ok()?);
+    (version.0 == 1 && version.1 <= 6).then_some(version)
+}
+
+/// Parses the environment component of a target triple, which may be followed
+/// by an explicit SPIR-V version, e.g. `vulkan1.2spv1.6`.
+fn parse_env(s: &str) -> Option<(SpirvTargetEnv, Option<(u8, u8)>)> {
+    // NOTE this also handles `vulkan1.1spv1.4`, which `spirv-tools`
+    // treats as its own environment, distinct from `vulkan1.1`.
+    if let Ok(env) = s.parse() {
+        return Some((env, None));
+    }
+
+    let (env, version) = s.rsplit_once("spv")?;
+    let env = env.parse::<SpirvTargetEnv>().ok()?;
+    let version = parse_spirv_version(version)?;
+
+    // The `spvX.Y` environments already are an explicit SPIR-V version.
+    if env.memory_model() == MemoryModel::Simple {
+        return None;
+    }
+    match version.cmp(&env.spirv_version()) {
+        std::cmp::Ordering::Less => None,
+        std::cmp::Ordering::Equal => Some((env, None)),
+        std::cmp::Ordering::Greater => Some((env, Some(version))),
+    }
+}
+
 impl std::str::FromStr for SpirvTarget {
     type Err = InvalidTarget;
 
     fn from_str(target: &str) -> Result<Self, Self::Err> {
-        let iter = target.split('-').collect::<Vec<&str>>();
-        let error = || InvalidTarget(target.into());
+        let error = || InvalidTarget::new(target);
+
+        let mut components = target.split('-');
+        let (Some(arch), Some(vendor), Some(env), None) = (
+            components.next(),
+            components.next(),
+            components.next(),
+            components.next(),
+        ) else {
+            return Err(error());
+        };
+
+        let pointer_width = match arch {
+            ARCH => 32,
+            ARCH_64 => 64,
+            _ => return Err(error()),
+        };
 
-        if iter[0] != ARCH {
+        if vendor.is_empty()
+            || !vendor
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
             return Err(error());
         }
 
-        let vendor = iter[1].to_string();
+        let (env, spirv_version) = parse_env(env).ok_or_else(error)?;
 
-        let env = TargetEnv::from_str(iter[2]).unwrap();
+        let result = Self {
+            env,
+            spirv_version,
+            vendor: vendor.to_string(),
+            pointer_width,
+        };
+
+        // 64-bit pointers need either physical addressing (OpenCL kernels),
+        // or `PhysicalStorageBuffer64` (Vulkan with buffer device address,
+        // which WebGPU doesn't have).
+        if pointer_width == 64
+            && (!matches!(
+                result.memory_model(),
+                MemoryModel::OpenCL | MemoryModel::Vulkan
+            ) || env == SpirvTargetEnv::WebGPU_0)
+        {
+            return Err(error());
+        }
 
-        let result = Self { env, vendor };
         Ok(result)
     }
 }
 
 impl std::fmt::Display for SpirvTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}-{}", ARCH, self.vendor, self.env)
+        write!(f, "{}-{}-{}", self.arch(), self.vendor, self.env)?;
+        if let Some((major, minor)) = self.spirv_version {
+            write!(f, "spv{major}.{minor}")?;
+        }
+        Ok(())
     }
 }
 
-pub(crate) const ALL_VALID_TARGETS: [&'static str; 24] = [
+/// Returns the JSON target spec for `triple`, as expected by `rustc --target`
+/// (when given a path to a `.json` file, named after the triple).
+pub fn target_spec_json(triple: &str) -> Result<String, InvalidTarget> {
+    let target = triple.parse::<SpirvTarget>()?.rustc_target();
+    Ok(serde_json::to_string_pretty(&target.to_json()).unwrap())
+}
+
+pub const ALL_VALID_TARGETS: [&'static str; 39] = [
     "spirv-unknown-spv1.0",
     "spirv-unknown-spv1.1",
     "spirv-unknown-spv1.2",
     "spirv-unknown-spv1.3",
     "spirv-unknown-spv1.4",
     "spirv-unknown-spv1.5",
+    "spirv-unknown-spv1.6",
     "spirv-unknown-vulkan1.0",
     "spirv-unknown-vulkan1.1",
     "spirv-unknown-vulkan1.1spv1.4",
     "spirv-unknown-vulkan1.2",
+    "spirv-unknown-vulkan1.3",
     "spirv-unknown-webgpu0",
     "spirv-unknown-opengl4.0",
     "spirv-unknown-opengl4.1",
@@ -156,50 +537,185 @@ pub(crate) const ALL_VALID_TARGETS: [&'static str; 24] = [
     "spirv-unknown-opencl2.1embedded",
     "spirv-unknown-opencl2.2",
     "spirv-unknown-opencl2.2embedded",
+    "spirv64-unknown-vulkan1.0",
+    "spirv64-unknown-vulkan1.1",
+    "spirv64-unknown-vulkan1.1spv1.4",
+    "spirv64-unknown-vulkan1.2",
+    "spirv64-unknown-vulkan1.3",
+    "spirv64-unknown-opencl1.2",
+    "spirv64-unknown-opencl1.2embedded",
+    "spirv64-unknown-opencl2.0",
+    "spirv64-unknown-opencl2.0embedded",
+    "spirv64-unknown-opencl2.1",
+    "spirv64-unknown-opencl2.1embedded",
+    "spirv64-unknown-opencl2.2",
+    "spirv64-unknown-opencl2.2embedded",
 ];
 
 #[derive(Debug)]
-pub struct InvalidTarget(String);
+pub struct InvalidTarget {
+    target: String,
+    suggestion: Option<&'static str>,
+}
+
+impl InvalidTarget {
+    fn new(target: &str) -> Self {
+        // Same threshold as rustc's own "did you mean" suggestions.
+        let max_dist = std::cmp::max(target.len(), 3) / 3;
+        let suggestion = ALL_VALID_TARGETS
+            .iter()
+            .filter_map(|&valid| Some((edit_distance(target, valid, max_dist)?, valid)))
+            .min_by_key(|&(dist, _)| dist)
+            .map(|(_, valid)| valid);
+        Self {
+            target: target.to_string(),
+            suggestion,
+        }
+    }
+}
 
 impl std::error::Error for InvalidTarget {}
 impl std::fmt::Display for InvalidTarget {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Invalid target `{}`.\n Valid targets: {:#?}",
-            self.0, ALL_VALID_TARGETS
-        )
+        write!(f, "Invalid target `{}`.", self.target)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, "\n Did you mean `{suggestion}`?")?;
+        }
+        write!(f, "\n Valid targets: {:#?}", ALL_VALID_TARGETS)
     }
 }
 
 #[cfg(test)]
 mod register_targets {
-    use std::{fs::File, io::Write, path::Path, str::FromStr};
+    use super::{target_spec_json, ALL_VALID_TARGETS};
 
-    use rustc_target::json::ToJson;
+    #[test]
+    fn target_spec_json_for_all_targets() {
+        for &target in &ALL_VALID_TARGETS {
+            let json: serde_json::Value =
+                serde_json::from_str(&target_spec_json(target).unwrap()).unwrap();
+            assert_eq!(json["llvm-target"], target);
+        }
+    }
+}
 
-    use crate::target::SpirvTarget;
+#[cfg(test)]
+mod parse_targets {
+    use super::{SpirvTarget, SpirvTargetEnv, ALL_VALID_TARGETS};
 
-    use super::ALL_VALID_TARGETS;
+    #[test]
+    fn all_valid_targets_roundtrip() {
+        for &target in &ALL_VALID_TARGETS {
+            let parsed = target
+                .parse::<SpirvTarget>()
+                .unwrap_or_else(|e| panic!("{e}"));
+            assert_eq!(parsed.to_string(), target);
+        }
+    }
 
     #[test]
-    fn write_target_json() {
-        // generate target json file if it is not exist
-        let target_path = Path::new("./target");
-        ALL_VALID_TARGETS.iter().for_each(|target| {
-            let target_json = target_path.join(format!("{}.json", target));
-            if let Ok(mut file) = File::create_new(target_json.clone()) {
-                file.write_all(
-                    SpirvTarget::from_str(target)
-                        .unwrap()
-                        .rustc_target()
-                        .to_json()
-                        .to_string()
-                        .as_bytes(),
-                )
-                .unwrap();
-                println!("Generating target json file: {:?}", target_json);
+    fn all_parseable_targets_are_valid() {
+        for env in SpirvTargetEnv::ALL {
+            for arch in ["spirv", "spirv64"] {
+                let target = format!("{arch}-unknown-{env}");
+                if target.parse::<SpirvTarget>().is_ok() {
+                    assert!(ALL_VALID_TARGETS.contains(&&*target), "{target}");
+                }
             }
-        });
+        }
+    }
+
+    #[test]
+    fn explicit_spirv_version() {
+        let target = "spirv-unknown-vulkan1.2spv1.6"
+            .parse::<SpirvTarget>()
+            .unwrap();
+        assert_eq!(target.spirv_version(), (1, 6));
+        assert_eq!(target.to_string(), "spirv-unknown-vulkan1.2spv1.6");
+        assert_eq!(target.to_spirv_tools(), spirv_tools::TargetEnv::Vulkan_1_2);
+
+        // Redundant versions are accepted, but normalized away.
+        let target = "spirv-unknown-vulkan1.2spv1.5"
+            .parse::<SpirvTarget>()
+            .unwrap();
+        assert_eq!(target.to_string(), "spirv-unknown-vulkan1.2");
+
+        for target in [
+            "spirv-unknown-vulkan1.2spv1.4",
+            "spirv-unknown-vulkan1.2spv1.7",
+            "spirv-unknown-vulkan1.2spv2.0",
+            "spirv-unknown-spv1.3spv1.4",
+        ] {
+            assert!(target.parse::<SpirvTarget>().is_err(), "{target}");
+        }
+    }
+
+    #[test]
+    fn spirv_tools_target_envs() {
+        use spirv_tools::TargetEnv;
+
+        for (target, expected) in [
+            ("spirv-unknown-vulkan1.1", TargetEnv::Vulkan_1_1),
+            ("spirv-unknown-vulkan1.1spv1.5", TargetEnv::Vulkan_1_2),
+            (
+                "spirv-unknown-vulkan1.0spv1.4",
+                TargetEnv::Vulkan_1_1_Spirv_1_4,
+            ),
+            ("spirv-unknown-opencl1.2spv1.3", TargetEnv::Universal_1_3),
+            ("spirv-unknown-vulkan1.3", TargetEnv::Vulkan_1_2),
+            ("spirv-unknown-spv1.6", TargetEnv::Universal_1_5),
+            ("spirv-unknown-opencl1.2spv1.6", TargetEnv::Universal_1_5),
+        ] {
+            let target = target.parse::<SpirvTarget>().unwrap();
+            assert_eq!(target.to_spirv_tools(), expected, "{target}");
+        }
+    }
+
+    #[test]
+    fn malformed_targets() {
+        for target in [
+            "",
+            "spirv",
+            "spirv-unknown",
+            "spirv-unknown-",
+            "spirv--vulkan1.1",
+            "-unknown-vulkan1.1",
+            "spirv-unknown-vulkan1.1-extra",
+            "spirv-unknown-vulkan",
+            "spirv-unknown-spv",
+            "spirv64-unknown-spv1.5",
+            "spirv64-unknown-opengl4.5",
+            "spirv64-unknown-webgpu0",
+        ] {
+            assert!(target.parse::<SpirvTarget>().is_err(), "{target:?}");
+        }
+    }
+
+    #[test]
+    fn typo_suggestions() {
+        for (typo, expected) in [
+            ("spirv-unknown-vulkn1.1", "spirv-unknown-vulkan1.1"),
+            ("spir-unknown-vulkan1.1", "spirv-unknown-vulkan1.1"),
+            ("spirv-unkown-vulkan1.2", "spirv-unknown-vulkan1.2"),
+            ("spirv-unknown-vulkan-1.2", "spirv-unknown-vulkan1.2"),
+            ("spirv-unknown-spirv1.5", "spirv-unknown-spv1.5"),
+            (
+                "spirv-unknown-opencl2.0embeded",
+                "spirv-unknown-opencl2.0embedded",
+            ),
+            (
+                "spirv64-unknown-vulkan1.1spv14",
+                "spirv64-unknown-vulkan1.1spv1.4",
+            ),
+        ] {
+            let err = typo.parse::<SpirvTarget>().err().unwrap();
+            assert_eq!(err.suggestion, Some(expected), "{typo}");
+        }
+
+        let err = "x86_64-unknown-linux-gnu"
+            .parse::<SpirvTarget>()
+            .err()
+            .unwrap();
+        assert_eq!(err.suggestion, None);
     }
 }
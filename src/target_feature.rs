@@ -1,19 +1,39 @@
+use std::sync::LazyLock;
+
+use num_traits::FromPrimitive;
+use rspirv::{dr::Operand, spirv::Capability};
+use rustc_data_structures::fx::FxHashSet;
+use rustc_session::Session;
 use rustc_span::symbol::Symbol;
 
+use crate::target::SpirvTarget;
+
+const EXT_PREFIX: &str = "ext:";
+const PROFILE_PREFIX: &str = "profile:";
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TargetFeature {
     Extension(Symbol),
     Capability(rspirv::spirv::Capability),
+    /// A named bundle of capabilities and extensions, replaced with its
+    /// contents by `expand_target_features`.
+    Profile(Profile),
 }
 
 impl std::str::FromStr for TargetFeature {
     type Err = String;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        const EXT_PREFIX: &str = "ext:";
-
         if let Some(input) = input.strip_prefix(EXT_PREFIX) {
             Ok(Self::Extension(Symbol::intern(input)))
+        } else if let Some(input) = input.strip_prefix(PROFILE_PREFIX) {
+            Ok(Self::Profile(input.parse().map_err(|()| {
+                let valid = Profile::ALL.map(|profile| format!("`{profile}`"));
+                format!(
+                    "Invalid profile: `{input}`, expected one of: {}",
+                    valid.join(", ")
+                )
+            })?))
         } else {
             Ok(Self::Capability(input.parse().map_err(|_err| {
                 format!("Invalid Capability: `{input}`")
@@ -21,3 +41,459 @@ impl std::str::FromStr for TargetFeature {
         }
     }
 }
+
+impl std::fmt::Display for TargetFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Extension(ext) => write!(f, "{EXT_PREFIX}{ext}"),
+            Self::Capability(cap) => write!(f, "{cap:?}"),
+            Self::Profile(profile) => write!(f, "{PROFILE_PREFIX}{profile}"),
+        }
+    }
+}
+
+/// Curated feature bundles, usable as `-Ctarget-feature=+profile:NAME`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Profile {
+    /// The shader features required by the Vulkan Roadmap 2022 profile
+    /// (`VP_KHR_roadmap_2022`), on top of Vulkan 1.3.
+    VulkanRoadmap2022,
+    /// The features required of OpenCL 3.0 devices supporting the full profile
+    /// (i.e. 64-bit integers and images).
+    OpenCl3_0Full,
+    /// What can be used when targeting WebGPU (i.e. what WGSL can express).
+    WebGpu,
+}
+
+impl Profile {
+    pub const ALL: [Self; 3] = [Self::VulkanRoadmap2022, Self::OpenCl3_0Full, Self::WebGpu];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VulkanRoadmap2022 => "vulkan-roadmap-2022",
+            Self::OpenCl3_0Full => "opencl3.0-full",
+            Self::WebGpu => "webgpu",
+        }
+    }
+
+    pub fn capabilities(self) -> &'static [Capability] {
+        use Capability::*;
+
+        match self {
+            Self::VulkanRoadmap2022 => &[
+                Shader,
+                Matrix,
+                Int8,
+                Int16,
+                Int64,
+                Float16,
+                ImageQuery,
+                DerivativeControl,
+                DrawParameters,
+                SampledImageArrayDynamicIndexing,
+                StorageBufferArrayDynamicIndexing,
+                StorageImageArrayDynamicIndexing,
+                StorageImageReadWithoutFormat,
+                StorageImageWriteWithoutFormat,
+                StorageBuffer8BitAccess,
+                UniformAndStorageBuffer8BitAccess,
+                StorageBuffer16BitAccess,
+                UniformAndStorageBuffer16BitAccess,
+                RuntimeDescriptorArray,
+                ShaderNonUniform,
+                SampledImageArrayNonUniformIndexing,
+                StorageBufferArrayNonUniformIndexing,
+                StorageImageArrayNonUniformIndexing,
+                UniformTexelBufferArrayDynamicIndexing,
+                StorageTexelBufferArrayDynamicIndexing,
+                UniformTexelBufferArrayNonUniformIndexing,
+                StorageTexelBufferArrayNonUniformIndexing,
+                GroupNonUniform,
+                GroupNonUniformVote,
+                GroupNonUniformArithmetic,
+                GroupNonUniformBallot,
+                GroupNonUniformShuffle,
+                GroupNonUniformShuffleRelative,
+                GroupNonUniformQuad,
+                VulkanMemoryModel,
+                VulkanMemoryModelDeviceScope,
+                PhysicalStorageBufferAddresses,
+            ],
+            Self::OpenCl3_0Full => &[
+                Kernel,
+                Addresses,
+                Int8,
+                Int16,
+                Int64,
+                Float16Buffer,
+                Vector16,
+                ImageBasic,
+                LiteralSampler,
+                Sampled1D,
+                Image1D,
+                SampledBuffer,
+                ImageBuffer,
+            ],
+            Self::WebGpu => &[Shader, Matrix, ImageQuery, DerivativeControl],
+        }
+    }
+
+    /// Extensions which the profile requires, but which don't provide any
+    /// capabilities (those needed by `capabilities` are added as necessary).
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::VulkanRoadmap2022 => &["SPV_KHR_non_semantic_info"],
+            Self::OpenCl3_0Full | Self::WebGpu => &[],
+        }
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.as_str() == s)
+            .ok_or(())
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The SPIR-V version a capability was added to the core specification in,
+/// for capabilities that weren't part of SPIR-V 1.0.
+pub fn core_version(cap: Capability) -> Option<(u8, u8)> {
+    use Capability::*;
+
+    Some(match cap {
+        SubgroupDispatch | NamedBarrier | PipeStorage => (1, 1),
+
+        GroupNonUniform
+        | GroupNonUniformVote
+        | GroupNonUniformArithmetic
+        | GroupNonUniformBallot
+        | GroupNonUniformShuffle
+        | GroupNonUniformShuffleRelative
+        | GroupNonUniformClustered
+        | GroupNonUniformQuad
+        | DeviceGroup
+        | MultiView
+        | VariablePointersStorageBuffer
+        | VariablePointers
+        | DrawParameters
+        | StorageBuffer16BitAccess
+        | UniformAndStorageBuffer16BitAccess
+        | StoragePushConstant16
+        | StorageInputOutput16 => (1, 3),
+
+        DenormPreserve
+        | DenormFlushToZero
+        | SignedZeroInfNanPreserve
+        | RoundingModeRTE
+        | RoundingModeRTZ => (1, 4),
+
+        ShaderLayer
+        | ShaderViewportIndex
+        | ShaderNonUniform
+        | RuntimeDescriptorArray
+        | InputAttachmentArrayDynamicIndexing
+        | UniformTexelBufferArrayDynamicIndexing
+        | StorageTexelBufferArrayDynamicIndexing
+        | UniformBufferArrayNonUniformIndexing
+        | SampledImageArrayNonUniformIndexing
+        | StorageBufferArrayNonUniformIndexing
+        | StorageImageArrayNonUniformIndexing
+        | InputAttachmentArrayNonUniformIndexing
+        | UniformTexelBufferArrayNonUniformIndexing
+        | StorageTexelBufferArrayNonUniformIndexing
+        | VulkanMemoryModel
+        | VulkanMemoryModelDeviceScope
+        | PhysicalStorageBufferAddresses
+        | StorageBuffer8BitAccess
+        | UniformAndStorageBuffer8BitAccess
+        | StoragePushConstant8 => (1, 5),
+
+        _ => return None,
+    })
+}
+
+/// The extensions that can provide `cap` (any one of them is enough), when the
+/// target's SPIR-V version doesn't already include it.
+pub fn enabling_extensions(cap: Capability) -> Vec<&'static str> {
+    use Capability::*;
+
+    match cap {
+        // NOTE these were promoted to core in SPIR-V 1.5, and the grammar
+        // `rspirv` is generated from only lists the version for them.
+        ShaderNonUniform
+        | RuntimeDescriptorArray
+        | InputAttachmentArrayDynamicIndexing
+        | UniformTexelBufferArrayDynamicIndexing
+        | StorageTexelBufferArrayDynamicIndexing
+        | UniformBufferArrayNonUniformIndexing
+        | SampledImageArrayNonUniformIndexing
+        | StorageBufferArrayNonUniformIndexing
+        | StorageImageArrayNonUniformIndexing
+        | InputAttachmentArrayNonUniformIndexing
+        | UniformTexelBufferArrayNonUniformIndexing
+        | StorageTexelBufferArrayNonUniformIndexing => vec!["SPV_EXT_descriptor_indexing"],
+        VulkanMemoryModel | VulkanMemoryModelDeviceScope => vec!["SPV_KHR_vulkan_memory_model"],
+        _ => Operand::Capability(cap).required_extensions(),
+    }
+}
+
+/// Extensions which can be enabled with `ext:...`, but which don't provide
+/// any capabilities (so they can't be found through `enabling_extensions`).
+const EXTENSIONS_WITHOUT_CAPABILITIES: &[&str] = &[
+    "SPV_AMD_gcn_shader",
+    "SPV_AMD_gpu_shader_half_float",
+    "SPV_AMD_gpu_shader_int16",
+    "SPV_AMD_shader_explicit_vertex_parameter",
+    "SPV_AMD_shader_trinary_minmax",
+    "SPV_GOOGLE_decorate_string",
+    "SPV_GOOGLE_hlsl_functionality1",
+    "SPV_GOOGLE_user_type",
+    "SPV_KHR_expect_assume",
+    "SPV_KHR_linkonce_odr",
+    "SPV_KHR_no_integer_wrap_decoration",
+    "SPV_KHR_non_semantic_info",
+    "SPV_KHR_storage_buffer_storage_class",
+    "SPV_KHR_terminate_invocation",
+];
+
+/// All the extensions that provide some capability (see `enabling_extensions`).
+static CAPABILITY_EXTENSIONS: LazyLock<FxHashSet<&'static str>> = LazyLock::new(|| {
+    (0..=u32::from(u16::MAX))
+        .filter_map(Capability::from_u32)
+        .flat_map(enabling_extensions)
+        .collect()
+});
+
+pub fn is_capability_extension(ext: &str) -> bool {
+    CAPABILITY_EXTENSIONS.contains(ext)
+}
+
+/// The features enabled for this session, in a deterministic order, and with
+/// everything they imply (see `expand_target_features`).
+///
+/// Invalid features are ignored here, as `SpirCodegenBackend::init` reports them.
+pub fn session_target_features(sess: &Session, target: &SpirvTarget) -> Vec<TargetFeature> {
+    let mut feature_names = sess
+        .target_features
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>();
+
+    // target_features is a HashSet, not a Vec, so we need to sort to have deterministic
+    // compilation - otherwise, the order of capabilities in binaries depends on the iteration
+    // order of the hashset. Sort by the string, since that's easy.
+    feature_names.sort_unstable();
+
+    let features = feature_names
+        .into_iter()
+        .filter_map(|s| s.parse().ok())
+        .collect::<Vec<_>>();
+    expand_target_features(target, &features)
+}
+
+/// Adds everything the requested features imply: the contents of profiles,
+/// the capabilities each capability depends on, and an extension providing it,
+/// if it isn't part of the target's SPIR-V version (and no such extension was
+/// requested already). Profiles themselves are kept, for `cfg(target_feature)`.
+pub fn expand_target_features(
+    target: &SpirvTarget,
+    features: &[TargetFeature],
+) -> Vec<TargetFeature> {
+    let mut expanded = features.to_vec();
+    let mut i = 0;
+    while let Some(feature) = expanded.get(i) {
+        i += 1;
+        let cap = match *feature {
+            TargetFeature::Capability(cap) => cap,
+            TargetFeature::Extension(_) => continue,
+            TargetFeature::Profile(profile) => {
+                let contents = profile
+                    .capabilities()
+                    .iter()
+                    .map(|&cap| TargetFeature::Capability(cap))
+                    .chain(
+                        profile
+                            .extensions()
+                            .iter()
+                            .map(|ext| TargetFeature::Extension(Symbol::intern(ext))),
+                    );
+                for feature in contents {
+                    if !expanded.contains(&feature) {
+                        expanded.push(feature);
+                    }
+                }
+                continue;
+            }
+        };
+
+        for implied in Operand::Capability(cap).required_capabilities() {
+            let implied = TargetFeature::Capability(implied);
+            if !expanded.contains(&implied) {
+                expanded.push(implied);
+            }
+        }
+
+        if core_version(cap).is_some_and(|version| target.spirv_version() >= version) {
+            continue;
+        }
+        let exts = enabling_extensions(cap);
+        let has_ext = exts
+            .iter()
+            .any(|ext| expanded.contains(&TargetFeature::Extension(Symbol::intern(ext))));
+        if let (false, Some(ext)) = (has_ext, exts.first()) {
+            expanded.push(TargetFeature::Extension(Symbol::intern(ext)));
+        }
+    }
+    expanded
+}
+
+/// Checks the (already expanded, see `expand_target_features`) features against
+/// the target, returning `(warnings, errors)`.
+pub fn check_target_features(
+    target: &SpirvTarget,
+    features: &[TargetFeature],
+) -> (Vec<String>, Vec<String>) {
+    let mut warnings = vec![];
+    let mut errors = vec![];
+    let (major, minor) = target.spirv_version();
+    for feature in features {
+        match *feature {
+            TargetFeature::Extension(ext) => {
+                if !is_capability_extension(ext.as_str())
+                    && !EXTENSIONS_WITHOUT_CAPABILITIES.contains(&ext.as_str())
+                {
+                    warnings.push(format!("unknown SPIR-V extension `{ext}`"));
+                }
+            }
+            TargetFeature::Profile(_) => {}
+            TargetFeature::Capability(cap) => {
+                let Some(version) = core_version(cap) else {
+                    continue;
+                };
+                if target.spirv_version() < version && enabling_extensions(cap).is_empty() {
+                    errors.push(format!(
+                        "capability `{cap:?}` requires SPIR-V {}.{}, but target `{target}` \
+                         is SPIR-V {major}.{minor}",
+                        version.0, version.1,
+                    ));
+                }
+            }
+        }
+    }
+
+    let has_cap = |cap| features.contains(&TargetFeature::Capability(cap));
+    if target.is_kernel() && has_cap(Capability::Shader) {
+        errors.push(format!(
+            "`Shader` capability (required by the enabled capabilities) is not supported \
+             by kernel target `{target}`"
+        ));
+    }
+    if !target.is_kernel() && has_cap(Capability::Kernel) {
+        errors.push(format!(
+            "`Kernel` capability (required by the enabled capabilities) is not supported \
+             by shader target `{target}`"
+        ));
+    }
+
+    (warnings, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use rspirv::spirv::Capability;
+    use rustc_span::{create_default_session_globals_then, symbol::Symbol};
+
+    use super::{check_target_features, expand_target_features, Profile, TargetFeature};
+    use crate::target::SpirvTarget;
+
+    fn expand(target: &str, features: &[&str]) -> (Vec<TargetFeature>, Vec<String>) {
+        let target = target.parse::<SpirvTarget>().unwrap();
+        let features = features
+            .iter()
+            .map(|f| f.parse().unwrap())
+            .collect::<Vec<_>>();
+        let expanded = expand_target_features(&target, &features);
+        let (_, errors) = check_target_features(&target, &expanded);
+        (expanded, errors)
+    }
+
+    #[test]
+    fn implied_capabilities() {
+        create_default_session_globals_then(|| {
+            let (features, errors) = expand("spirv-unknown-vulkan1.1", &["Int64Atomics"]);
+            assert!(features.contains(&TargetFeature::Capability(Capability::Int64)));
+            assert!(errors.is_empty());
+        });
+    }
+
+    #[test]
+    fn implied_extensions() {
+        create_default_session_globals_then(|| {
+            let ext = TargetFeature::Extension(Symbol::intern("SPV_KHR_8bit_storage"));
+
+            let (features, _) = expand("spirv-unknown-vulkan1.1", &["StorageBuffer8BitAccess"]);
+            assert!(features.contains(&ext));
+
+            // Part of SPIR-V 1.5, no extension needed.
+            let (features, _) = expand("spirv-unknown-vulkan1.2", &["StorageBuffer8BitAccess"]);
+            assert!(!features.contains(&ext));
+        });
+    }
+
+    #[test]
+    fn profiles() {
+        create_default_session_globals_then(|| {
+            let (features, errors) =
+                expand("spirv-unknown-vulkan1.1", &["profile:vulkan-roadmap-2022"]);
+            assert!(features.contains(&TargetFeature::Capability(Capability::VulkanMemoryModel)));
+            assert!(features.contains(&TargetFeature::Extension(Symbol::intern(
+                "SPV_KHR_vulkan_memory_model"
+            ))));
+            assert!(errors.is_empty());
+
+            let features = [
+                "profile:opencl3.0-full",
+                "profile:webgpu",
+                "ext:SPV_KHR_foo",
+            ];
+            for feature in features {
+                let parsed = feature.parse::<TargetFeature>().unwrap();
+                assert_eq!(parsed.to_string(), feature);
+            }
+            assert!("profile:vulkan".parse::<TargetFeature>().is_err());
+
+            let (_, errors) = expand("spirv-unknown-vulkan1.2", &["profile:opencl3.0-full"]);
+            assert_eq!(errors.len(), 1);
+
+            // The WebGPU profile needs nothing `webgpu0` doesn't already allow.
+            let webgpu = "spirv-unknown-webgpu0".parse::<SpirvTarget>().unwrap();
+            for cap in Profile::WebGpu.capabilities() {
+                assert!(webgpu.inferable_capabilities().contains(cap), "{cap:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn inexpressible_capabilities() {
+        create_default_session_globals_then(|| {
+            let (_, errors) = expand("spirv-unknown-vulkan1.0", &["GroupNonUniform"]);
+            assert_eq!(errors.len(), 1);
+
+            let (_, errors) = expand("spirv-unknown-vulkan1.1", &["Vector16"]);
+            assert_eq!(errors.len(), 1);
+
+            let (_, errors) = expand("spirv-unknown-opencl1.2", &["Geometry"]);
+            assert_eq!(errors.len(), 1);
+        });
+    }
+}
@@ -0,0 +1,39 @@
+#![no_std]
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// `AtomicU32::fetch_nand` has no SPIR-V instruction, and gets lowered to a
+/// compare-exchange loop, which (just like the other `core::sync::atomic`
+/// methods called from here) uses the `Workgroup` scope of this function.
+#[cfg_attr(target_arch = "spirv", rust_gpu::spirv(atomic_scope(workgroup)))]
+fn workgroup_fetch_nand(counter: &AtomicU32, mask: u32) -> u32 {
+    counter.fetch_nand(mask, Ordering::AcqRel)
+}
+
+#[cfg_attr(target_arch = "spirv", rust_gpu::spirv(atomic_float_add_intrinsic))]
+unsafe fn atomic_f32_add(ptr: *mut f32, value: f32, _semantics: u32) -> f32 {
+    let old = *ptr;
+    *ptr += value;
+    old
+}
+
+#[cfg_attr(target_arch = "spirv", rust_gpu::spirv(compute(threads(64))))]
+pub fn main(
+    #[cfg_attr(target_arch = "spirv", rust_gpu::spirv(workgroup))] counter: &AtomicU32,
+    #[cfg_attr(
+        target_arch = "spirv",
+        rust_gpu::spirv(storage_buffer, descriptor_set = 0, binding = 0)
+    )]
+    sum: &mut f32,
+) {
+    let old = workgroup_fetch_nand(counter, 0xf0f0);
+    unsafe {
+        atomic_f32_add(sum, old as f32, 0);
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    loop {}
+}
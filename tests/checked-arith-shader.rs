@@ -0,0 +1,49 @@
+#![no_std]
+
+use core::panic::PanicInfo;
+
+/// The sum, or a fixed value if it overflowed.
+fn checked_adds(a: u32, b: u32, c: i32, d: i32) -> u32 {
+    let unsigned = a.checked_add(b).unwrap_or(u32::MAX);
+    let signed = c.checked_add(d).unwrap_or(i32::MIN);
+    unsigned ^ signed as u32
+}
+
+/// The wrapped difference, along with whether it overflowed.
+fn overflowing_subs(a: u32, b: u32, c: i32, d: i32) -> u32 {
+    let (unsigned, unsigned_overflow) = a.overflowing_sub(b);
+    let (signed, signed_overflow) = c.overflowing_sub(d);
+    if unsigned_overflow || signed_overflow {
+        0
+    } else {
+        unsigned.wrapping_add(signed as u32)
+    }
+}
+
+/// Multiplication overflow has to check the high half of the full product,
+/// and 64-bit operands need the `Int64` capability.
+fn overflowing_muls(a: u64, b: u64, c: i64, d: i64) -> u32 {
+    let (unsigned, unsigned_overflow) = a.overflowing_mul(b);
+    let (signed, signed_overflow) = c.overflowing_mul(d);
+    (unsigned as u32) ^ (signed as u32) ^ (unsigned_overflow as u32) ^ (signed_overflow as u32)
+}
+
+#[cfg_attr(target_arch = "spirv", rust_gpu::spirv(compute(threads(64))))]
+pub fn main(
+    #[cfg_attr(
+        target_arch = "spirv",
+        rust_gpu::spirv(storage_buffer, descriptor_set = 0, binding = 0)
+    )]
+    values: &mut [u32],
+) {
+    let (a, b) = (values[0], values[1]);
+    let (c, d) = (a as i32, b as i32);
+    values[2] = checked_adds(a, b, c, d);
+    values[3] = overflowing_subs(a, b, c, d);
+    values[4] = overflowing_muls(a as u64, b as u64, c as i64, d as i64);
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![feature(f16)]
+
+use core::panic::PanicInfo;
+use core::ptr;
+
+pub struct Pair {
+    pub a: u32,
+    pub b: f32,
+}
+
+/// Structs are filled field by field, with a constant pattern per field type.
+fn memset_struct(pair: &mut Pair) {
+    unsafe {
+        ptr::write_bytes(pair as *mut Pair, 0xab, 1);
+    }
+}
+
+/// Pointers can only be filled with zeroes (i.e. null), without addresses.
+fn memset_pointers() -> bool {
+    let mut ptrs = [&0u32 as *const u32; 2];
+    unsafe {
+        ptr::write_bytes(ptrs.as_mut_ptr(), 0, ptrs.len());
+    }
+    ptrs[0].is_null() && ptrs[1].is_null()
+}
+
+/// Runtime arrays have no value to store as a whole, and their length is only
+/// known at runtime, so they get filled element by element, in a loop.
+fn memset_runtime_array(values: &mut [u32]) {
+    unsafe {
+        ptr::write_bytes(values.as_mut_ptr(), 0xff, values.len());
+    }
+}
+
+fn memset_bools() -> bool {
+    let mut flags = [false; 4];
+    unsafe {
+        ptr::write_bytes(flags.as_mut_ptr(), 1, flags.len());
+    }
+    flags[0] && flags[3]
+}
+
+fn memset_f16() -> f32 {
+    let mut halves = [0.0f16; 2];
+    unsafe {
+        ptr::write_bytes(halves.as_mut_ptr(), 0x3c, halves.len());
+    }
+    halves[1] as f32
+}
+
+#[cfg_attr(target_arch = "spirv", rust_gpu::spirv(compute(threads(64))))]
+pub fn main(
+    #[cfg_attr(
+        target_arch = "spirv",
+        rust_gpu::spirv(storage_buffer, descriptor_set = 0, binding = 0)
+    )]
+    pair: &mut Pair,
+    #[cfg_attr(
+        target_arch = "spirv",
+        rust_gpu::spirv(storage_buffer, descriptor_set = 0, binding = 1)
+    )]
+    values: &mut [u32],
+) {
+    memset_struct(pair);
+    memset_runtime_array(values);
+    if memset_pointers() && memset_bools() {
+        pair.b += memset_f16();
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    loop {}
+}